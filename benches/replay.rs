@@ -0,0 +1,163 @@
+//! Benchmarks of the replay path.
+//!
+//! Save a baseline with `cargo bench -- --save-baseline master` and compare a change against it
+//! with `cargo bench -- --baseline master`. When comparing against a baseline, the run fails if
+//! the mean of any benchmark regressed by more than `REGRESSION_THRESHOLD`, so CI can enforce it.
+
+#[macro_use]
+extern crate criterion;
+extern crate reqwest_mock;
+#[macro_use]
+extern crate serde_json;
+
+use criterion::{black_box, BenchmarkId, Criterion};
+use reqwest_mock::data::FORMAT_VERSION;
+use reqwest_mock::{Bytes, Client, Method, RecordingTarget, ReplayClient, Request, Response,
+                   StatusCode, Url};
+use reqwest_mock::header::{Headers, UserAgent};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+/// Regressions of the mean larger than this fail a comparison against a baseline.
+const REGRESSION_THRESHOLD: f64 = 0.10;
+
+fn request(n: usize) -> Request {
+    let mut headers = Headers::new();
+    headers.set(UserAgent::new("reqwest_mock benchmarks"));
+
+    Request {
+        url: Url::parse(&format!("http://example.com/items/{}", n)).unwrap(),
+        method: Method::Get,
+        headers: headers,
+        body: None,
+    }
+}
+
+fn response(request: &Request, body_len: usize) -> Response {
+    Response {
+        url: request.url.clone(),
+        status: StatusCode::Ok,
+        headers: Headers::new(),
+        body: Bytes::from(vec![42u8; body_len]),
+        elapsed: None,
+        replay: None,
+        interim: Vec::new(),
+        trailers: Headers::new(),
+        reset_at: None,
+    }
+}
+
+/// Creates a fresh replay directory containing `n` recorded requests.
+fn replay_dir(n: usize) -> PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("reqwest_mock_bench_{}", n));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    for i in 0..n {
+        let req = request(i);
+        let data = json!({
+            "request": req,
+            "response": response(&req, 256),
+            "format_version": FORMAT_VERSION,
+        });
+        let f = File::create(dir.join(format!("{:x}.json", req.fingerprint()))).unwrap();
+        serde_json::to_writer(f, &data).unwrap();
+    }
+
+    dir
+}
+
+fn fingerprint(c: &mut Criterion) {
+    let mut req = request(0);
+    req.body = Some(Bytes::from(vec![7u8; 4096]));
+
+    c.bench_function("fingerprint", |b| b.iter(|| black_box(&req).fingerprint()));
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    for &n in &[1, 100, 10_000] {
+        let client = ReplayClient::new(RecordingTarget::dir(replay_dir(n)));
+        let req = request(n / 2);
+
+        group.bench_with_input(BenchmarkId::from_parameter(n), &req, |b, req| {
+            b.iter(|| client.execute(None, req.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn serialize_body(c: &mut Criterion) {
+    let resp = response(&request(0), 1 << 20);
+    let json = serde_json::to_string(&resp).unwrap();
+
+    c.bench_function("serialize 1MiB body", |b| {
+        b.iter(|| serde_json::to_string(black_box(&resp)).unwrap())
+    });
+    c.bench_function("deserialize 1MiB body", |b| {
+        b.iter(|| serde_json::from_str::<Response>(black_box(&json)).unwrap())
+    });
+}
+
+/// Returns the benchmarks whose mean regressed by more than `REGRESSION_THRESHOLD` according to
+/// the comparisons criterion wrote to `dir` since `start`.
+fn regressions(dir: &Path, start: SystemTime) -> Vec<(PathBuf, f64)> {
+    let mut found = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return found,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            found.extend(regressions(&path, start));
+            continue;
+        }
+        if !path.ends_with("change/estimates.json") {
+            continue;
+        }
+        // Comparisons of benchmarks which didn't run this time are stale.
+        let modified = entry.metadata().and_then(|m| m.modified());
+        if modified.map_or(true, |m| m < start) {
+            continue;
+        }
+
+        let estimates: serde_json::Value = match File::open(&path) {
+            Ok(f) => serde_json::from_reader(f).unwrap(),
+            Err(_) => continue,
+        };
+        let change = estimates["mean"]["point_estimate"].as_f64().unwrap_or(0.0);
+        if change > REGRESSION_THRESHOLD {
+            found.push((path, change));
+        }
+    }
+    found
+}
+
+criterion_group!{
+    name = benches;
+    // Changes smaller than this are considered noise when comparing against a baseline.
+    config = Criterion::default().noise_threshold(0.05);
+    targets = fingerprint, lookup, serialize_body
+}
+
+fn main() {
+    let start = SystemTime::now();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+
+    if !::std::env::args().any(|arg| arg == "--baseline") {
+        return;
+    }
+    let target = ::std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".to_string());
+    let regressions = regressions(&Path::new(&target).join("criterion"), start);
+    for &(ref path, change) in &regressions {
+        eprintln!("Regressed by {:.1}%: {}", change * 100.0, path.display());
+    }
+    if !regressions.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,37 @@
+//! Writes the body of a recorded response to a file:
+//!
+//! ```text
+//! cargo run --example extract -- <replay file or dir> [fingerprint] --out body.pdf
+//! ```
+
+extern crate reqwest_mock;
+
+use std::env;
+use std::process;
+
+fn main() {
+    let mut positional = Vec::new();
+    let mut out = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--out" {
+            out = args.next();
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let (replay, fingerprint, out) = match (positional.get(0), out) {
+        (Some(replay), Some(out)) => (replay, positional.get(1), out),
+        _ => {
+            eprintln!("usage: extract <replay file or dir> [fingerprint] --out <file>");
+            process::exit(2);
+        }
+    };
+
+    let fingerprint = fingerprint.map(|f| f.as_str());
+    if let Err(e) = reqwest_mock::data::extract_body(replay, fingerprint, 0, &out) {
+        eprintln!("extracting the body failed: {}", e);
+        process::exit(1);
+    }
+}
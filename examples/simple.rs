@@ -19,7 +19,7 @@ fn main() {
     // times.
     assert_ne!(r1, r2);
 
-    let c2 = ReplayClient::new(RecordingTarget::file("simple.replay"));
+    let c2 = ReplayClient::recording(RecordingTarget::file("simple.replay"));
     let r1 = perform_request(&c2, URL);
     let r2 = perform_request(&c2, URL);
     assert_eq!(r1, r2);
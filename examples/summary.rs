@@ -0,0 +1,35 @@
+//! Prints a markdown table of the requests recorded in a replay file or directory:
+//!
+//! ```text
+//! cargo run --example summary -- <replay file or dir>
+//! ```
+
+extern crate reqwest_mock;
+
+use reqwest_mock::{RecordingTarget, ReplayClient};
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let replay = match env::args().nth(1) {
+        Some(replay) => replay,
+        None => {
+            eprintln!("usage: summary <replay file or dir>");
+            process::exit(2);
+        }
+    };
+
+    let target = if Path::new(&replay).is_dir() {
+        RecordingTarget::dir(replay)
+    } else {
+        RecordingTarget::file(replay)
+    };
+    match ReplayClient::new(target).snapshot() {
+        Ok(cassette) => print!("{}", cassette.summary()),
+        Err(e) => {
+            eprintln!("reading the replay data failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -1,16 +1,60 @@
+use bytes::Bytes;
+
 // TODO: Implement more conversions.
 pub trait IntoBody {
-    fn into_body(self) -> Vec<u8>;
+    fn into_body(self) -> Bytes;
 }
 
-impl IntoBody for Vec<u8> {
-    fn into_body(self) -> Vec<u8> {
+impl IntoBody for Bytes {
+    fn into_body(self) -> Bytes {
         self
     }
 }
 
+impl IntoBody for Vec<u8> {
+    fn into_body(self) -> Bytes {
+        Bytes::from(self)
+    }
+}
+
+impl IntoBody for String {
+    fn into_body(self) -> Bytes {
+        Bytes::from(self)
+    }
+}
+
 impl<'a> IntoBody for &'a str {
-    fn into_body(self) -> Vec<u8> {
-        self.bytes().collect()
+    fn into_body(self) -> Bytes {
+        Bytes::from(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn into_body() {
+        let body = Bytes::from("body");
+        assert_eq!(body.clone().into_body(), body);
+        assert_eq!(b"body".to_vec().into_body(), body);
+        assert_eq!("body".to_string().into_body(), body);
+        assert_eq!("body".into_body(), body);
+    }
+
+    /// Building a request shares the body instead of copying it.
+    #[cfg(feature = "client")]
+    #[test]
+    fn request_body_shared() {
+        use client::{Client, StubClient, StubSettings};
+
+        let client = StubClient::new(StubSettings::default());
+        let body = Bytes::from(vec![42u8; 1024]);
+        let request = client
+            .post("http://example.com/")
+            .body(body.clone())
+            .build()
+            .unwrap();
+        assert_eq!(request.body.unwrap().as_ptr(), body.as_ptr());
     }
 }
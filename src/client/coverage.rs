@@ -0,0 +1,86 @@
+use error::Error;
+use std::fmt;
+
+/// Reports which recorded requests or stubs were used by a test, see `ReplayClient::coverage`
+/// and `StubClient::coverage`.
+///
+/// Entries are described as `METHOD URL`, using `*` for stubs matching any method.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Coverage {
+    /// The used entries together with how many times they were used.
+    pub used: Vec<(String, usize)>,
+
+    /// The entries which were never used, e.g. dead fixtures or untested endpoints.
+    pub unused: Vec<String>,
+}
+
+impl Coverage {
+    pub(crate) fn new(mut entries: Vec<(String, usize)>) -> Self {
+        entries.sort();
+        let (used, unused): (Vec<_>, Vec<_>) = entries.into_iter().partition(|&(_, n)| n > 0);
+        Coverage {
+            used: used,
+            unused: unused.into_iter().map(|(entry, _)| entry).collect(),
+        }
+    }
+
+    /// Returns the percentage of used entries, 100 if there are none at all.
+    pub fn percent(&self) -> f64 {
+        let total = self.used.len() + self.unused.len();
+        if total == 0 {
+            100.0
+        } else {
+            self.used.len() as f64 * 100.0 / total as f64
+        }
+    }
+
+    /// Returns an error listing the unused entries if less than `min_percent` of the entries
+    /// were used.
+    pub fn require(&self, min_percent: f64) -> Result<(), Error> {
+        if self.percent() >= min_percent {
+            Ok(())
+        } else {
+            Err(
+                format!(
+                    "Only {:.1}% of the recorded requests or stubs were used, expected at least \
+                     {:.1}%.\n{}",
+                    self.percent(),
+                    min_percent,
+                    self
+                ).into(),
+            )
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(ref entry, n) in &self.used {
+            writeln!(f, "used {}x: {}", n, entry)?;
+        }
+        for entry in &self.unused {
+            writeln!(f, "unused: {}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent() {
+        let coverage = Coverage::new(vec![
+            ("GET http://example.com/b".to_string(), 2),
+            ("GET http://example.com/a".to_string(), 0),
+            ("* http://example.com/c".to_string(), 1),
+        ]);
+        assert_eq!(coverage.unused, vec!["GET http://example.com/a".to_string()]);
+        assert_eq!(coverage.used.len(), 2);
+        assert!(coverage.require(60.0).is_ok());
+        assert!(coverage.require(70.0).is_err());
+
+        assert_eq!(Coverage::new(Vec::new()).percent(), 100.0);
+    }
+}
@@ -1,8 +1,13 @@
+use bytes::Bytes;
 use client::{Client, Response};
 use config::ClientConfig;
-use error::Error;
+use error::{Error, ErrorKind};
+use metrics::ResponseSource;
 use request::Request;
-use std::io::Read;
+use reqwest::Url;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::time::Instant;
 
 /// Just a regular client performing no mocking at all.
 ///
@@ -10,16 +15,124 @@ use std::io::Read;
 /// while another client is to be used in testing code.
 pub struct DirectClient {
     config: ClientConfig,
+    client: Option<::reqwest::Client>,
+
+    /// The most recently built `reqwest::Client` together with the config it was built from, so
+    /// it only has to be rebuilt if the config changes.
+    cached: Mutex<Option<(ClientConfig, ::reqwest::Client)>>,
 }
 
 impl DirectClient {
     pub fn new() -> Self {
-        DirectClient { config: ClientConfig::default() }
+        DirectClient {
+            config: ClientConfig::default(),
+            client: None,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Create a `DirectClient` performing all requests through the provided `reqwest::Client`.
+    ///
+    /// This allows you to use custom TLS settings, proxies or connection pools. Note that the
+    /// `gzip`, `redirect`, `referer` and `timeout` settings of `ClientConfig` are part of the
+    /// `reqwest::Client` and will have no effect for a client created this way.
+    pub fn with_client(client: ::reqwest::Client) -> Self {
+        DirectClient {
+            config: ClientConfig::default(),
+            client: Some(client),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Returns the `reqwest::Client` to be used for a request with the provided config.
+    fn reqwest_client(&self, config: &ClientConfig) -> Result<::reqwest::Client, Error> {
+        if let Some(ref client) = self.client {
+            return Ok(client.clone());
+        }
+
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((ref cached_config, ref client)) = *cached {
+            if cached_config == config {
+                return Ok(client.clone());
+            }
+        }
+
+        let mut client_builder = ::reqwest::Client::builder()?;
+        client_builder.gzip(config.gzip);
+        client_builder.redirect(config.redirect.clone().into());
+        client_builder.referer(config.referer);
+        if let Some(timeout) = config.timeout.clone() {
+            client_builder.timeout(timeout);
+        }
+        let client = client_builder.build()?;
+
+        debug!("Built new reqwest::Client for config: {:?}", config);
+        *cached = Some((config.clone(), client.clone()));
+        Ok(client)
     }
 }
 
 impl Client for DirectClient {
     fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        let config = config.unwrap_or_else(|| &self.config);
+        ::metrics::instrument(config, request, |request| {
+            Ok((self.send_live(config, request)?, ResponseSource::Live))
+        })
+    }
+
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+        body: Box<dyn Read + Send>,
+    ) -> Result<Response, Error> {
+        let config = config.unwrap_or_else(|| &self.config);
+        ::metrics::instrument(config, request, |request| {
+            let body = ::reqwest::Body::new(body);
+            Ok((self.send(config, request, Some(body), None)?, ResponseSource::Live))
+        })
+    }
+}
+
+impl DirectClient {
+    /// Performs the request without reporting it to the `MetricsSink`, for clients which report
+    /// it themselves.
+    pub(crate) fn send_live(
+        &self,
+        config: &ClientConfig,
+        request: Request,
+    ) -> Result<Response, Error> {
+        self.send(config, request, None, None)
+    }
+
+    /// Like `send_live`, but fails with an `ErrorKind::BodyTooLarge` error as soon as more than
+    /// `limit` bytes of the response body were read, without reading the rest of it.
+    pub(crate) fn send_live_limited(
+        &self,
+        config: &ClientConfig,
+        request: Request,
+        limit: usize,
+    ) -> Result<Response, Error> {
+        self.send(config, request, None, Some(limit))
+    }
+
+    /// Performs the request, sending the streamed body instead of the one of the request if
+    /// provided and reading at most `limit` bytes of the response body.
+    fn send(
+        &self,
+        config: &ClientConfig,
+        request: Request,
+        stream: Option<::reqwest::Body>,
+        limit: Option<usize>,
+    ) -> Result<Response, Error> {
         // Some information potentially useful for debugging.
         debug!(
             "ReplayClient performing {} request of URL: {}",
@@ -29,46 +142,179 @@ impl Client for DirectClient {
         trace!("request headers: {}", request.headers);
         trace!("request body: {:?}", request.body);
 
-        // Use internal config if none was provided together with the request.
-        let config = config.unwrap_or_else(|| &self.config);
+        let start = config.clock.now();
 
         // Setup the client instance.
-        let mut client_builder = ::reqwest::Client::builder()?;
-        client_builder.gzip(config.gzip);
-        client_builder.redirect(config.redirect.clone().into());
-        client_builder.referer(config.referer);
-        if let Some(timeout) = config.timeout.clone() {
-            client_builder.timeout(timeout);
-        }
-        let client = client_builder.build()?;
+        let client = self.reqwest_client(config)?;
 
         // Build the request.
         let mut builder = client.request(request.method, request.url)?;
-        if let Some(body) = request.body {
-            builder.body(body);
+        builder.headers(request.headers);
+        if let Some(stream) = stream {
+            builder.body(stream);
+        } else if let Some(body) = request.body {
+            // reqwest can't take `Bytes` directly, so this is the one place the body is copied.
+            builder.body(body.to_vec());
         }
 
         // Send the request.
         let mut response = builder.send()?;
+        let elapsed = config.clock.now().duration_since(start);
+        if config.connect_timeout.map_or(false, |t| elapsed > t) ||
+            config.total_timeout.map_or(false, |t| elapsed > t)
+        {
+            return Err(ErrorKind::TimedOut(elapsed).into());
+        }
+
+        // Read the body, stopping once it exceeds the limit or a timeout.
+        let url = response.url().clone();
+        let buf = read_body(&mut response, config, start, limit, &url)?;
 
         // Extract the response.
-        Ok(Response {
-            url: response.url().clone(),
+        let mut response = Response {
+            url: url,
             status: response.status().clone(),
             headers: response.headers().clone(),
-            body: {
-                let mut buf = Vec::<u8>::new();
-                response.read_to_end(&mut buf)?;
-                buf
-            },
-        })
+            body: Bytes::from(buf),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: ::reqwest::header::Headers::new(),
+            reset_at: None,
+        };
+        ::compression::decompress_response(config, &mut response)?;
+        response.elapsed = Some(config.clock.now().duration_since(start));
+
+        Ok(response)
     }
+}
 
-    fn config(&self) -> &ClientConfig {
-        &self.config
+/// Reads a response body in chunks, failing with an `ErrorKind::TimedOut` error as soon as a
+/// chunk took longer than the `read_timeout` or the request exceeded the `total_timeout`, and
+/// with an `ErrorKind::BodyTooLarge` error as soon as more than `limit` bytes were read.
+fn read_body<R: Read>(
+    reader: &mut R,
+    config: &ClientConfig,
+    start: Instant,
+    limit: Option<usize>,
+    url: &Url,
+) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::<u8>::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        let chunk_start = config.clock.now();
+        let n = match reader.read(&mut chunk) {
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let now = config.clock.now();
+        if config.read_timeout.map_or(
+            false,
+            |t| now.duration_since(chunk_start) > t,
+        ) ||
+            config.total_timeout.map_or(
+                false,
+                |t| now.duration_since(start) > t,
+            )
+        {
+            return Err(ErrorKind::TimedOut(now.duration_since(start)).into());
+        }
+
+        if n == 0 {
+            return Ok(buf);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(limit) = limit {
+            if buf.len() > limit {
+                return Err(ErrorKind::BodyTooLarge(url.to_string(), limit).into());
+            }
+        }
     }
+}
 
-    fn config_mut(&mut self) -> &mut ClientConfig {
-        &mut self.config
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::{Clock, MockClock};
+    use std::time::Duration;
+
+    /// A body whose chunks each take two seconds to arrive.
+    struct SlowBody {
+        clock: MockClock,
+        chunks: Vec<&'static [u8]>,
+    }
+
+    impl Read for SlowBody {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.clock.advance(Duration::from_secs(2));
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.chunks.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    fn read(config: &ClientConfig, clock: &MockClock) -> (Result<Vec<u8>, Error>, usize) {
+        let mut body = SlowBody {
+            clock: clock.clone(),
+            chunks: b"abcd".chunks(1).collect(),
+        };
+        let url = Url::parse("http://example.com/").unwrap();
+        let result = read_body(&mut body, config, config.clock.now(), None, &url);
+        (result, body.chunks.len())
+    }
+
+    #[test]
+    fn read_body_timeouts() {
+        let clock = MockClock::new();
+        let mut config = ClientConfig::default();
+        config.clock = Clock::Mock(clock.clone());
+
+        let (result, _) = read(&config, &clock);
+        assert_eq!(result.unwrap(), b"abcd");
+
+        // The body is cut off once the deadline passed, without reading the rest of it.
+        config.total_timeout = Some(Duration::from_secs(5));
+        let (result, unread) = read(&config, &clock);
+        match *result.unwrap_err().kind() {
+            ErrorKind::TimedOut(elapsed) => assert_eq!(elapsed, Duration::from_secs(6)),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        assert_eq!(unread, 1);
+
+        config.total_timeout = None;
+        config.read_timeout = Some(Duration::from_secs(1));
+        let (result, unread) = read(&config, &clock);
+        match *result.unwrap_err().kind() {
+            ErrorKind::TimedOut(elapsed) => assert_eq!(elapsed, Duration::from_secs(2)),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        assert_eq!(unread, 3);
+    }
+
+    /// The `reqwest::Client` is only rebuilt when the config changes.
+    #[test]
+    fn cached_client() {
+        let cached_config = |client: &DirectClient| {
+            client.cached.lock().unwrap().as_ref().map(|c| c.0.clone())
+        };
+
+        let client = DirectClient::new();
+        let mut config = ClientConfig::default();
+        client.reqwest_client(&config).unwrap();
+        assert_eq!(cached_config(&client), Some(config.clone()));
+
+        config.gzip = false;
+        client.reqwest_client(&config).unwrap();
+        assert_eq!(cached_config(&client), Some(config.clone()));
+
+        // A provided client is used regardless of the config.
+        let client = DirectClient::with_client(::reqwest::Client::new().unwrap());
+        client.reqwest_client(&config).unwrap();
+        assert_eq!(cached_config(&client), None);
     }
 }
@@ -3,6 +3,7 @@ use client::{DirectClient, RecordingTarget, ReplayClient, StubClient};
 use config::ClientConfig;
 use error::Error;
 use request::Request;
+use std::io::Read;
 use std::path::PathBuf;
 
 enum InnerClient {
@@ -27,14 +28,14 @@ impl GenericClient {
     /// request to one single replay file. If a differing request is made, the file will be
     /// overwritten again.
     pub fn replay_file<P: Into<PathBuf>>(replay_file: P) -> Self {
-        ReplayClient::new(RecordingTarget::File(replay_file.into())).into()
+        ReplayClient::recording(RecordingTarget::File(replay_file.into())).into()
     }
 
     /// Create a `GenericClient` using `ReplayClient` internally, recording multiple requests
     /// to a single directory. Each unique request will get its own replay file independent of
     /// other requests in the specified directory.
     pub fn replay_dir<P: Into<PathBuf>>(replay_dir: P) -> Self {
-        ReplayClient::new(RecordingTarget::Dir(replay_dir.into())).into()
+        ReplayClient::recording(RecordingTarget::Dir(replay_dir.into())).into()
     }
 
     /// Create a `GenericClient` using `StubClient` internally.
@@ -52,6 +53,16 @@ impl GenericClient {
         }
     }
 
+    /// If this is a ReplayClient it will attach the annotation to the next recorded request, see
+    /// [ReplayClient::annotate](struct.ReplayClient.html#method.annotate).
+    pub fn annotate<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        match self.inner {
+            InnerClient::Direct(_) |
+            InnerClient::Stub(_) => {}
+            InnerClient::Replay(ref replay) => replay.annotate(key, value),
+        }
+    }
+
     /*
     /// Convert the current instance to a `ReplayClient` replaying the file at the provided path.
     ///
@@ -105,4 +116,25 @@ impl Client for GenericClient {
             InnerClient::Stub(ref mut client) => client.config_mut(),
         }
     }
+
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+        body: Box<dyn Read + Send>,
+    ) -> Result<Response, Error> {
+        match self.inner {
+            InnerClient::Direct(ref client) => client.execute_streaming(config, request, body),
+            InnerClient::Replay(ref client) => client.execute_streaming(config, request, body),
+            InnerClient::Stub(ref client) => client.execute_streaming(config, request, body),
+        }
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        match self.inner {
+            InnerClient::Direct(ref client) => client.requests(),
+            InnerClient::Replay(ref client) => client.requests(),
+            InnerClient::Stub(ref client) => client.requests(),
+        }
+    }
 }
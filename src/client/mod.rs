@@ -1,11 +1,13 @@
 //! Defines the main types to be used to mock the HTTP client.
 
+use bytes::Bytes;
 use config::ClientConfig;
 use error::Error;
 use request::Request;
 use request_builder::RequestBuilder;
 use reqwest::{Method, IntoUrl};
 use response::Response;
+use std::io::Read;
 
 /// Provides a unified interface over the different Clients.
 ///
@@ -24,8 +26,61 @@ pub trait Client: Sized {
     /// Returns a mutable reference to the internal config.
     fn config_mut(&mut self) -> &mut ClientConfig;
 
+    /// Execute a request whose body is streamed from a reader, any body of the request itself is
+    /// replaced.
+    ///
+    /// The `DirectClient` passes the reader on to reqwest without buffering it, the other
+    /// clients need the whole body to match and record the request, so by default it is read
+    /// into memory and the request is passed to `execute`.
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        mut request: Request,
+        mut body: Box<dyn Read + Send>,
+    ) -> Result<Response, Error> {
+        let mut buf = Vec::new();
+        body.read_to_end(&mut buf)?;
+        request.body = Some(Bytes::from(buf));
+        self.execute(config, request)
+    }
+
+    /// Returns the requests executed so far, in the order they were executed.
+    ///
+    /// Only the testing clients keep track of their requests, the `DirectClient` always returns
+    /// an empty list so production code doesn't accumulate them.
+    fn requests(&self) -> Vec<Request> {
+        Vec::new()
+    }
+
     ////////////////////////////////////////////////////////
 
+    /// Returns the number of requests executed so far, see `requests`.
+    fn request_count(&self) -> usize {
+        self.requests().len()
+    }
+
+    /// Returns the request executed last, see `requests`.
+    fn last_request(&self) -> Option<Request> {
+        self.requests().pop()
+    }
+
+    /// Returns the requests executed so far whose URL matches the pattern, see `requests`.
+    ///
+    /// A `*` in the pattern matches any sequence of characters, otherwise the pattern has to
+    /// match the whole URL, e.g. `https://example.com/users/*`.
+    fn requests_to(&self, url_pattern: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|r| url_matches(url_pattern, r.url.as_str()))
+            .collect()
+    }
+
+    /// Set the `User-Agent` header sent with every request which doesn't set one itself, see
+    /// `ClientConfig::user_agent`.
+    fn user_agent<S: Into<String>>(&mut self, user_agent: S) {
+        self.config_mut().user_agent = Some(user_agent.into());
+    }
+
     /// Convenience method to make a `GET` request to a URL.
     fn get<'cl, U: IntoUrl>(&'cl self, url: U) -> RequestBuilder<'cl, Self> {
         self.request(Method::Get, url)
@@ -63,15 +118,230 @@ pub trait Client: Sized {
     }
 }
 
+/// Matches an URL against a pattern in which `*` matches any sequence of characters.
+fn url_matches(pattern: &str, url: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !url.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &url[first.len()..];
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(i) => rest = &rest[i + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+mod coverage;
+pub use self::coverage::Coverage;
+
 mod direct;
 pub use self::direct::DirectClient;
 
 mod replay;
-pub use self::replay::{RecordingTarget, ReplayClient};
+pub use self::replay::{BodyFormat, BodyLimit, Cassette, ContentLengthPolicy, DateRewrite,
+                       DeterminismReport, Difference, DifferenceKind, HeaderPolicy, IdScrubbing,
+                       Interaction, InteractionGroup, RecordingSession, RecordingTarget,
+                       ReplayClient, ReplayConfig, ReplaySettings, RequestBodyPolicy,
+                       RerecordMode};
 
 mod stub;
-pub use self::stub::{StubClient, StubDefault, StubSettings, StubStrictness, RequestStubber,
-                     ResponseStubber};
+pub use self::stub::{Pagination, SessionAffinity, StubClient, StubDefault, StubSettings,
+                     StubStrictness, RequestStubber, ResponseStubber};
 
 mod generic;
 pub use self::generic::GenericClient;
+
+mod rules;
+pub use self::rules::RuleClient;
+
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "tower")]
+pub use self::service::ClientService;
+
+#[cfg(feature = "json-schema")]
+mod validating;
+#[cfg(feature = "json-schema")]
+pub use self::validating::ValidatingClient;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Url;
+
+    #[test]
+    fn requests_to() {
+        assert!(url_matches("http://example.com/", "http://example.com/"));
+        assert!(!url_matches("http://example.com/", "http://example.com/a"));
+        assert!(url_matches("http://example.com/*", "http://example.com/a"));
+        assert!(url_matches("*/users/*/files", "http://example.com/users/1/files"));
+        assert!(!url_matches("*/users/*/files", "http://example.com/users/1"));
+
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::Url,
+        });
+        let url = Url::parse("http://example.com/users/1").unwrap();
+        client.stub(url.clone()).response().body("user").mock();
+        client.get(url).send().unwrap();
+        assert!(client.get("http://example.com/other").send().is_err());
+
+        assert_eq!(client.request_count(), 2);
+        assert_eq!(
+            client.last_request().unwrap().url.as_str(),
+            "http://example.com/other"
+        );
+        assert_eq!(client.requests_to("http://example.com/users/*").len(), 1);
+    }
+
+    #[test]
+    fn last_request_redirected() {
+        use reqwest::StatusCode;
+
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::Url,
+        });
+        let old = Url::parse("http://example.com/old").unwrap();
+        let new = Url::parse("http://example.com/new").unwrap();
+        client
+            .stub(old.clone())
+            .response()
+            .redirects_to("/new", StatusCode::MovedPermanently)
+            .mock();
+        client.stub(new.clone()).response().body("moved").mock();
+
+        let response = client.post(old.clone()).body("data").send().unwrap();
+        assert_eq!(response.url, new);
+
+        // The request as sent, not the one following the redirect.
+        assert_eq!(client.request_count(), 1);
+        let last = client.last_request().unwrap();
+        assert_eq!(last.url, old);
+        assert_eq!(last.method, Method::Post);
+        assert_eq!(last.body, Some(Bytes::from("data")));
+        assert!(client.requests_to("*/new").is_empty());
+    }
+
+    #[test]
+    fn body_reader() {
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::BodyMethodUrl,
+        });
+        let url = Url::parse("http://example.com/upload").unwrap();
+        client
+            .stub(url.clone())
+            .method(Method::Post)
+            .body("streamed")
+            .response()
+            .body("ok")
+            .mock();
+
+        let response = client
+            .post(url)
+            .body_reader(::std::io::Cursor::new(b"streamed".to_vec()))
+            .send()
+            .unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "ok");
+    }
+
+    #[test]
+    fn compress() {
+        use compression::Compression;
+        use header_ext::HeadersExt;
+
+        let client = StubClient::new(StubSettings::default());
+        let url = Url::parse("http://example.com/upload").unwrap();
+        for streamed in &[false, true] {
+            let builder = client.post(url.clone()).compress(Compression::Gzip);
+            let builder = if *streamed {
+                builder.body_reader(::std::io::Cursor::new(b"compressed".to_vec()))
+            } else {
+                builder.body("compressed")
+            };
+            let request = builder.build().unwrap();
+
+            assert_eq!(request.headers.get_str("Content-Encoding"), Some("gzip"));
+            let body = Compression::Gzip.decompress(request.body.as_ref().unwrap());
+            assert_eq!(&body.unwrap()[..], b"compressed");
+        }
+    }
+
+    /// Requests are retried according to the config they were sent with, waiting on its clock.
+    #[test]
+    fn retry_after() {
+        use clock::{Clock, MockClock};
+        use config::RetryAfterPolicy;
+        use reqwest::StatusCode;
+        use reqwest::header::Headers;
+        use std::time::Duration;
+
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::Url,
+        });
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut headers = Headers::new();
+        headers.set_raw("Retry-After", "3");
+        for attempt in 1..3 {
+            client
+                .stub(url.clone())
+                .attempt(attempt)
+                .response()
+                .status_code(StatusCode::TooManyRequests)
+                .headers(headers.clone())
+                .mock();
+        }
+        client.stub(url.clone()).response().body("ok").mock();
+
+        // The config of the client doesn't retry.
+        let response = client.get(url.clone()).send().unwrap();
+        assert_eq!(response.status, StatusCode::TooManyRequests);
+
+        let clock = MockClock::new();
+        let mut config = client.config().clone();
+        config.clock = Clock::Mock(clock.clone());
+        config.retry_after = Some(RetryAfterPolicy::default());
+        let response = client.get(url).config(config).send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "ok");
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+        assert_eq!(client.request_count(), 3);
+    }
+
+    #[test]
+    fn deadline() {
+        use clock::{Clock, MockClock};
+        use error::ErrorKind;
+        use std::time::Duration;
+
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::Url,
+        });
+        let clock = MockClock::new();
+        client.config_mut().clock = Clock::Mock(clock.clone());
+        let url = Url::parse("http://example.com/").unwrap();
+        client.stub(url.clone()).response().body("ok").mock();
+
+        let deadline = client.config().clock.now() + Duration::from_secs(5);
+        assert!(client.get(url.clone()).deadline(deadline).send().is_ok());
+
+        clock.advance(Duration::from_secs(6));
+        match *client.get(url).deadline(deadline).send().unwrap_err().kind() {
+            ErrorKind::DeadlineExceeded(_) => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+    }
+}
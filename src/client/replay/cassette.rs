@@ -0,0 +1,256 @@
+use client::replay::{ReplayClient, ReplayData};
+use error::Error;
+use request::Request;
+use response::Response;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::{File, create_dir_all};
+use std::path::Path;
+use std::slice;
+
+/// A single recorded request along with its responses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub request: Request,
+    pub response: Response,
+
+    /// Further responses recorded for the request, see `RerecordMode::Sequence`.
+    pub sequence: Vec<Response>,
+
+    /// User provided annotations, see `ReplayClient::annotate`.
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// An immutable snapshot of the requests recorded by a `ReplayClient`, see
+/// `ReplayClient::snapshot`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Returns an iterator over the recorded interactions.
+    pub fn iter(&self) -> slice::Iter<Interaction> {
+        self.interactions.iter()
+    }
+
+    /// Returns the number of recorded interactions.
+    pub fn len(&self) -> usize {
+        self.interactions.len()
+    }
+
+    /// Returns true if nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.interactions.is_empty()
+    }
+
+    /// Returns a new cassette only containing the interactions for which `f` returns true.
+    pub fn filter<F: FnMut(&Interaction) -> bool>(&self, mut f: F) -> Cassette {
+        Cassette { interactions: self.iter().filter(|i| f(i)).cloned().collect() }
+    }
+
+    /// Serializes the interactions as a JSON array of replay files, which can be embedded and
+    /// replayed with `ReplayClient::from_static`.
+    pub fn to_json(&self) -> Result<String, Error> {
+        let data: Vec<ReplayData> = self.iter().map(to_replay_data).collect();
+        Ok(::serde_json::to_string_pretty(&data)?)
+    }
+
+    /// Returns a markdown table listing the method, url, status, body size and annotations of
+    /// every interaction, so changes to replay files can be reviewed without reading the JSON.
+    ///
+    /// Interactions with a sequence of responses list the status of every response.
+    pub fn summary(&self) -> String {
+        let mut summary = String::from(
+            "| Method | URL | Status | Body size | Tags |\n|---|---|---|---|---|\n",
+        );
+        for interaction in self.iter() {
+            let responses = Some(&interaction.response).into_iter().chain(
+                interaction.sequence.iter(),
+            );
+            let status: Vec<String> = responses
+                .map(|r| u16::from(r.status).to_string())
+                .collect();
+            let tags: Vec<String> = interaction
+                .annotations
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+
+            let _ = writeln!(
+                summary,
+                "| {} | {} | {} | {} | {} |",
+                interaction.request.method,
+                interaction.request.url,
+                status.join(", "),
+                interaction.response.body.len(),
+                tags.join(", ").replace('|', "\\|")
+            );
+        }
+        summary
+    }
+
+    /// Writes every interaction to its own replay file in the directory, using the layout of
+    /// `RecordingTarget::Dir`.
+    pub fn write_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        create_dir_all(dir)?;
+
+        for interaction in self.iter() {
+            let file = dir.join(format!("{:x}.json", interaction.request.fingerprint()));
+            let f = File::create(&file)?;
+            ::serde_json::to_writer(f, &to_replay_data(interaction))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Cassette {
+    type Item = &'a Interaction;
+    type IntoIter = slice::Iter<'a, Interaction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn to_replay_data(interaction: &Interaction) -> ReplayData {
+    let mut data = ReplayData::new(
+        interaction.request.clone(),
+        interaction.response.clone(),
+        interaction.annotations.clone(),
+    );
+    data.sequence = interaction.sequence.clone();
+    data
+}
+
+impl ReplayClient {
+    /// Returns a snapshot of all requests recorded so far, including the ones buffered by an
+    /// active `RecordingSession`.
+    ///
+    /// This can be used for custom reports or to export a subset of the recorded requests.
+    pub fn snapshot(&self) -> Result<Cassette, Error> {
+        let mut data = self.recorded_data()?;
+        if let Some(ref buffered) = *self.session.lock().unwrap() {
+            // Buffered data replaces what was recorded for the same request before.
+            data.retain(|d| {
+                !buffered.iter().any(|b| b.request.fingerprint() == d.request.fingerprint())
+            });
+            data.extend(buffered.iter().cloned());
+        }
+
+        Ok(Cassette {
+            interactions: data.into_iter()
+                .map(|d| {
+                    Interaction {
+                        request: d.request,
+                        response: d.response,
+                        sequence: d.sequence,
+                        annotations: d.annotations,
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use client::{Client, RecordingTarget};
+    use reqwest::header::Headers;
+    use reqwest::{Method, StatusCode, Url};
+
+    fn data(path: &str) -> ReplayData {
+        let url = Url::parse(&format!("https://example.com/{}", path)).unwrap();
+        ReplayData::new(
+            Request {
+                url: url.clone(),
+                method: Method::Get,
+                headers: Headers::new(),
+                body: None,
+            },
+            Response {
+                url: url,
+                status: StatusCode::Ok,
+                headers: Headers::new(),
+                body: Bytes::from(path),
+                elapsed: None,
+                replay: None,
+                interim: Vec::new(),
+                trailers: Headers::new(),
+                reset_at: None,
+            },
+            BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn snapshot() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_snapshot");
+        let export = ::std::env::temp_dir().join("reqwest_mock_snapshot_export");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        client.store_data(&data("stored")).unwrap();
+
+        // Requests buffered by a session are included.
+        let session = client.start_session();
+        client.store_data(&data("buffered")).unwrap();
+        let cassette = client.snapshot().unwrap();
+        session.abort();
+        assert_eq!(cassette.len(), 2);
+        assert_eq!(client.snapshot().unwrap().len(), 1);
+
+        let buffered = cassette.filter(|i| i.request.url.path() == "/buffered");
+        assert_eq!(buffered.len(), 1);
+        buffered.write_to_dir(&export).unwrap();
+        let replayed = ReplayClient::new(RecordingTarget::Dir(export.clone()));
+        assert_eq!(replayed.get("https://example.com/buffered").send().unwrap().body, "buffered");
+        assert!(replayed.get("https://example.com/stored").send().is_err());
+
+        let json = cassette.to_json().unwrap();
+        let embedded = ReplayClient::from_static(Box::leak(json.into_bytes().into_boxed_slice()))
+            .unwrap();
+        assert_eq!(embedded.get("https://example.com/stored").send().unwrap().body, "stored");
+        ::std::fs::remove_dir_all(dir).unwrap();
+        ::std::fs::remove_dir_all(export).unwrap();
+    }
+
+    #[test]
+    fn summary() {
+        let url = Url::parse("https://example.com/users").unwrap();
+        let mut annotations = BTreeMap::new();
+        annotations.insert("test".to_string(), "list_users".to_string());
+        let cassette = Cassette {
+            interactions: vec![
+                Interaction {
+                    request: Request {
+                        url: url.clone(),
+                        method: Method::Get,
+                        headers: Headers::new(),
+                        body: None,
+                    },
+                    response: Response {
+                        url: url,
+                        status: StatusCode::Ok,
+                        headers: Headers::new(),
+                        body: Bytes::from("[]"),
+                        elapsed: None,
+                        replay: None,
+                        interim: Vec::new(),
+                        trailers: Headers::new(),
+                        reset_at: None,
+                    },
+                    sequence: Vec::new(),
+                    annotations: annotations,
+                },
+            ],
+        };
+
+        let summary = cassette.summary();
+        assert_eq!(summary.lines().count(), 3);
+        assert!(summary.ends_with(
+            "| GET | https://example.com/users | 200 | 2 | test=list_users |\n",
+        ));
+    }
+}
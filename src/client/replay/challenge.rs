@@ -0,0 +1,107 @@
+use client::replay::{ReplayClient, ReplayData};
+use error::Error;
+use request::Request;
+use reqwest::StatusCode;
+use response::Response;
+
+/// The annotation linking a request answering a `401 Unauthorized` challenge to the request
+/// which was challenged, holding the fingerprint of the latter.
+pub(super) const CHALLENGE_ANNOTATION: &'static str = "reqwest_mock.challenge";
+
+impl ReplayClient {
+    /// Remembers the request if the response is an authentication challenge, so the request
+    /// answering it can be linked to it.
+    pub(super) fn note_challenge(&self, request: &Request, response: &Response) {
+        if response.status == StatusCode::Unauthorized &&
+            response.headers.get_raw("WWW-Authenticate").is_some()
+        {
+            self.challenges.lock().unwrap().insert(
+                challenge_key(request),
+                request.fingerprint(),
+            );
+        }
+    }
+
+    /// Returns the fingerprint of the challenge answered by the request, if it carries
+    /// credentials for a request which was challenged before.
+    pub(super) fn answered_challenge(&self, request: &Request) -> Option<String> {
+        if request.headers.get_raw("Authorization").is_none() {
+            return None;
+        }
+        self.challenges
+            .lock()
+            .unwrap()
+            .get(&challenge_key(request))
+            .map(|fingerprint| format!("{:x}", fingerprint))
+    }
+
+    /// Returns the recorded challenge if the request answers it with credentials which weren't
+    /// recorded, so authentication code keeps being challenged until it supplies the recorded
+    /// credentials.
+    pub(super) fn replay_challenge(&self, request: &Request) -> Result<Option<ReplayData>, Error> {
+        if request.headers.get_raw("Authorization").is_none() {
+            return Ok(None);
+        }
+
+        let data = self.recorded_data()?;
+        let challenge = data.iter()
+            .filter(|d| {
+                d.request.method == request.method && d.request.url == request.url
+            })
+            .filter_map(|d| d.annotations.get(CHALLENGE_ANNOTATION))
+            .next();
+        let challenge = match challenge {
+            Some(challenge) => challenge,
+            None => return Ok(None),
+        };
+
+        debug!("Credentials weren't recorded, replaying the challenge instead.");
+        Ok(
+            data.iter()
+                .find(|d| format!("{:x}", d.request.fingerprint()) == *challenge)
+                .cloned(),
+        )
+    }
+}
+
+fn challenge_key(request: &Request) -> String {
+    format!("{} {}", request.method, request.url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use client::RecordingTarget;
+    use reqwest::header::Headers;
+    use reqwest::{Method, Url};
+
+    #[test]
+    fn answered_challenge() {
+        let client = ReplayClient::new(RecordingTarget::dir("replay"));
+        let mut request = Request {
+            url: Url::parse("https://example.com/private").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let mut response = Response {
+            url: request.url.clone(),
+            status: StatusCode::Unauthorized,
+            headers: Headers::new(),
+            body: Bytes::new(),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        response.headers.set_raw("WWW-Authenticate", "Basic realm=\"private\"");
+        client.note_challenge(&request, &response);
+        let challenge = format!("{:x}", request.fingerprint());
+
+        assert_eq!(client.answered_challenge(&request), None);
+        request.headers.set_raw("Authorization", "Basic YWxhZGRpbg==");
+        assert_eq!(client.answered_challenge(&request), Some(challenge));
+    }
+}
@@ -0,0 +1,216 @@
+//! Storing the bodies of recorded responses in readable formats, see
+//! `ReplaySettings::body_formats`.
+
+use base64;
+use client::replay::{BodyFormat, ReplayData};
+use data::{F_BODY, F_BODY_FORMAT, F_BODY_RENDERING};
+use error::Error;
+use header_ext::HeadersExt;
+use response::Response;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+use std::str;
+
+/// Serializes the replay data, storing the bodies of its responses in the formats configured
+/// for their media types.
+pub(super) fn encode(
+    data: &ReplayData,
+    formats: &BTreeMap<String, BodyFormat>,
+) -> Result<Value, Error> {
+    let mut value = serde_json::to_value(data)?;
+    if formats.is_empty() {
+        return Ok(value);
+    }
+
+    let responses = ::std::iter::once(&data.response).chain(data.sequence.iter());
+    for (i, response) in responses.enumerate() {
+        let stored = if i == 0 {
+            value.get_mut("response")
+        } else {
+            value.get_mut("sequence").and_then(|s| s.get_mut(i - 1))
+        };
+        let format = media_type(response).and_then(|media_type| formats.get(&media_type));
+        if let (Some(stored), Some(format)) = (stored.and_then(Value::as_object_mut), format) {
+            encode_body(stored, &response.body, *format);
+        }
+    }
+    Ok(value)
+}
+
+/// Returns the media type of the response without parameters, e.g. `application/json`.
+fn media_type(response: &Response) -> Option<String> {
+    response.headers.get_str("Content-Type").map(|content_type| {
+        content_type.split(';').next().unwrap_or("").trim().to_lowercase()
+    })
+}
+
+fn encode_body(stored: &mut Map<String, Value>, body: &[u8], format: BodyFormat) {
+    match format {
+        BodyFormat::Base64 => {}
+        BodyFormat::Text => {
+            if let Ok(text) = str::from_utf8(body) {
+                stored.insert(F_BODY.to_string(), Value::String(text.to_string()));
+                stored.insert(F_BODY_FORMAT.to_string(), Value::from("text"));
+            }
+        }
+        BodyFormat::Json => {
+            if let Ok(json) = serde_json::from_slice::<Value>(body) {
+                if serde_json::to_vec(&json).ok().as_ref().map(|v| &v[..]) == Some(body) {
+                    stored.insert(F_BODY.to_string(), json);
+                    stored.insert(F_BODY_FORMAT.to_string(), Value::from("json"));
+                } else {
+                    stored.insert(F_BODY_RENDERING.to_string(), json);
+                }
+            }
+        }
+        BodyFormat::Protobuf => {
+            if let Some(fields) = render_protobuf(body) {
+                stored.insert(F_BODY_RENDERING.to_string(), Value::from(fields));
+            }
+        }
+    }
+}
+
+/// Renders the fields of a protobuf message as `number: value`, or returns `None` if the body
+/// isn't a valid message.
+///
+/// Without the schema, length-delimited fields are shown as strings if they are valid UTF-8 and
+/// base64 encoded otherwise, nested messages aren't decoded.
+fn render_protobuf(mut bytes: &[u8]) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes)?;
+        let value = match key & 7 {
+            0 => varint(&mut bytes)?.to_string(),
+            1 => fixed(take(&mut bytes, 8)?),
+            2 => {
+                let len = varint(&mut bytes)? as usize;
+                let data = take(&mut bytes, len)?;
+                match str::from_utf8(data) {
+                    Ok(text) => format!("{:?}", text),
+                    Err(_) => format!("base64:{}", base64::encode(data)),
+                }
+            }
+            5 => fixed(take(&mut bytes, 4)?),
+            _ => return None,
+        };
+        fields.push(format!("{}: {}", key >> 3, value));
+    }
+    Some(fields)
+}
+
+fn varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..10).map(|i| i * 7) {
+        let (&byte, rest) = (*bytes).split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if bytes.len() < n {
+        return None;
+    }
+    let (taken, rest) = (*bytes).split_at(n);
+    *bytes = rest;
+    Some(taken)
+}
+
+/// Renders a little-endian fixed-size value in hex, as its type isn't known.
+fn fixed(bytes: &[u8]) -> String {
+    let digits: Vec<String> = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use data::ReplayFile;
+    use reqwest::header::Headers;
+    use reqwest::{Method, StatusCode, Url};
+    use request::Request;
+
+    fn data(content_type: &str, body: &'static [u8]) -> ReplayData {
+        let request = Request {
+            url: Url::parse("https://example.com/").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let mut response = Response {
+            url: request.url.clone(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from_static(body),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        response.headers.set_raw("Content-Type", content_type.to_string());
+        ReplayData::new(request, response, BTreeMap::new())
+    }
+
+    fn roundtrip(data: &ReplayData, formats: &BTreeMap<String, BodyFormat>) -> Value {
+        let stored = encode(data, formats).unwrap();
+        let file = ReplayFile::from_value(stored.clone()).unwrap();
+        let decoded = ReplayData::from_file(file).unwrap();
+        assert_eq!(decoded.response.body, data.response.body);
+        stored
+    }
+
+    #[test]
+    fn json() {
+        let mut formats = BTreeMap::new();
+        formats.insert("application/json".to_string(), BodyFormat::Json);
+
+        let compact = data("application/json; charset=utf-8", b"{\"a\":[1,2]}");
+        let stored = roundtrip(&compact, &formats);
+        assert_eq!(stored["response"]["body"]["a"][1], 2);
+
+        // The keys would be reordered, so the JSON is only rendered alongside.
+        let unordered = data("application/json", b"{\"b\": 1, \"a\": 2}");
+        let stored = roundtrip(&unordered, &formats);
+        assert!(stored["response"]["body"].is_string());
+        assert_eq!(stored["response"]["body_rendering"]["b"], 1);
+    }
+
+    #[test]
+    fn replay_file() {
+        let mut formats = BTreeMap::new();
+        formats.insert("application/json".to_string(), BodyFormat::Json);
+        formats.insert("text/plain".to_string(), BodyFormat::Text);
+        let mut data = data("application/json", b"{\"a\":1}");
+        let mut text = data.response.clone().with_body("text");
+        text.headers.set_raw("Content-Type", "text/plain");
+        data.sequence.push(text);
+        data.recorded_at = Some(1_500_000_000);
+
+        let stored = serde_json::to_vec(&encode(&data, &formats).unwrap()).unwrap();
+        let file = ReplayFile::from_reader(&stored[..]).unwrap();
+        assert_eq!(file.response.body, b"{\"a\":1}".to_vec());
+        assert_eq!(file.sequence[0].body, b"text".to_vec());
+        assert_eq!(file.recorded_at, Some(1_500_000_000));
+    }
+
+    #[test]
+    fn protobuf() {
+        assert_eq!(
+            render_protobuf(&[0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i']),
+            Some(vec!["1: 150".to_string(), "2: \"hi\"".to_string()])
+        );
+        assert_eq!(render_protobuf(&[0x12, 0x05, b'h']), None);
+
+        let mut formats = BTreeMap::new();
+        formats.insert("application/x-protobuf".to_string(), BodyFormat::Protobuf);
+        let stored = roundtrip(&data("application/x-protobuf", &[0x08, 0x2a]), &formats);
+        assert_eq!(stored["response"]["body_rendering"][0], "1: 42");
+    }
+}
@@ -0,0 +1,92 @@
+use client::replay::{RecordingTarget, ReplayClient, ReplaySettings};
+use config::ClientConfig;
+use error::{Error, ResultExt};
+use std::fs::File;
+use std::path::Path;
+
+/// The complete configuration of a `ReplayClient`, so the fixture policy of a large test suite
+/// can be kept in one JSON file instead of being repeated in code, see
+/// `ReplayClient::from_config`.
+///
+/// Only `target` is required, all other fields take their default value if missing.
+///
+/// # Examples
+///
+/// ```json
+/// {
+///     "target": {"dir": "fixtures"},
+///     "client": {"timeout": "30s", "failure_style": "Panic"},
+///     "settings": {"offline": false, "ignore_user_agent": true}
+/// }
+/// ```
+#[derive(Serialize, Deserialize)]
+pub struct ReplayConfig {
+    /// Where the replay files are stored, relative paths are relative to the directory of the
+    /// config file.
+    pub target: RecordingTarget,
+
+    #[serde(default)]
+    pub client: ClientConfig,
+
+    #[serde(default)]
+    pub settings: ReplaySettings,
+}
+
+impl ReplayConfig {
+    /// Read the config from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ReplayConfig, Error> {
+        let path = path.as_ref();
+        let file = File::open(path).chain_err(
+            || format!("failed to open config file {}", path.display()),
+        )?;
+        let mut config: ReplayConfig = ::serde_json::from_reader(file).chain_err(|| {
+            format!("invalid config file {}", path.display())
+        })?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        match config.target {
+            RecordingTarget::File(ref mut p) |
+            RecordingTarget::Dir(ref mut p) |
+            RecordingTarget::TemplatedDir(ref mut p, _) => {
+                let joined = base.join(&p);
+                *p = joined;
+            }
+            RecordingTarget::Static(_) => {}
+        }
+        Ok(config)
+    }
+}
+
+impl ReplayClient {
+    /// Create a new `ReplayClient` from a config file, see `ReplayConfig`.
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<ReplayClient, Error> {
+        let config = ReplayConfig::load(path)?;
+        let mut client = ReplayClient::with_settings(config.target, config.settings);
+        client.config = config.client;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn deserialize() {
+        let config: ReplayConfig = ::serde_json::from_value(json!({
+            "target": {"dir": "fixtures"},
+            "client": {"timeout": "30s"},
+            "settings": {"offline": false}
+        })).unwrap();
+
+        match config.target {
+            RecordingTarget::Dir(ref dir) => assert_eq!(dir, Path::new("fixtures")),
+            _ => panic!("wrong target"),
+        }
+        assert_eq!(config.client.timeout, Some(Duration::from_secs(30)));
+        assert!(config.client.gzip);
+        assert!(!config.settings.offline);
+        assert_eq!(config.settings.ignore_scheme, false);
+    }
+}
@@ -0,0 +1,250 @@
+use client::replay::ReplayClient;
+use request::Request;
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::mem;
+
+/// What differed between two runs, see `ReplayClient::check_determinism`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DifferenceKind {
+    /// The runs made a different number of requests, so only the common prefix was compared.
+    RequestCount(usize, usize),
+
+    /// The method or the url without its query differed, so the requests weren't compared
+    /// further.
+    Target,
+
+    /// The value of the query parameter with this name differed.
+    Query(String),
+
+    /// The value of the header with this name differed.
+    Header(String),
+
+    /// The field at this JSON pointer of the JSON bodies differed.
+    BodyField(String),
+
+    /// The (non-JSON) bodies differed.
+    Body,
+}
+
+/// A single difference between two runs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Difference {
+    /// The index of the request in the runs.
+    pub index: usize,
+
+    /// The url of the request in the first run.
+    pub url: Url,
+
+    pub kind: DifferenceKind,
+}
+
+/// Report about the nondeterministic inputs of requests, see `ReplayClient::check_determinism`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeterminismReport {
+    pub differences: Vec<Difference>,
+}
+
+impl DeterminismReport {
+    /// Compare the requests made by two runs of the same code.
+    pub fn compare(first: &[Request], second: &[Request]) -> Self {
+        let mut report = DeterminismReport::default();
+
+        for (index, (a, b)) in first.iter().zip(second.iter()).enumerate() {
+            let mut diff = |kind| {
+                report.differences.push(Difference {
+                    index: index,
+                    url: a.url.clone(),
+                    kind: kind,
+                })
+            };
+
+            let (mut url_a, mut url_b) = (a.url.clone(), b.url.clone());
+            url_a.set_query(None);
+            url_b.set_query(None);
+            if a.method != b.method || url_a != url_b {
+                diff(DifferenceKind::Target);
+                continue;
+            }
+
+            let (query_a, query_b) = (a.query_pairs(), b.query_pairs());
+            let names: BTreeSet<&String> = query_a
+                .iter()
+                .chain(query_b.iter())
+                .map(|&(ref k, _)| k)
+                .collect();
+            for name in names {
+                if a.query(name) != b.query(name) {
+                    diff(DifferenceKind::Query(name.clone()));
+                }
+            }
+
+            let (headers_a, headers_b) = (
+                ::helper::serialize_headers(&a.headers),
+                ::helper::serialize_headers(&b.headers),
+            );
+            let names: BTreeSet<&String> = headers_a.keys().chain(headers_b.keys()).collect();
+            for name in names {
+                if headers_a.get(name) != headers_b.get(name) {
+                    diff(DifferenceKind::Header(name.clone()));
+                }
+            }
+
+            if a.body != b.body {
+                let json = |body: &Option<_>| {
+                    body.as_ref().and_then(|b: &::bytes::Bytes| {
+                        ::serde_json::from_slice::<Value>(b).ok()
+                    })
+                };
+                match (json(&a.body), json(&b.body)) {
+                    (Some(ref json_a), Some(ref json_b)) => {
+                        let mut paths = Vec::new();
+                        diff_json(json_a, json_b, String::new(), &mut paths);
+                        for path in paths {
+                            diff(DifferenceKind::BodyField(path));
+                        }
+                    }
+                    _ => diff(DifferenceKind::Body),
+                }
+            }
+        }
+
+        if first.len() != second.len() {
+            if let Some(req) = first.first().or_else(|| second.first()) {
+                report.differences.push(Difference {
+                    index: ::std::cmp::min(first.len(), second.len()),
+                    url: req.url.clone(),
+                    kind: DifferenceKind::RequestCount(first.len(), second.len()),
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Returns true if no differences were found.
+    pub fn is_deterministic(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// Lists the differences along with suggestions on what to exclude from matching.
+impl fmt::Display for DeterminismReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_deterministic() {
+            return writeln!(f, "All requests were deterministic.");
+        }
+
+        for d in &self.differences {
+            write!(f, "request #{} ({}): ", d.index, d.url)?;
+            match d.kind {
+                DifferenceKind::RequestCount(a, b) => {
+                    writeln!(f, "{} requests in the first run, {} in the second.", a, b)?
+                }
+                DifferenceKind::Target => writeln!(f, "method or url differ.")?,
+                DifferenceKind::Query(ref name) => {
+                    writeln!(
+                        f,
+                        "query parameter `{}` differs, consider excluding it from matching.",
+                        name
+                    )?
+                }
+                DifferenceKind::Header(ref name) => {
+                    writeln!(
+                        f,
+                        "header `{}` differs, consider excluding it from matching.",
+                        name
+                    )?
+                }
+                DifferenceKind::BodyField(ref path) => {
+                    writeln!(
+                        f,
+                        "body field `{}` differs, consider excluding it from matching.",
+                        path
+                    )?
+                }
+                DifferenceKind::Body => writeln!(f, "body differs.")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the JSON pointers of all differing fields.
+fn diff_json(a: &Value, b: &Value, path: String, paths: &mut Vec<String>) {
+    match (a, b) {
+        (&Value::Object(ref a), &Value::Object(ref b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff_json(a, b, child, paths),
+                    _ => paths.push(child),
+                }
+            }
+        }
+        (&Value::Array(ref a), &Value::Array(ref b)) if a.len() == b.len() => {
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                diff_json(a, b, format!("{}/{}", path, i), paths);
+            }
+        }
+        (a, b) => {
+            if a != b {
+                paths.push(if path.is_empty() { "/".to_string() } else { path });
+            }
+        }
+    }
+}
+
+impl ReplayClient {
+    /// Run the provided code twice and report the inputs of its requests which changed between
+    /// the runs, e.g. timestamps or random ids. These prevent replay files from matching, so the
+    /// report suggests excluding them from matching.
+    pub fn check_determinism<F: FnMut(&ReplayClient)>(&self, mut f: F) -> DeterminismReport {
+        let previous = mem::replace(&mut *self.history.lock().unwrap(), Vec::new());
+
+        f(self);
+        let first = mem::replace(&mut *self.history.lock().unwrap(), Vec::new());
+        f(self);
+        let second = mem::replace(&mut *self.history.lock().unwrap(), previous);
+
+        DeterminismReport::compare(&first, &second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+    use reqwest::header::Headers;
+
+    fn request(url: &str, body: &str) -> Request {
+        let mut headers = Headers::new();
+        headers.set_raw("X-Request-Id", url.len().to_string());
+        Request {
+            url: Url::parse(url).unwrap(),
+            method: Method::Post,
+            headers: headers,
+            body: Some(::bytes::Bytes::from(body)),
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let first = [request("http://example.com/?t=1", r#"{"id": 1, "name": "a"}"#)];
+        let second = [request("http://example.com/?t=22", r#"{"id": 2, "name": "a"}"#)];
+
+        let report = DeterminismReport::compare(&first, &second);
+        let kinds: Vec<DifferenceKind> = report.differences.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DifferenceKind::Query("t".to_string()),
+                DifferenceKind::Header("X-Request-Id".to_string()),
+                DifferenceKind::BodyField("/id".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Similarity scores of requests, used to fall back to a similar recorded request when no exact
+//! match exists, see `ReplaySettings::fuzzy_threshold`.
+
+use request::Request;
+use response::Response;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Returns the similarity of both requests in percent, with 100 meaning equal.
+///
+/// Requests with different methods or hosts are never similar. Otherwise the url contributes
+/// half of the score, the body three tenths and the headers the remaining fifth.
+pub fn score(a: &Request, b: &Request) -> u8 {
+    if a.method != b.method || a.url.host_str() != b.url.host_str() {
+        return 0;
+    }
+
+    let url = similarity(&url_tokens(a), &url_tokens(b));
+    let headers = similarity(&header_tokens(a), &header_tokens(b));
+    let body = if a.body == b.body {
+        1.
+    } else {
+        match (json_tokens(a), json_tokens(b)) {
+            (Some(ref tokens_a), Some(ref tokens_b)) => similarity(tokens_a, tokens_b),
+            _ => 0.,
+        }
+    };
+
+    (100. * (0.5 * url + 0.3 * body + 0.2 * headers)).round() as u8
+}
+
+/// Returns false if the recorded response varies on a request header which differs between the
+/// recorded and the incoming request, as the recorded response might be a different variant.
+pub fn vary_matches(response: &Response, recorded: &Request, incoming: &Request) -> bool {
+    let vary = match response.headers.get_raw("Vary") {
+        Some(raw) => raw.iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect::<Vec<_>>()
+            .join(","),
+        None => return true,
+    };
+
+    vary.split(',').map(str::trim).filter(|name| !name.is_empty()).all(|name| {
+        name != "*" && recorded.headers.get_raw(name) == incoming.headers.get_raw(name)
+    })
+}
+
+/// The Jaccard index of both sets, two empty sets are considered equal.
+fn similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        1.
+    } else {
+        a.intersection(b).count() as f64 / union as f64
+    }
+}
+
+fn url_tokens(request: &Request) -> BTreeSet<String> {
+    let url = &request.url;
+    let segments = url.path().split('/').enumerate().map(|(i, segment)| {
+        format!("path {} {}", i, segment)
+    });
+    let query = request.query_pairs().into_iter().map(|(k, v)| {
+        format!("query {}={}", k, v)
+    });
+    segments.chain(query).collect()
+}
+
+fn header_tokens(request: &Request) -> BTreeSet<String> {
+    ::helper::serialize_headers(&request.headers)
+        .into_iter()
+        .map(|(k, v)| format!("{}: {}", k, v))
+        .collect()
+}
+
+/// Returns the leaf values of a JSON body along with their JSON pointers.
+fn json_tokens(request: &Request) -> Option<BTreeSet<String>> {
+    fn collect(value: &Value, path: String, tokens: &mut BTreeSet<String>) {
+        match *value {
+            Value::Object(ref obj) => {
+                for (k, v) in obj {
+                    collect(v, format!("{}/{}", path, k), tokens);
+                }
+            }
+            Value::Array(ref arr) => {
+                for (i, v) in arr.iter().enumerate() {
+                    collect(v, format!("{}/{}", path, i), tokens);
+                }
+            }
+            ref leaf => {
+                tokens.insert(format!("{} {}", path, leaf));
+            }
+        }
+    }
+
+    let body = request.body.as_ref()?;
+    let value: Value = ::serde_json::from_slice(body).ok()?;
+    let mut tokens = BTreeSet::new();
+    collect(&value, String::new(), &mut tokens);
+    Some(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use reqwest::{Method, Url};
+    use reqwest::header::Headers;
+
+    fn request(url: &str, body: &str) -> Request {
+        Request {
+            url: Url::parse(url).unwrap(),
+            method: Method::Post,
+            headers: Headers::new(),
+            body: Some(Bytes::from(body)),
+        }
+    }
+
+    #[test]
+    fn score() {
+        let a = request("http://example.com/users?page=1", r#"{"name": "a", "age": 3}"#);
+        assert_eq!(super::score(&a, &a), 100);
+
+        // A benign field added to the body.
+        let b = request(
+            "http://example.com/users?page=1",
+            r#"{"name": "a", "age": 3, "new": true}"#,
+        );
+        assert_eq!(super::score(&a, &b), 90);
+
+        let c = request("http://example.org/users?page=1", r#"{"name": "a", "age": 3}"#);
+        assert_eq!(super::score(&a, &c), 0);
+    }
+
+    #[test]
+    fn vary_matches() {
+        let mut json = request("http://example.com/users", "");
+        json.headers.set_raw("Accept", "application/json");
+        let mut xml = json.clone();
+        xml.headers.set_raw("Accept", "application/xml");
+
+        let mut response = Response {
+            url: json.url.clone(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::new(),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        assert!(super::vary_matches(&response, &json, &xml));
+
+        response.headers.set_raw("Vary", "Accept-Encoding, Accept");
+        assert!(super::vary_matches(&response, &json, &json));
+        assert!(!super::vary_matches(&response, &json, &xml));
+    }
+}
@@ -0,0 +1,141 @@
+use client::replay::ReplayClient;
+use error::Error;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The annotation holding the group of a recorded request, see `ReplayClient::group`.
+pub(super) const GROUP_ANNOTATION: &'static str = "reqwest_mock.group";
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A group of requests belonging to one logical operation, see `ReplayClient::group`.
+///
+/// The group ends once it is finished or dropped.
+pub struct InteractionGroup<'cl> {
+    client: &'cl ReplayClient,
+    id: usize,
+    path: String,
+    finished: bool,
+}
+
+impl ReplayClient {
+    /// Start a group of requests belonging to one logical operation, e.g. an upload consisting
+    /// of initiating it, uploading the parts and completing it. All requests recorded while the
+    /// group is active are annotated with its name, so replay files stay organized.
+    ///
+    /// Groups can be nested, the annotation of requests in nested groups holds the path of
+    /// names separated by slashes, e.g. `upload/parts`.
+    ///
+    /// Use `InteractionGroup::finish` to verify the whole operation was replayed.
+    pub fn group<S: Into<String>>(&self, name: S) -> InteractionGroup {
+        let mut groups = self.groups.lock().unwrap();
+        let mut path: Vec<&str> = groups.iter().map(|g| g.1.as_str()).collect();
+        let name = name.into();
+        path.push(&name);
+        let path = path.join("/");
+
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        groups.push((id, name.clone(), Default::default()));
+        InteractionGroup {
+            client: self,
+            id: id,
+            path: path,
+            finished: false,
+        }
+    }
+
+    /// Returns the path of the active groups, if any.
+    pub(super) fn group_path(&self) -> Option<String> {
+        let groups = self.groups.lock().unwrap();
+        if groups.is_empty() {
+            None
+        } else {
+            Some(
+                groups
+                    .iter()
+                    .map(|g| g.1.as_str())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        }
+    }
+
+    /// Marks the request as executed in all active groups.
+    pub(super) fn group_executed(&self, fingerprint: u64) {
+        for group in self.groups.lock().unwrap().iter_mut() {
+            group.2.insert(fingerprint);
+        }
+    }
+}
+
+impl<'cl> InteractionGroup<'cl> {
+    /// End the group, verifying that every request recorded in the group (including its nested
+    /// groups) was executed while it was active.
+    ///
+    /// Whether a failed verification panics or returns an error depends on the
+    /// `failure_style` of the client config.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.finished = true;
+        let executed = self.remove();
+
+        let prefix = format!("{}/", self.path);
+        let missing: Vec<String> = self.client
+            .recorded_data()?
+            .into_iter()
+            .filter(|d| match d.annotations.get(GROUP_ANNOTATION) {
+                Some(group) => *group == self.path || group.starts_with(&prefix),
+                None => false,
+            })
+            .filter(|d| !executed.contains(&d.request.fingerprint()))
+            .map(|d| format!("{} {}", d.request.method, d.request.url))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+        self.client.config.failure_style.fail(
+            format!(
+                "These requests recorded in group {} weren't executed:\n{}",
+                self.path,
+                missing.join("\n")
+            ).into(),
+        )
+    }
+
+    /// Removes the group from the active groups, returning the fingerprints of the requests
+    /// executed in it. Groups aren't necessarily ended in the reverse order they were started.
+    fn remove(&self) -> HashSet<u64> {
+        let mut groups = self.client.groups.lock().unwrap();
+        match groups.iter().position(|g| g.0 == self.id) {
+            Some(i) => groups.remove(i).2,
+            None => HashSet::new(),
+        }
+    }
+}
+
+impl<'cl> Drop for InteractionGroup<'cl> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::RecordingTarget;
+
+    #[test]
+    fn drop_out_of_order() {
+        let client = ReplayClient::new(RecordingTarget::dir("replay"));
+        let upload = client.group("upload");
+        let parts = client.group("parts");
+        assert_eq!(client.group_path(), Some("upload/parts".to_string()));
+
+        drop(upload);
+        assert_eq!(client.group_path(), Some("parts".to_string()));
+        drop(parts);
+        assert_eq!(client.group_path(), None);
+    }
+}
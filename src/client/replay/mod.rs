@@ -0,0 +1,2299 @@
+use bytes::Bytes;
+use client::{Client, Coverage, DirectClient};
+use compression::Compression;
+use config::ClientConfig;
+use data::{FORMAT_VERSION, ReplayFile};
+use error::{Error, ErrorKind, ResultExt};
+use metrics::ResponseSource;
+use request::Request;
+use response::{ReplayInfo, Response};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hasher;
+use std::fs::{File, OpenOptions, create_dir_all, read_dir, remove_file};
+use std::io::{BufRead, BufReader, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use twox_hash::XxHash;
+
+mod settings;
+pub use self::settings::{BodyFormat, BodyLimit, ContentLengthPolicy, DateRewrite,
+                         HeaderPolicy, ReplaySettings, RequestBodyPolicy, RerecordMode};
+
+mod cassette;
+pub use self::cassette::{Cassette, Interaction};
+
+mod determinism;
+mod fuzzy;
+pub use self::determinism::{DeterminismReport, Difference, DifferenceKind};
+
+mod challenge;
+mod codec;
+
+mod group;
+pub use self::group::InteractionGroup;
+
+mod registry;
+
+mod scrub;
+pub use self::scrub::IdScrubbing;
+
+mod config_file;
+pub use self::config_file::ReplayConfig;
+
+/// Headers only relevant to a single connection, see `ReplaySettings::keep_hop_by_hop_headers`.
+const HOP_BY_HOP: &'static [&'static str] = &["Connection", "Keep-Alive", "TE", "Trailer",
+                                              "Transfer-Encoding", "Upgrade"];
+
+/// The annotation listing the headers of a recorded request whose value came from the client
+/// config, see `ClientConfig::default_headers`.
+const DEFAULT_HEADERS_ANNOTATION: &'static str = "reqwest_mock.default_headers";
+
+/// The recording target.
+///
+/// When (de)serialized as part of a `ReplayConfig` it is represented like `{"dir": "fixtures"}`,
+/// `Static` targets can't be (de)serialized.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingTarget {
+    /// A single file is used for recording one request, if the request changes the file is
+    /// replaced by a new one.
+    File(PathBuf),
+
+    /// A directory is used in which multiple replay files are managed for each request data
+    /// an individual file is created.
+    Dir(PathBuf),
+
+    /// Like `Dir`, but the paths of the replay files inside the directory are determined by a
+    /// template, so big directories of replay files stay navigable.
+    ///
+    /// The following placeholders are replaced in the template:
+    ///
+    /// - `{host}`: host of the url.
+    /// - `{port}`: port of the url (or the default port of the scheme).
+    /// - `{method}`: HTTP method.
+    /// - `{path}`: path of the url, with slashes replaced by underscores.
+    /// - `{path_hash}`: hash of the path of the url.
+    /// - `{fingerprint}`: hash of the whole request.
+    ///
+    /// For example `{host}/{method}_{path}_{fingerprint}.json`. If the template doesn't contain
+    /// `{fingerprint}` different requests can end up sharing the same replay file, in which case
+    /// it is replaced just like with `File`.
+    TemplatedDir(PathBuf, String),
+
+    /// Replay data embedded into the binary, see `ReplayClient::from_static`. Nothing can be
+    /// recorded, so requests which weren't recorded before result in an error.
+    #[serde(skip)]
+    Static(&'static [u8]),
+}
+
+impl RecordingTarget {
+    /// Shorthand to specify `RecordingTarget::File`.
+    pub fn file<P: Into<PathBuf>>(file: P) -> Self {
+        RecordingTarget::File(file.into())
+    }
+
+    /// Shorthand to specify `RecordingTarget::Dir`.
+    pub fn dir<P: Into<PathBuf>>(dir: P) -> Self {
+        RecordingTarget::Dir(dir.into())
+    }
+
+    /// Shorthand to specify `RecordingTarget::TemplatedDir`.
+    pub fn templated_dir<P: Into<PathBuf>, T: Into<String>>(dir: P, template: T) -> Self {
+        RecordingTarget::TemplatedDir(dir.into(), template.into())
+    }
+}
+
+/// Records responses to requests and replays them if the request is unchanged.
+///
+/// Requests answering a `401 Unauthorized` challenge are recorded linked to the challenged
+/// request. When replaying, requests with credentials which weren't recorded get the recorded
+/// challenge again, so authentication code is exercised rather than skipped.
+pub struct ReplayClient {
+    config: ClientConfig,
+    settings: ReplaySettings,
+    target: RecordingTarget,
+    force_record_next: AtomicBool,
+    client: DirectClient,
+
+    /// Replay data which was already read from disk (or written to it), indexed by the
+    /// fingerprint of its request, so each replay file only has to be parsed once.
+    index: Mutex<HashMap<u64, ReplayData>>,
+
+    /// Annotations to be stored together with the next recorded request.
+    annotations: Mutex<BTreeMap<String, String>>,
+
+    /// The context recorded requests are annotated with, see `ReplayClient::set_context`.
+    context: Mutex<Option<String>>,
+
+    /// How many times each request was replayed already, used to replay sequences of responses.
+    positions: Mutex<HashMap<u64, usize>>,
+
+    /// Recorded data buffered by an active `RecordingSession`.
+    session: Mutex<Option<Vec<ReplayData>>>,
+
+    /// All requests executed by this client.
+    history: Mutex<Vec<Request>>,
+
+    /// Requests which were performed live, or attempted while offline, see
+    /// `ReplayClient::assert_no_network`.
+    live_requests: Mutex<Vec<String>>,
+
+    /// Overrides applied to responses, see `ReplayClient::override_response`.
+    overrides: Mutex<Vec<ResponseOverride>>,
+
+    /// The active groups by their id, with the fingerprints of the requests executed in them,
+    /// see `ReplayClient::group`.
+    groups: Mutex<Vec<(usize, String, HashSet<u64>)>>,
+
+    /// The fingerprints of the requests last challenged with `401 Unauthorized`, by method and
+    /// url, so the requests answering the challenges can be linked to them.
+    challenges: Mutex<HashMap<String, u64>>,
+
+    /// The id of the client in the process-wide registry of replay files and the conflict with
+    /// another client detected when registering, which is returned as an error by `execute`.
+    registration: usize,
+    conflict: Option<String>,
+}
+
+type ResponseOverride = (
+    Box<dyn Fn(&Request) -> bool + Send>,
+    Box<dyn Fn(Response) -> Response + Send>,
+);
+
+impl ReplayClient {
+    /// Create a new `ReplayClient` instance replaying the specified target.
+    ///
+    /// The client is `offline`, requests which weren't recorded yet fail, see `recording` for a
+    /// client recording them.
+    pub fn new(target: RecordingTarget) -> Self {
+        ReplayClient::with_settings(target, ReplaySettings::default())
+    }
+
+    /// Create a new `ReplayClient` instance reading and writing to the specified target, which
+    /// performs requests which weren't recorded yet live and records them.
+    pub fn recording(target: RecordingTarget) -> Self {
+        ReplayClient::with_settings(
+            target,
+            ReplaySettings {
+                offline: false,
+                ..ReplaySettings::default()
+            },
+        )
+    }
+
+    /// Create a new `ReplayClient` instance reading and writing to the specified target, using
+    /// the provided settings.
+    pub fn with_settings(target: RecordingTarget, settings: ReplaySettings) -> Self {
+        let (registration, conflict) = registry::register(&target, &settings);
+        ReplayClient {
+            config: ClientConfig::default(),
+            settings: settings,
+            target: target,
+            force_record_next: AtomicBool::new(false),
+            client: DirectClient::new(),
+            index: Mutex::new(HashMap::new()),
+            annotations: Mutex::new(BTreeMap::new()),
+            context: Mutex::new(None),
+            positions: Mutex::new(HashMap::new()),
+            session: Mutex::new(None),
+            history: Mutex::new(Vec::new()),
+            live_requests: Mutex::new(Vec::new()),
+            overrides: Mutex::new(Vec::new()),
+            groups: Mutex::new(Vec::new()),
+            challenges: Mutex::new(HashMap::new()),
+            registration: registration,
+            conflict: conflict,
+        }
+    }
+
+    /// Create a new `ReplayClient` instance reading and writing to the specified target, which
+    /// records new requests through the provided `reqwest::Client`.
+    ///
+    /// See [DirectClient::with_client](struct.DirectClient.html#method.with_client) for the
+    /// caveats regarding the `ClientConfig`.
+    pub fn with_client(target: RecordingTarget, client: ::reqwest::Client) -> Self {
+        let mut replay = ReplayClient::recording(target);
+        replay.client = DirectClient::with_client(client);
+        replay
+    }
+
+    /// Create a new `ReplayClient` instance replaying data embedded into the binary, which is
+    /// useful for doctests and environments without filesystem access at test runtime.
+    ///
+    /// The data can either be the contents of a single replay file, or a JSON array of the
+    /// contents of multiple replay files.
+    ///
+    /// ```ignore
+    /// let client = ReplayClient::from_static(include_bytes!("../fixtures/users.json")).unwrap();
+    /// ```
+    pub fn from_static(data: &'static [u8]) -> Result<Self, Error> {
+        use serde_json::Value;
+
+        let parsed = ::serde_json::from_slice(data).chain_err(|| {
+            ErrorKind::Fixture("embedded replay data isn't valid JSON".to_string())
+        })?;
+        let values = match parsed {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        let client = ReplayClient::new(RecordingTarget::Static(data));
+        {
+            let mut index = client.index.lock().unwrap();
+            for value in values {
+                match replay_data_from_value(value)? {
+                    Some(d) => index.insert(d.request.fingerprint(), d),
+                    None => {
+                        return Err(
+                            ErrorKind::Fixture(
+                                "embedded replay data has the wrong format version".to_string(),
+                            ).into(),
+                        )
+                    }
+                };
+            }
+        }
+        Ok(client)
+    }
+
+    /// Calling this method ensures that whatever next request is performed it will be recorded
+    /// again, even the exact same request was already made before.
+    pub fn force_record_next(&self) {
+        self.force_record_next.store(true, Ordering::SeqCst);
+    }
+
+    /// Attach an annotation to whatever next request is performed, which will be stored in the
+    /// replay file if that request is recorded. This can be used to document why a replay file
+    /// exists inside of the file itself.
+    ///
+    /// The annotations are discarded after the next request, regardless of whether it was
+    /// recorded or replayed.
+    pub fn annotate<K: Into<String>, V: Into<String>>(&self, key: K, value: V) {
+        self.annotations.lock().unwrap().insert(
+            key.into(),
+            value.into(),
+        );
+    }
+
+    /// Set the context, usually the name of the test, which is stored in the
+    /// `reqwest_mock.context` annotation of all requests recorded from now on. This way it is
+    /// possible to tell which test recorded which entry of a shared replay file.
+    ///
+    /// Without an explicit context the name of the current thread is used, which the libtest
+    /// harness sets to the path of the running test, e.g. `tests::users::create`.
+    pub fn set_context<S: Into<String>>(&self, context: S) {
+        *self.context.lock().unwrap() = Some(context.into());
+    }
+
+    /// Returns the context recorded requests are annotated with, see `set_context`.
+    fn context(&self) -> Option<String> {
+        if let Some(ref context) = *self.context.lock().unwrap() {
+            return Some(context.clone());
+        }
+        ::std::thread::current()
+            .name()
+            .filter(|name| *name != "main")
+            .map(|name| name.to_string())
+    }
+
+    /// Alter the responses to all requests matched by `matcher`, without touching the replay
+    /// files. This way a test can reuse shared replay files but for example simulate a failure
+    /// of a single request.
+    ///
+    /// Overrides only live as long as the client and are applied in the order they were added.
+    ///
+    /// ```ignore
+    /// client.override_response(
+    ///     |req| req.url.path() == "/users",
+    ///     |resp| resp.with_status(StatusCode::InternalServerError),
+    /// );
+    /// ```
+    pub fn override_response<M, F>(&self, matcher: M, f: F)
+    where
+        M: Fn(&Request) -> bool + Send + 'static,
+        F: Fn(Response) -> Response + Send + 'static,
+    {
+        self.overrides.lock().unwrap().push(
+            (Box::new(matcher), Box::new(f)),
+        );
+    }
+
+    /// Load a replay file, and the files it includes, so its recorded request is replayed even
+    /// though the file isn't part of the target. This way common requests like a login only
+    /// have to be recorded once and can be shared by many tests.
+    ///
+    /// With a `RecordingTarget::File` the same can be achieved by listing the shared files in
+    /// the `include` field of the replay file, relative to its directory:
+    ///
+    /// ```json
+    /// { "include": ["common/auth.json"], "request": ..., "response": ..., "format_version": 3 }
+    /// ```
+    ///
+    /// Recorded requests of the target take precedence over included ones.
+    pub fn include<P: AsRef<Path>>(&self, file: P) -> Result<(), Error> {
+        let file = file.as_ref();
+        let data = read_replay_file(file)?.ok_or_else(|| {
+            ErrorKind::Fixture(format!("included replay file {:?} missing or outdated", file))
+        })?;
+
+        self.index.lock().unwrap().entry(data.request.fingerprint()).or_insert_with(|| data.clone());
+        self.load_includes(file, &data.include, &mut vec![file.to_path_buf()])
+    }
+
+    /// Panics if any request was performed live instead of being replayed, listing these requests.
+    ///
+    /// Call this at the end of a test to make sure it only relied on replay files. The underlying
+    /// `reqwest::Client` is only built once a live request is performed. Requests refused because
+    /// the client is `offline` are listed as well, as the code under test might have swallowed
+    /// the error.
+    pub fn assert_no_network(&self) {
+        let live_requests = self.live_requests.lock().unwrap();
+        if !live_requests.is_empty() {
+            panic!(
+                "Expected no network access, but these requests were attempted live:\n{}",
+                live_requests.join("\n")
+            );
+        }
+    }
+
+    /// Like `assert_no_network`, but returns an error unless the `failure_style` of the config
+    /// is `FailureStyle::Panic`.
+    pub fn verify_no_network(&self) -> Result<(), Error> {
+        let live_requests = self.live_requests.lock().unwrap();
+        if live_requests.is_empty() {
+            return Ok(());
+        }
+        self.config.failure_style.fail(
+            format!(
+                "Expected no network access, but these requests were attempted live:\n{}",
+                live_requests.join("\n")
+            ).into(),
+        )
+    }
+
+    /// Finish using the client, usually at the end of a test.
+    ///
+    /// The requests recorded by a session which is still active are written to the target as if
+    /// the session was committed, so nothing recorded is lost. If the client is `offline`,
+    /// `verify_no_network` is run afterwards, which catches requests whose `NetworkAccess` error
+    /// was swallowed by the code under test.
+    pub fn finish(&self) -> Result<(), Error> {
+        let buffered = self.session.lock().unwrap().take();
+        if let Some(buffered) = buffered {
+            debug!("Writing {} requests of the active session.", buffered.len());
+            for data in buffered {
+                self.write_data(&data)?;
+            }
+            self.remove_journal()?;
+        }
+
+        if self.settings.offline {
+            self.verify_no_network()?;
+        }
+        Ok(())
+    }
+
+    /// Start a recording session, while the session is active all recorded requests are only
+    /// buffered in memory. They are written to the target once the session is committed and
+    /// discarded if it is aborted or dropped, which prevents half-recorded replay files when a
+    /// test fails midway through recording.
+    ///
+    /// Panics if there is already an active session.
+    pub fn start_session(&self) -> RecordingSession {
+        let mut session = self.session.lock().unwrap();
+        if session.is_some() {
+            panic!("Tried starting a recording session while another one is still active.");
+        }
+        *session = Some(Vec::new());
+
+        RecordingSession { client: self }
+    }
+
+    /// Returns all requests and responses currently recorded in the target.
+    pub fn recorded(&self) -> Result<Vec<(Request, Response)>, Error> {
+        Ok(
+            self.recorded_data()?
+                .into_iter()
+                .map(|d| (d.request, d.response))
+                .collect(),
+        )
+    }
+
+    /// Reports which recorded requests were replayed by this client and how often. Requests
+    /// which are never replayed usually belong to dead fixtures.
+    pub fn coverage(&self) -> Result<Coverage, Error> {
+        let positions = self.positions.lock().unwrap();
+        let entries = self.recorded_data()?
+            .into_iter()
+            .map(|d| {
+                let replayed = positions.get(&d.request.fingerprint()).cloned().unwrap_or(0);
+                (format!("{} {}", d.request.method, d.request.url), replayed)
+            })
+            .collect();
+        Ok(Coverage::new(entries))
+    }
+
+    fn recorded_data(&self) -> Result<Vec<ReplayData>, Error> {
+        let files = match self.target {
+            RecordingTarget::File(ref file) => vec![file.clone()],
+            RecordingTarget::Static(_) => {
+                // Sorted, as the order of the index changes with every run.
+                let index = self.index.lock().unwrap();
+                let mut recorded: Vec<ReplayData> = index.values().cloned().collect();
+                recorded.sort_by_key(|d| d.request.fingerprint());
+                return Ok(recorded);
+            }
+            RecordingTarget::Dir(ref dir) |
+            RecordingTarget::TemplatedDir(ref dir, _) => {
+                let mut files = Vec::new();
+                collect_replay_files(dir, &mut files)?;
+                files.sort();
+                files
+            }
+        };
+
+        let mut recorded = Vec::new();
+        for file in files {
+            if let Some(data) = read_replay_file(&file)? {
+                recorded.push(data);
+            }
+        }
+        Ok(recorded)
+    }
+
+    fn replay_file_path(&self, request: &Request) -> PathBuf {
+        match self.target {
+            RecordingTarget::File(ref file) => file.clone(),
+            // All embedded data is in the index, so this file is never read or written.
+            RecordingTarget::Static(_) => PathBuf::new(),
+            RecordingTarget::Dir(ref dir) => {
+                let filename = format!("{:x}.json", request.fingerprint());
+                dir.join(filename)
+            }
+            RecordingTarget::TemplatedDir(ref dir, ref template) => {
+                dir.join(render_template(template, request))
+            }
+        }
+    }
+
+    /// The path of the journal, see `ReplaySettings::journal`.
+    fn journal_path(&self) -> Option<PathBuf> {
+        match self.target {
+            RecordingTarget::File(ref file) => {
+                let mut journal = file.clone().into_os_string();
+                journal.push(".journal");
+                Some(journal.into())
+            }
+            RecordingTarget::Static(_) => None,
+            RecordingTarget::Dir(ref dir) |
+            RecordingTarget::TemplatedDir(ref dir, _) => Some(dir.join("journal.jsonl")),
+        }
+    }
+
+    /// Appends the recorded data to the journal, as a single line of JSON.
+    fn append_journal(&self, data: &ReplayData) -> Result<(), Error> {
+        let path = match self.journal_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                create_dir_all(parent)?;
+            }
+        }
+
+        let mut line = ::serde_json::to_vec(data)?;
+        line.push(b'\n');
+        let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+        f.write_all(&line)?;
+        f.sync_data()?;
+        Ok(())
+    }
+
+    fn remove_journal(&self) -> Result<(), Error> {
+        match self.journal_path() {
+            Some(ref path) if path.exists() => Ok(remove_file(path)?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the requests left in the journal by a session which was neither committed nor
+    /// aborted, e.g. because the test crashed, to the target and removes the journal. Returns
+    /// the number of recovered requests.
+    ///
+    /// A last line only written partially is skipped.
+    pub fn compact_journal(&self) -> Result<usize, Error> {
+        let path = match self.journal_path() {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return Ok(0),
+        };
+
+        let mut recovered = 0;
+        for line in BufReader::new(File::open(&path)?).lines() {
+            let line = line?;
+            match ::serde_json::from_str::<ReplayData>(&line) {
+                Ok(data) => {
+                    self.write_data(&data)?;
+                    recovered += 1;
+                }
+                Err(e) => warn!("Skipping incomplete journal entry: {}", e),
+            }
+        }
+        remove_file(&path)?;
+        Ok(recovered)
+    }
+
+    /// The possible results:
+    ///
+    /// Err(_)      → something went wrong.
+    /// Ok(None)    → no data was stored yet, i. e. the file doesn't exist yet.
+    /// Ok(Some(_)) → the actual data
+    fn get_data(&self, request: &Request) -> Result<Option<ReplayData>, Error> {
+        if let Some(data) = self.index.lock().unwrap().get(&request.fingerprint()) {
+            debug!("Found replay data in the index.");
+            return Ok(Some(data.clone()));
+        }
+
+        let file = self.replay_file_path(request);
+        match read_replay_file(&file)? {
+            Some(ref d) if !self.matcher_unchanged(&file, d) => Ok(None),
+            Some(d) => {
+                self.index.lock().unwrap().insert(
+                    d.request.fingerprint(),
+                    d.clone(),
+                );
+                self.load_includes(&file, &d.include, &mut vec![file.clone()])?;
+
+                // The request might be one of the included ones.
+                let index = self.index.lock().unwrap();
+                Ok(Some(index.get(&request.fingerprint()).cloned().unwrap_or(d)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks whether the replay file was recorded with the current matching settings, see
+    /// `ReplaySettings::rerecord_on_matcher_change`. Returns false if it should be recorded again.
+    fn matcher_unchanged(&self, file: &Path, data: &ReplayData) -> bool {
+        let current = self.settings.matcher_fingerprint();
+        match data.matcher {
+            Some(ref recorded) if *recorded != current => {
+                warn!(
+                    "Replay file {:?} was recorded with different matching settings.",
+                    file
+                );
+                !self.settings.rerecord_on_matcher_change
+            }
+            _ => true,
+        }
+    }
+
+    /// Recursively adds the files included by a replay file to the index, `visited` holds the
+    /// files which were already loaded to break cycles.
+    fn load_includes(
+        &self,
+        file: &Path,
+        include: &[String],
+        visited: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        let dir = file.parent().unwrap_or_else(|| Path::new(""));
+        for path in include {
+            let included = dir.join(path);
+            if visited.contains(&included) {
+                continue;
+            }
+            visited.push(included.clone());
+
+            debug!("Loading included replay file: {:?}", included);
+            let data = read_replay_file(&included)?.ok_or_else(|| {
+                ErrorKind::Fixture(format!(
+                    "replay file {:?} included by {:?} missing or outdated",
+                    included,
+                    file
+                ))
+            })?;
+            self.index
+                .lock()
+                .unwrap()
+                .entry(data.request.fingerprint())
+                .or_insert_with(|| data.clone());
+            self.load_includes(&included, &data.include, visited)?;
+        }
+        Ok(())
+    }
+
+    fn store_data(&self, data: &ReplayData) -> Result<(), Error> {
+        if let Some(ref mut buffered) = *self.session.lock().unwrap() {
+            debug!("Buffering recorded request until the session is committed.");
+            if self.settings.journal {
+                self.append_journal(data)?;
+            }
+            buffered.push(data.clone());
+            self.update_index(data);
+            return Ok(());
+        }
+
+        self.write_data(data)
+    }
+
+    fn write_data(&self, data: &ReplayData) -> Result<(), Error> {
+        let file = self.replay_file_path(&data.request);
+        debug!("Writing replay file at: {:?}", file);
+
+        // Attempt to create the directory of the file if it doesn't exist yet.
+        if let Some(parent) = file.parent() {
+            if !parent.exists() {
+                create_dir_all(parent)?;
+            }
+        }
+
+        // Write the file, pretty-printed if bodies are stored in readable formats.
+        let value = codec::encode(data, &self.settings.body_formats)?;
+        let f = File::create(&file)?;
+        if self.settings.body_formats.is_empty() {
+            ::serde_json::to_writer(f, &value)?;
+        } else {
+            ::serde_json::to_writer_pretty(f, &value)?;
+        }
+
+        self.update_index(data);
+        Ok(())
+    }
+
+    fn update_index(&self, data: &ReplayData) {
+        // A single replay file only ever holds one request.
+        let file = self.replay_file_path(&data.request);
+        let mut index = self.index.lock().unwrap();
+        index.retain(|_, d| self.replay_file_path(&d.request) != file);
+        index.insert(data.request.fingerprint(), data.clone());
+    }
+
+    /// Returns the request as it is matched and recorded, according to the settings.
+    fn normalize(&self, mut request: Request) -> Request {
+        if self.settings.ignore_user_agent {
+            request.headers.remove_raw("User-Agent");
+        }
+
+        if self.settings.ignore_authorization_credentials {
+            use header_ext::HeadersExt;
+
+            let scheme = request.headers.get_str("Authorization").map(|value| {
+                value.split_whitespace().next().unwrap_or("").to_string()
+            });
+            if let Some(scheme) = scheme {
+                request.headers.set_raw("Authorization", scheme);
+            }
+        }
+
+        // Hostnames are already converted to punycode by the url crate, but percent-encoding is
+        // kept as it is.
+        let path = normalize_percent_encoding(request.url.path());
+        request.url.set_path(&path);
+        if let Some(query) = request.url.query().map(normalize_percent_encoding) {
+            request.url.set_query(Some(&query));
+        }
+
+        if let Some(ref scrubbing) = self.settings.scrub_ids {
+            scrubbing.scrub(&mut request);
+        }
+
+        if self.settings.ignore_scheme && request.url.scheme() == "http" {
+            // Both are special schemes, so this can't fail.
+            let _ = request.url.set_scheme("https");
+            if request.url.port() == Some(443) {
+                let _ = request.url.set_port(None);
+            }
+        }
+
+        if self.settings.decompress_request_body {
+            use header_ext::HeadersExt;
+
+            let compression = request.headers.get_str("Content-Encoding").and_then(
+                Compression::from_encoding,
+            );
+            if let (Some(compression), Some(body)) = (compression, request.body.clone()) {
+                match compression.decompress(&body) {
+                    Ok(body) => request.body = Some(body),
+                    Err(e) => warn!("Failed to decompress request body: {}", e),
+                }
+            }
+        }
+
+        match self.settings.request_body {
+            RequestBodyPolicy::Record => {}
+            RequestBodyPolicy::Hash => {
+                request.body = request.body.map(|body| {
+                    let mut hasher = XxHash::with_seed(42);
+                    hasher.write(&body);
+                    Bytes::from(format!("xxhash64:{:016x}", hasher.finish()))
+                });
+            }
+            RequestBodyPolicy::Omit => request.body = None,
+        }
+
+        request
+    }
+
+    /// Returns the most similar recorded data reaching the threshold, see
+    /// `ReplaySettings::fuzzy_threshold`.
+    fn fuzzy_match(&self, request: &Request, threshold: u8) -> Result<Option<ReplayData>, Error> {
+        let best = self.recorded_data()?
+            .into_iter()
+            .filter(|d| fuzzy::vary_matches(&d.response, &d.request, request))
+            .map(|d| (fuzzy::score(&d.request, request), d))
+            .filter(|&(score, _)| score >= threshold)
+            .max_by_key(|&(score, _)| score);
+
+        Ok(best.map(|(score, d)| {
+            info!(
+                "Fuzzily matched {} request of URL {} to the recorded request of URL {} ({}% similar).",
+                request.method,
+                request.url,
+                d.request.url,
+                score
+            );
+            d
+        }))
+    }
+
+    /// Returns the response to be replayed next for the recorded data.
+    fn next_response(&self, request: &Request, data: ReplayData) -> Result<Response, Error> {
+        let path = match self.target {
+            RecordingTarget::Static(_) => None,
+            _ => Some(self.replay_file_path(&data.request)),
+        };
+        let fingerprint = format!("{:x}", data.request.fingerprint());
+        let recorded_at = data.recorded_at.map(|secs| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+        });
+        let (index, mut response) = self.next_recorded_response(data);
+        response.replay = Some(ReplayInfo {
+            path: path,
+            fingerprint: fingerprint,
+            index: index,
+            recorded_at: recorded_at,
+        });
+
+        // TODO: Simulating a chunked transfer of the body requires streaming response bodies.
+        if !self.settings.keep_hop_by_hop_headers {
+            for name in HOP_BY_HOP {
+                response.headers.remove_raw(name);
+            }
+        }
+
+        self.rewrite_dates(&mut response);
+        self.check_content_length(request, response)
+    }
+
+    /// Applies `ReplaySettings::rewrite_date` to a replayed response.
+    fn rewrite_dates(&self, response: &mut Response) {
+        use header_ext::HeadersExt;
+        use reqwest::header::HttpDate;
+
+        let target = match self.settings.rewrite_date {
+            None => return,
+            Some(DateRewrite::Now) => SystemTime::now(),
+            Some(DateRewrite::Fixed(time)) => time,
+        };
+        let parse = |response: &Response, name: &str| {
+            response
+                .headers
+                .get_str(name)
+                .and_then(|value| value.parse::<HttpDate>().ok())
+                .map(SystemTime::from)
+        };
+
+        let recorded = match parse(response, "Date") {
+            Some(recorded) => recorded,
+            None => {
+                response.headers.set_raw("Date", HttpDate::from(target).to_string());
+                return;
+            }
+        };
+        for name in &["Date", "Expires", "Last-Modified"] {
+            if let Some(time) = parse(response, name) {
+                let shifted = match target.duration_since(recorded) {
+                    Ok(offset) => time + offset,
+                    Err(e) => time - e.duration(),
+                };
+                response.headers.set_raw(*name, HttpDate::from(shifted).to_string());
+            }
+        }
+    }
+
+    /// Applies the `ReplaySettings::content_length` policy to a replayed response.
+    fn check_content_length(
+        &self,
+        request: &Request,
+        mut response: Response,
+    ) -> Result<Response, Error> {
+        use header_ext::HeadersExt;
+        use reqwest::{Method, StatusCode};
+
+        if request.method == Method::Head || response.status == StatusCode::NotModified {
+            return Ok(response);
+        }
+
+        let actual = response.body.len() as u64;
+        match response.headers.content_length() {
+            Some(recorded) if recorded != actual => {
+                match self.settings.content_length {
+                    ContentLengthPolicy::Ignore => {}
+                    ContentLengthPolicy::Fix => {
+                        debug!(
+                            "Fixing recorded Content-Length {} to the body length {}.",
+                            recorded,
+                            actual
+                        );
+                        response.headers.set_raw("Content-Length", actual.to_string());
+                    }
+                    ContentLengthPolicy::Error => {
+                        return Err(
+                            ErrorKind::Fixture(format!(
+                                "recorded Content-Length {} doesn't match the body length {} of \
+                                 the response to URL: {}",
+                                recorded,
+                                actual,
+                                request.url
+                            )).into(),
+                        )
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(response)
+    }
+
+    /// Returns the recorded response which is next in sequence, together with its index among
+    /// the recorded responses.
+    fn next_recorded_response(&self, data: ReplayData) -> (usize, Response) {
+        let mut positions = self.positions.lock().unwrap();
+        let position = positions.entry(data.request.fingerprint()).or_insert(0);
+        let index = *position;
+        *position += 1;
+
+        if index == 0 || data.sequence.is_empty() {
+            (0, data.response)
+        } else {
+            let index = ::std::cmp::min(index, data.sequence.len());
+            (index, data.sequence.into_iter().nth(index - 1).unwrap())
+        }
+    }
+}
+
+impl Client for ReplayClient {
+    fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        let metrics_config = config.unwrap_or_else(|| &self.config);
+        let result = ::metrics::instrument(metrics_config, request, |request| {
+            let (mut response, source) = self.replay_or_record(config, request.clone())?;
+            if let (ResponseSource::Replayed, Some(elapsed), Some(total_timeout)) =
+                (source, response.elapsed, metrics_config.total_timeout)
+            {
+                if elapsed > total_timeout {
+                    return Err(ErrorKind::TimedOut(elapsed).into());
+                }
+            }
+
+            for &(ref matcher, ref f) in self.overrides.lock().unwrap().iter() {
+                if matcher(&request) {
+                    debug!(
+                        "Overriding response to {} request of URL: {}",
+                        request.method,
+                        request.url
+                    );
+                    response = f(response);
+                }
+            }
+            Ok((response, source))
+        });
+        metrics_config.failure_style.check(result)
+    }
+
+    fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        &mut self.config
+    }
+
+    /// Returns the requests executed so far, normalized the way they are matched against the
+    /// recorded ones.
+    fn requests(&self) -> Vec<Request> {
+        self.history.lock().unwrap().clone()
+    }
+}
+
+impl ReplayClient {
+    fn replay_or_record(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<(Response, ResponseSource), Error> {
+        if let Some(ref conflict) = self.conflict {
+            return Err(ErrorKind::Configuration(conflict.clone()).into());
+        }
+
+        // The request is matched and recorded normalized, but sent as it is.
+        let live_request = request.clone();
+        let request = self.normalize(request);
+
+        // Some information potentially useful for debugging.
+        debug!(
+            "ReplayClient performing {} request of URL: {}",
+            request.method,
+            request.url
+        );
+        trace!("request headers: {}", request.headers);
+        trace!("request body: {:?}", request.body);
+
+        // Use internal config if none was provided together with the request.
+        let config = config.unwrap_or_else(|| &self.config);
+        let mut annotations =
+            mem::replace(&mut *self.annotations.lock().unwrap(), BTreeMap::new());
+        if let Some(context) = self.context() {
+            annotations.entry("reqwest_mock.context".to_string()).or_insert(context);
+        }
+        if let Some(group) = self.group_path() {
+            annotations.insert(group::GROUP_ANNOTATION.to_string(), group);
+        }
+        let defaults = default_headers(config, &live_request);
+        if !defaults.is_empty() {
+            annotations.insert(DEFAULT_HEADERS_ANNOTATION.to_string(), defaults.join(", "));
+        }
+        if let Some(challenge) = self.answered_challenge(&request) {
+            annotations.insert(challenge::CHALLENGE_ANNOTATION.to_string(), challenge);
+        }
+        self.group_executed(request.fingerprint());
+        self.history.lock().unwrap().push(request.clone());
+
+        // Check if the request was already performed with this exact arguments,
+        // if it was just return the existing result otherwise perform the request and store
+        // the output.
+
+        let force_record = self.force_record_next.swap(false, Ordering::SeqCst);
+        let data = self.get_data(&request)?;
+        if let Some(ref d) = data {
+            if d.request == request {
+                if force_record {
+                    debug!("Force record was requested, ignoring existing replay data.");
+                } else {
+                    let response = self.next_response(&request, d.clone())?;
+                    self.note_challenge(&request, &response);
+                    return Ok((response, ResponseSource::Replayed));
+                }
+            }
+        }
+
+        if let (false, Some(threshold)) = (force_record, self.settings.fuzzy_threshold) {
+            if let Some(d) = self.fuzzy_match(&request, threshold)? {
+                let response = self.next_response(&request, d)?;
+                return Ok((response, ResponseSource::Replayed));
+            }
+        }
+
+        if let Some(ref d) = data {
+            if d.request != request {
+                if !self.settings.offline {
+                    warn!("Request has changed, recording again now.");
+                }
+
+                let empty = ::bytes::Bytes::new();
+                let recorded = d.request.body.as_ref().unwrap_or(&empty);
+                let incoming = request.body.as_ref().unwrap_or(&empty);
+                if let Some(diff) = ::helper::body_diff(recorded, incoming) {
+                    warn!("Recorded and incoming request {}", diff);
+                }
+            }
+        }
+
+        if !force_record {
+            if let Some(d) = self.replay_challenge(&request)? {
+                let response = self.next_response(&request, d)?;
+                return Ok((response, ResponseSource::Replayed));
+            }
+        }
+
+        if let RecordingTarget::Static(_) = self.target {
+            return Err(
+                ErrorKind::Unmatched(request.method.to_string(), request.url.to_string()).into(),
+            );
+        }
+
+        if self.settings.offline {
+            self.live_requests.lock().unwrap().push(
+                format!("{} {}", request.method, request.url),
+            );
+            return Err(
+                ErrorKind::NetworkAccess(request.method.to_string(), request.url.to_string())
+                    .into(),
+            );
+        }
+
+        // We actually have to perform the request and store the response.
+        self.live_requests.lock().unwrap().push(
+            format!("{} {}", request.method, request.url),
+        );
+        let response = match self.settings.max_body_size {
+            Some(BodyLimit::Error(max)) => {
+                self.client.send_live_limited(config, live_request, max)?
+            }
+            _ => self.client.send_live(config, live_request)?,
+        };
+        self.note_challenge(&request, &response);
+
+        // Only the headers allowed by the policy are recorded.
+        let mut recorded = response.clone();
+        recorded.headers = ::reqwest::header::Headers::new();
+        recorded.headers.extend(response.headers.iter().filter(|h| {
+            self.settings.record_headers.records(h.name())
+        }));
+
+        if let Some(BodyLimit::Truncate(max)) = self.settings.max_body_size {
+            if recorded.body.len() > max {
+                debug!("Truncating recorded body of {} bytes.", recorded.body.len());
+                annotations.insert(
+                    "reqwest_mock.truncated_body".to_string(),
+                    format!("{} bytes", recorded.body.len()),
+                );
+                recorded.body.truncate(max);
+            }
+        }
+
+        let mut data = match data {
+            Some(mut d) => {
+                if d.request == request {
+                    if d.response == recorded || d.sequence.contains(&recorded) {
+                        debug!("Identical response was already recorded, not writing it again.");
+                        return Ok((response, ResponseSource::Live));
+                    }
+
+                    match self.settings.rerecord {
+                        RerecordMode::Replace => {
+                            d.response = recorded;
+                            d.sequence.clear();
+                        }
+                        RerecordMode::Sequence => d.sequence.push(recorded),
+                    }
+                    d.annotations.extend(annotations);
+                    d
+                } else {
+                    // The replay file is replaced, but should still include the same files.
+                    let mut new = ReplayData::new(request, recorded, annotations);
+                    new.include = d.include;
+                    new
+                }
+            }
+            None => ReplayData::new(request, recorded, annotations),
+        };
+        data.matcher = Some(self.settings.matcher_fingerprint());
+        data.recorded_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        self.store_data(&data)?;
+
+        // Return the response.
+        Ok((response, ResponseSource::Live))
+    }
+}
+
+/// An active recording session of a `ReplayClient`, see `ReplayClient::start_session`.
+///
+/// Dropping the session without committing it discards all requests recorded during the session.
+/// Unless the thread is panicking anyway (e.g. because a test failed), this is considered a bug
+/// and results in a panic in debug builds, so call `abort` to discard them deliberately.
+#[must_use]
+pub struct RecordingSession<'cl> {
+    client: &'cl ReplayClient,
+}
+
+impl<'cl> RecordingSession<'cl> {
+    /// Write all requests recorded during the session to the target.
+    pub fn commit(self) -> Result<(), Error> {
+        let buffered = self.client.session.lock().unwrap().take();
+        for data in buffered.unwrap_or_default() {
+            self.client.write_data(&data)?;
+        }
+        self.client.remove_journal()
+    }
+
+    /// Discard all requests recorded during the session.
+    pub fn abort(self) {
+        self.discard();
+        if let Err(e) = self.client.remove_journal() {
+            warn!("Failed removing the journal of the aborted session: {}", e);
+        }
+    }
+
+    /// Returns the total time it took to perform the requests recorded during the session so
+    /// far.
+    pub fn elapsed(&self) -> Duration {
+        let session = self.client.session.lock().unwrap();
+        let buffered = session.as_ref().map(|b| &b[..]).unwrap_or(&[]);
+        buffered
+            .iter()
+            .flat_map(|d| d.sequence.iter().chain(Some(&d.response)))
+            .filter_map(|r| r.elapsed)
+            .fold(Duration::from_secs(0), |total, e| total + e)
+    }
+
+    /// Discards the buffered requests, returning how many there were.
+    fn discard(&self) -> usize {
+        let buffered = self.client.session.lock().unwrap().take();
+        match buffered {
+            Some(buffered) => {
+                debug!("Discarding {} requests recorded in session.", buffered.len());
+
+                // Forget about the discarded data, so it is read from disk again.
+                let mut index = self.client.index.lock().unwrap();
+                for data in &buffered {
+                    index.remove(&data.request.fingerprint());
+                }
+                buffered.len()
+            }
+            None => 0,
+        }
+    }
+}
+
+impl Drop for ReplayClient {
+    fn drop(&mut self) {
+        registry::unregister(self.registration);
+    }
+}
+
+impl<'cl> Drop for RecordingSession<'cl> {
+    fn drop(&mut self) {
+        let discarded = self.discard();
+        if !::std::thread::panicking() {
+            debug_assert!(
+                discarded == 0,
+                "RecordingSession with {} recorded requests was dropped without calling \
+                 `commit()` or `abort()`, the requests were discarded.",
+                discarded
+            );
+        }
+    }
+}
+
+/// The data stored inside of a replay file.
+///
+/// Headers and annotations are stored in sorted maps and the fields are always written in the
+/// same order, so recording an unchanged interaction again results in an identical file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ReplayData {
+    request: Request,
+    response: Response,
+
+    /// Further responses recorded for the same request, see `RerecordMode::Sequence`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sequence: Vec<Response>,
+
+    /// User provided annotations, see `ReplayClient::annotate`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<String, String>,
+
+    /// Further replay files to load, relative to the directory of this one, see
+    /// `ReplayClient::include`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+
+    /// Fingerprint of the matching settings used when recording, see
+    /// `ReplaySettings::matcher_fingerprint`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matcher: Option<String>,
+
+    /// When the interaction was recorded, in seconds since the Unix epoch, see
+    /// `Response::replay_info`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recorded_at: Option<u64>,
+
+    format_version: u8,
+}
+
+impl ReplayData {
+    fn new(request: Request, response: Response, annotations: BTreeMap<String, String>) -> Self {
+        ReplayData {
+            request: request,
+            response: response,
+            sequence: Vec::new(),
+            annotations: annotations,
+            include: Vec::new(),
+            matcher: None,
+            recorded_at: None,
+            format_version: FORMAT_VERSION,
+        }
+    }
+
+    /// Converts the contents of a replay file as parsed by `data::ReplayFile`.
+    fn from_file(file: ReplayFile) -> Result<Self, Error> {
+        Ok(ReplayData {
+            request: Request::from_data(file.request)?,
+            response: Response::from_data(file.response)?,
+            sequence: file.sequence
+                .into_iter()
+                .map(Response::from_data)
+                .collect::<Result<_, _>>()?,
+            annotations: file.annotations,
+            include: file.include,
+            matcher: file.matcher,
+            recorded_at: file.recorded_at,
+            format_version: file.format_version,
+        })
+    }
+}
+
+/// Reads a replay file from disk, see `ReplayClient::get_data` for the possible results.
+fn read_replay_file(file: &Path) -> Result<Option<ReplayData>, Error> {
+    debug!("Checking presence of replay file: {:?}", file);
+
+    if !file.exists() {
+        debug!("No existing replay file was found.");
+        Ok(None)
+    } else {
+        use serde_json::Value;
+
+        debug!("Reading existing replay file.");
+        let f = File::open(&file)?;
+        let value: Value = ::serde_json::from_reader(f).chain_err(|| {
+            ErrorKind::Fixture(format!("replay file {:?} isn't valid JSON", file))
+        })?;
+        replay_data_from_value(value).chain_err(|| {
+            ErrorKind::Fixture(format!("invalid replay file {:?}", file))
+        })
+    }
+}
+
+/// Parses the contents of a replay file, returning `Ok(None)` if its format version differs.
+fn replay_data_from_value(
+    value: ::serde_json::Value,
+) -> Result<Option<ReplayData>, Error> {
+    use serde_json::Value;
+
+    // Check the format version.
+    let format_version = match value {
+        Value::Object(ref obj) => {
+            obj.get("format_version").and_then(|val| val.as_u64()).map(
+                |n| n as u8,
+            )
+        }
+        _ => None,
+    };
+
+    if format_version == Some(FORMAT_VERSION) {
+        let file = ReplayFile::from_value(value).chain_err(|| {
+            ErrorKind::Fixture("replay data doesn't match the format".to_string())
+        })?;
+        let data = ReplayData::from_file(file).chain_err(|| {
+            ErrorKind::Fixture("replay data holds invalid values".to_string())
+        })?;
+        Ok(Some(data))
+    } else {
+        debug!(
+            "Replay data has wrong format version: {:?}",
+            format_version
+        );
+        Ok(None)
+    }
+}
+
+/// Recursively collects all replay files in the directory.
+fn collect_replay_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_replay_files(&path, files)?;
+        } else if path.extension().map_or(false, |ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Normalizes percent-encoding as described by RFC 3986: hex digits are uppercased and
+/// unreserved characters are decoded.
+fn normalize_percent_encoding(s: &str) -> String {
+    fn hex(b: u8) -> Option<u8> {
+        (b as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let bytes = s.as_bytes();
+    let mut normalized = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                let c = hi * 16 + lo;
+                if c.is_ascii_alphanumeric() || b"-._~".contains(&c) {
+                    normalized.push(c);
+                } else {
+                    normalized.extend(format!("%{:02X}", c).bytes());
+                }
+                i += 3;
+                continue;
+            }
+        }
+        normalized.push(bytes[i]);
+        i += 1;
+    }
+
+    // Only ASCII was replaced by ASCII, so this is still valid UTF-8.
+    String::from_utf8(normalized).unwrap()
+}
+
+/// Returns the names of the headers of the request whose value equals the one configured by
+/// `ClientConfig::user_agent` or `ClientConfig::default_headers`.
+///
+/// A header set explicitly to the default value can't be told apart, so it counts as default.
+fn default_headers(config: &ClientConfig, request: &Request) -> Vec<String> {
+    let user_agent = config.user_agent.as_ref().map(|ua| ("User-Agent", ua));
+    let defaults = user_agent.into_iter().chain(
+        config.default_headers.iter().map(|(n, v)| (n.as_str(), v)),
+    );
+
+    let mut names = Vec::new();
+    for (name, value) in defaults {
+        let is_default = request.headers.get_raw(name).and_then(|raw| raw.one()) ==
+            Some(value.as_bytes());
+        if is_default && !names.iter().any(|n: &String| n.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Replaces the placeholders of a `RecordingTarget::TemplatedDir` template.
+fn render_template(template: &str, request: &Request) -> String {
+    // Values are inserted into paths, so they may only contain harmless characters.
+    fn sanitize(s: &str) -> String {
+        s.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            })
+            .collect()
+    }
+
+    let url = &request.url;
+    let mut path_hasher = XxHash::with_seed(42);
+    path_hasher.write(url.path().as_bytes());
+
+    template
+        .replace("{host}", &sanitize(url.host_str().unwrap_or("")))
+        .replace(
+            "{port}",
+            &url.port_or_known_default()
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+        )
+        .replace("{method}", &sanitize(request.method.as_ref()))
+        .replace("{path}", &sanitize(url.path().trim_matches('/')))
+        .replace("{path_hash}", &format!("{:x}", path_hasher.finish()))
+        .replace("{fingerprint}", &format!("{:x}", request.fingerprint()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::Headers;
+    use reqwest::{Method, StatusCode, Url};
+    use helper::serve;
+    use serde_json::Value;
+
+    fn get(url: &str) -> Request {
+        Request {
+            url: Url::parse(url).unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        }
+    }
+
+    fn ok(request: &Request, body: &str) -> Response {
+        Response {
+            url: request.url.clone(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from(body),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        }
+    }
+
+    /// Returns the contents of the replay file the request is recorded in.
+    fn replay_file(client: &ReplayClient, request: Request) -> Value {
+        let file = client.replay_file_path(&client.normalize(request));
+        ::serde_json::from_reader(File::open(file).unwrap()).unwrap()
+    }
+
+
+    #[test]
+    fn normalize_percent_encoding() {
+        assert_eq!(
+            super::normalize_percent_encoding("/a%2fb/%7euser/%41?x=%e2%82%ac"),
+            "/a%2Fb/~user/A?x=%E2%82%AC"
+        );
+        assert_eq!(super::normalize_percent_encoding("100%"), "100%");
+
+        // Hostnames are normalized by the url crate.
+        assert_eq!(
+            Url::parse("http://bücher.example/").unwrap().host_str(),
+            Some("xn--bcher-kva.example")
+        );
+    }
+
+    #[test]
+    fn default_ports_normalized() {
+        // Matching relies on the url crate omitting default ports.
+        assert_eq!(
+            Url::parse("https://example.com:443/").unwrap(),
+            Url::parse("https://example.com/").unwrap()
+        );
+        assert_eq!(
+            Url::parse("http://example.com:80/").unwrap(),
+            Url::parse("http://example.com/").unwrap()
+        );
+    }
+
+    #[test]
+    fn deterministic_serialization() {
+        let data = |names: &[&str]| {
+            let mut headers = Headers::new();
+            for name in names {
+                headers.set_raw(name.to_string(), "value");
+            }
+            let request = Request {
+                url: Url::parse("https://example.com/?b=1&a=2").unwrap(),
+                method: Method::Get,
+                headers: headers.clone(),
+                body: None,
+            };
+            let response = Response {
+                url: request.url.clone(),
+                status: ::reqwest::StatusCode::Ok,
+                headers: headers,
+                body: Bytes::from("body"),
+                elapsed: None,
+                replay: None,
+                interim: Vec::new(),
+                trailers: Headers::new(),
+                reset_at: None,
+            };
+            let mut annotations = BTreeMap::new();
+            for name in names {
+                annotations.insert(name.to_string(), "value".to_string());
+            }
+            ::serde_json::to_string(&ReplayData::new(request, response, annotations)).unwrap()
+        };
+
+        assert_eq!(
+            data(&["X-B", "Content-Type", "X-A"]),
+            data(&["X-A", "X-B", "Content-Type"])
+        );
+    }
+
+    #[test]
+    fn replay_info() {
+        let client = ReplayClient::new(RecordingTarget::Dir("replay".into()));
+        let request = Request {
+            url: Url::parse("https://example.com/").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let response = Response {
+            url: request.url.clone(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from("first"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        let mut data = ReplayData::new(request.clone(), response.clone(), BTreeMap::new());
+        data.sequence.push(response.with_body("second"));
+        data.recorded_at = Some(1_500_000_000);
+
+        let first = client.next_response(&request, data.clone()).unwrap();
+        let info = first.replay_info().unwrap();
+        let fingerprint = format!("{:x}", request.fingerprint());
+        assert_eq!(info.path, Some(Path::new("replay").join(format!("{}.json", fingerprint))));
+        assert_eq!(info.fingerprint, fingerprint);
+        assert_eq!(info.index, 0);
+        assert_eq!(
+            info.recorded_at,
+            Some(::std::time::UNIX_EPOCH + Duration::from_secs(1_500_000_000))
+        );
+
+        let second = client.next_response(&request, data).unwrap();
+        assert_eq!(second.body, Bytes::from("second"));
+        assert_eq!(second.replay_info().unwrap().index, 1);
+    }
+
+    #[test]
+    fn rewrite_dates() {
+        let now = ::std::time::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir("replay".into()),
+            ReplaySettings {
+                rewrite_date: Some(DateRewrite::Fixed(now)),
+                ..ReplaySettings::default()
+            },
+        );
+
+        let mut response = Response {
+            url: Url::parse("https://example.com/").unwrap(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::new(),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        response.headers.set_raw("Date", "Sun, 06 Nov 1994 08:49:37 GMT");
+        response.headers.set_raw("Expires", "Sun, 06 Nov 1994 09:49:37 GMT");
+        client.rewrite_dates(&mut response);
+
+        use header_ext::HeadersExt;
+        assert_eq!(response.headers.get_str("Date"), Some("Fri, 14 Jul 2017 02:40:00 GMT"));
+        assert_eq!(response.headers.get_str("Expires"), Some("Fri, 14 Jul 2017 03:40:00 GMT"));
+    }
+
+    #[test]
+    fn request_body_policy() {
+        let client = |policy| {
+            ReplayClient::with_settings(
+                RecordingTarget::Dir("replay".into()),
+                ReplaySettings {
+                    request_body: policy,
+                    ..ReplaySettings::default()
+                },
+            )
+        };
+        let request = |body: &'static [u8]| {
+            Request {
+                url: Url::parse("https://example.com/upload").unwrap(),
+                method: Method::Post,
+                headers: Headers::new(),
+                body: Some(Bytes::from(body)),
+            }
+        };
+
+        let hash = client(RequestBodyPolicy::Hash);
+        let stored = hash.normalize(request(b"secret"));
+        assert!(!stored.body.as_ref().unwrap().windows(6).any(|w| w == b"secret"));
+        assert_eq!(stored, hash.normalize(request(b"secret")));
+        assert_ne!(stored, hash.normalize(request(b"other")));
+
+        let omit = client(RequestBodyPolicy::Omit);
+        assert_eq!(omit.normalize(request(b"secret")).body, None);
+    }
+
+    #[test]
+    fn decompress_request_body() {
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir("replay".into()),
+            ReplaySettings {
+                decompress_request_body: true,
+                ..ReplaySettings::default()
+            },
+        );
+        let mut request = Request {
+            url: Url::parse("https://example.com/upload").unwrap(),
+            method: Method::Post,
+            headers: Headers::new(),
+            body: Some(Compression::Deflate.compress(b"upload").unwrap()),
+        };
+        request.headers.set_raw("Content-Encoding", "deflate");
+
+        assert_eq!(client.normalize(request).body, Some(Bytes::from("upload")));
+    }
+
+    #[test]
+    fn finish() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_finish");
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir(dir.clone()),
+            ReplaySettings {
+                offline: true,
+                ..ReplaySettings::default()
+            },
+        );
+        let request = Request {
+            url: Url::parse("https://example.com/finish").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let response = Response {
+            url: request.url.clone(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from("body"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+
+        let session = client.start_session();
+        client
+            .store_data(&ReplayData::new(request.clone(), response, BTreeMap::new()))
+            .unwrap();
+        client.finish().unwrap();
+        assert!(client.replay_file_path(&request).exists());
+        drop(session);
+
+        let missing = Url::parse("https://example.com/missing").unwrap();
+        assert!(client.get(missing).send().is_err());
+        assert!(client.finish().is_err());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A client replaying and a client re-recording can share a directory.
+    #[test]
+    fn shared_dir() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_shared_dir");
+        let recording = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let offline = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        let request = Request {
+            url: Url::parse("https://example.com/shared").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let response = Response {
+            url: request.url.clone(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from("shared"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        let recorded = recording.normalize(request.clone());
+        recording
+            .store_data(&ReplayData::new(recorded, response, BTreeMap::new()))
+            .unwrap();
+
+        for client in &[&recording, &offline] {
+            let replayed = client.execute(None, request.clone()).unwrap();
+            assert_eq!(replayed.body, Bytes::from("shared"));
+        }
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Headers sent on multiple lines have to be replayed on multiple lines.
+    #[test]
+    fn replay_cookies() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_replay_cookies");
+        let client = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        let request = Request {
+            url: Url::parse("https://example.com/login").unwrap(),
+            method: Method::Post,
+            headers: Headers::new(),
+            body: None,
+        };
+        let mut response = Response {
+            url: request.url.clone(),
+            status: ::reqwest::StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::new(),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        response.headers.append_raw("Set-Cookie", "session=1; Path=/; HttpOnly");
+        response.headers.append_raw("Set-Cookie", "theme=dark; Max-Age=3600");
+        let recorded = client.normalize(request.clone());
+        client
+            .store_data(&ReplayData::new(recorded, response, BTreeMap::new()))
+            .unwrap();
+
+        let replayed = client.execute(None, request).unwrap();
+        let cookies = replayed.cookies();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].name, "session");
+        assert!(cookies[0].http_only());
+        assert_eq!(cookies[1].name, "theme");
+        assert_eq!(cookies[1].attribute("Max-Age"), Some("3600"));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignore_authorization_credentials() {
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir("replay".into()),
+            ReplaySettings {
+                ignore_authorization_credentials: true,
+                ..ReplaySettings::default()
+            },
+        );
+        let request = |authorization: &'static str| {
+            let mut headers = Headers::new();
+            headers.set_raw("Authorization", authorization);
+            client.normalize(Request {
+                url: Url::parse("https://example.com/").unwrap(),
+                method: Method::Get,
+                headers: headers,
+                body: None,
+            })
+        };
+
+        assert_eq!(request("Bearer abc"), request("Bearer xyz"));
+        assert_ne!(request("Bearer abc"), request("Basic YWxhZGRpbg=="));
+    }
+
+    #[test]
+    fn default_headers() {
+        let mut config = ClientConfig::default();
+        config.user_agent = Some("app/1.0".to_string());
+        config.default_headers.insert("X-Tenant".to_string(), "acme".to_string());
+        config.default_headers.insert("X-Env".to_string(), "test".to_string());
+
+        let mut headers = Headers::new();
+        headers.set_raw("User-Agent", "app/1.0");
+        headers.set_raw("X-Tenant", "acme");
+        headers.set_raw("X-Env", "staging");
+        let request = Request {
+            url: Url::parse("https://example.com/").unwrap(),
+            method: Method::Get,
+            headers: headers,
+            body: None,
+        };
+
+        assert_eq!(
+            super::default_headers(&config, &request),
+            vec!["User-Agent".to_string(), "X-Tenant".to_string()]
+        );
+    }
+
+    #[test]
+    fn render_template() {
+        let request = Request {
+            url: Url::parse("https://example.com/api/users?page=2").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+
+        assert_eq!(
+            super::render_template("{host}/{port}/{method}_{path}.json", &request),
+            "example.com/443/GET_api_users.json"
+        );
+        assert_eq!(
+            super::render_template("{fingerprint}.json", &request),
+            format!("{:x}.json", request.fingerprint())
+        );
+    }
+
+    /// A broken replay file is a fixture problem, unlike a broken response body.
+    #[test]
+    fn invalid_replay_file() {
+        use error::ErrorCategory;
+
+        let file = ::std::env::temp_dir().join("reqwest_mock_invalid_replay_file.json");
+        File::create(&file).unwrap().write_all(b"{ not json").unwrap();
+        let client = ReplayClient::new(RecordingTarget::File(file.clone()));
+        let request = Request {
+            url: Url::parse("https://example.com/broken").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+
+        let err = client.execute(None, request).unwrap_err();
+        assert_eq!(err.category(), ErrorCategory::Fixture);
+        ::std::fs::remove_file(file).unwrap();
+    }
+
+    /// New requests are recorded through the provided client, which doesn't follow redirects.
+    #[test]
+    fn with_client() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_with_client");
+        let mut builder = ::reqwest::Client::builder().unwrap();
+        builder.redirect(::reqwest::RedirectPolicy::none());
+        let client = ReplayClient::with_client(
+            RecordingTarget::Dir(dir.clone()),
+            builder.build().unwrap(),
+        );
+        let (base_url, server) =
+            serve(&["HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n"]);
+        let url = format!("{}/a", base_url);
+
+        assert_eq!(client.get(&url[..]).send().unwrap().status, StatusCode::Found);
+        server.join().unwrap();
+
+        let offline = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        assert_eq!(offline.get(&url[..]).send().unwrap().status, StatusCode::Found);
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn annotate() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_annotate");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let (base_url, server) = serve(&["HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"]);
+        let url = format!("{}/annotated", base_url);
+
+        client.annotate("reason", "documents the endpoint");
+        client.get(&url[..]).send().unwrap();
+        server.join().unwrap();
+
+        let file = replay_file(&client, get(&url));
+        assert_eq!(file["annotations"]["reason"], "documents the endpoint");
+        // Annotations only apply to the next request.
+        assert!(client.annotations.lock().unwrap().is_empty());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Re-recorded responses are replayed in sequence, identical ones aren't recorded twice.
+    #[test]
+    fn rerecord_sequence() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_rerecord_sequence");
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir(dir.clone()),
+            ReplaySettings {
+                offline: false,
+                rerecord: RerecordMode::Sequence,
+                ..ReplaySettings::default()
+            },
+        );
+        let (base_url, server) = serve(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none",
+                "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+                "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+            ],
+        );
+        let url = format!("{}/sequence", base_url);
+
+        for _ in 0..3 {
+            client.force_record_next();
+            client.get(&url[..]).send().unwrap();
+        }
+        server.join().unwrap();
+        let file = replay_file(&client, get(&url));
+        assert_eq!(file["sequence"].as_array().unwrap().len(), 1);
+
+        let offline = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        for expected in &["one", "two", "two"] {
+            let response = offline.get(&url[..]).send().unwrap();
+            assert_eq!(response.body_to_utf8().unwrap(), *expected);
+        }
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Requests recorded during a session are only written once it is committed.
+    #[test]
+    fn recording_session() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_recording_session");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let committed = get("https://example.com/committed");
+        let aborted = get("https://example.com/aborted");
+
+        let session = client.start_session();
+        let data = ReplayData::new(committed.clone(), ok(&committed, "ok"), BTreeMap::new());
+        client.store_data(&data).unwrap();
+        assert!(!client.replay_file_path(&committed).exists());
+        // Buffered requests are replayed already.
+        assert_eq!(client.execute(None, committed.clone()).unwrap().body, "ok");
+        session.commit().unwrap();
+        assert!(client.replay_file_path(&committed).exists());
+
+        let session = client.start_session();
+        let data = ReplayData::new(aborted.clone(), ok(&aborted, "ok"), BTreeMap::new());
+        client.store_data(&data).unwrap();
+        session.abort();
+        assert!(!client.replay_file_path(&aborted).exists());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn from_static() {
+        let embedded = |format_version: u8| -> &'static [u8] {
+            let files: Vec<Value> = ["a", "b"]
+                .iter()
+                .map(|path| {
+                    let url = format!("https://example.com/{}", path);
+                    json!({
+                        "request": {"url": url, "method": "GET", "headers": {}},
+                        "response": {
+                            "url": url,
+                            "status": 200,
+                            "headers": {},
+                            "body": path,
+                            "body_format": "text"
+                        },
+                        "format_version": format_version
+                    })
+                })
+                .collect();
+            Box::leak(::serde_json::to_vec(&files).unwrap().into_boxed_slice())
+        };
+
+        let client = ReplayClient::from_static(embedded(FORMAT_VERSION)).unwrap();
+        for path in &["a", "b"] {
+            let url = format!("https://example.com/{}", path);
+            assert_eq!(client.get(&url[..]).send().unwrap().body, *path);
+        }
+        match *client.get("https://example.com/c").send().unwrap_err().kind() {
+            ErrorKind::Unmatched(..) => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+
+        let err = ReplayClient::from_static(embedded(FORMAT_VERSION - 1)).err().unwrap();
+        assert!(err.is_fixture_problem());
+    }
+
+    /// Overrides alter replayed responses without touching the replay files.
+    #[test]
+    fn override_response() {
+        use header_ext::HeadersExt;
+
+        let dir = ::std::env::temp_dir().join("reqwest_mock_override_response");
+        let client = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        let users = get("https://example.com/users");
+        let groups = get("https://example.com/groups");
+        for request in &[&users, &groups] {
+            let data = ReplayData::new((*request).clone(), ok(request, "[]"), BTreeMap::new());
+            client.store_data(&data).unwrap();
+        }
+
+        client.override_response(|req| req.url.path() == "/users", |resp| {
+            resp.with_status(StatusCode::InternalServerError)
+                .with_header("Retry-After", "1")
+                .with_body("failed")
+        });
+        let response = client.execute(None, users.clone()).unwrap();
+        assert_eq!(response.status, StatusCode::InternalServerError);
+        assert_eq!(response.headers.get_str("Retry-After"), Some("1"));
+        assert_eq!(response.body, "failed");
+        assert_eq!(client.execute(None, groups).unwrap().body, "[]");
+
+        let other = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        assert_eq!(other.execute(None, users).unwrap().status, StatusCode::Ok);
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Requests of shared replay files are replayed, whether included by the replay file or
+    /// explicitly.
+    #[test]
+    fn include() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_include");
+        let login = get("https://example.com/login");
+        let shared = ReplayClient::recording(RecordingTarget::File(dir.join("common/auth.json")));
+        let data = ReplayData::new(login.clone(), ok(&login, "token"), BTreeMap::new());
+        shared.store_data(&data).unwrap();
+
+        let users = get("https://example.com/users");
+        let file = dir.join("users.json");
+        let mut data = ReplayData::new(users.clone(), ok(&users, "[]"), BTreeMap::new());
+        data.include = vec!["common/auth.json".to_string()];
+        let recording = ReplayClient::recording(RecordingTarget::File(file.clone()));
+        recording.store_data(&data).unwrap();
+
+        let client = ReplayClient::new(RecordingTarget::File(file));
+        assert_eq!(client.execute(None, users).unwrap().body, "[]");
+        assert_eq!(client.execute(None, login.clone()).unwrap().body, "token");
+
+        let client = ReplayClient::new(RecordingTarget::Dir(dir.join("other")));
+        client.include(dir.join("common/auth.json")).unwrap();
+        assert_eq!(client.execute(None, login).unwrap().body, "token");
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// The elapsed time of live requests is recorded and replayed, but not compared.
+    #[test]
+    fn elapsed() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_elapsed");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let (base_url, server) = serve(&["HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"]);
+        let url = format!("{}/elapsed", base_url);
+
+        let session = client.start_session();
+        let live = client.get(&url[..]).send().unwrap();
+        server.join().unwrap();
+        assert_eq!(Some(session.elapsed()), live.elapsed);
+        session.commit().unwrap();
+
+        let offline = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        let replayed = offline.get(&url[..]).send().unwrap();
+        assert!(replayed.elapsed.is_some());
+        assert_eq!(replayed.body, live.body);
+
+        let mut unmeasured = live.clone();
+        unmeasured.elapsed = None;
+        assert_eq!(unmeasured, live);
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A `Content-Length` edited out of sync with the body is handled according to the policy.
+    #[test]
+    fn content_length() {
+        use header_ext::HeadersExt;
+
+        let dir = ::std::env::temp_dir().join("reqwest_mock_content_length");
+        let client = |policy: ContentLengthPolicy| {
+            ReplayClient::with_settings(
+                RecordingTarget::Dir(dir.clone()),
+                ReplaySettings {
+                    content_length: policy,
+                    ..ReplaySettings::default()
+                },
+            )
+        };
+        let request = get("https://example.com/edited");
+        let mut head = request.clone();
+        head.method = Method::Head;
+        for request in &[&request, &head] {
+            let response = ok(request, "ok").with_header("Content-Length", "10");
+            let data = ReplayData::new((*request).clone(), response, BTreeMap::new());
+            client(ContentLengthPolicy::Ignore).store_data(&data).unwrap();
+        }
+
+        let replayed = client(ContentLengthPolicy::Ignore).execute(None, request.clone());
+        assert_eq!(replayed.unwrap().headers.content_length(), Some(10));
+        let replayed = client(ContentLengthPolicy::Fix).execute(None, request.clone());
+        assert_eq!(replayed.unwrap().headers.content_length(), Some(2));
+        let err = client(ContentLengthPolicy::Error).execute(None, request).unwrap_err();
+        assert!(err.is_fixture_problem());
+
+        // The Content-Length of responses to HEAD requests refers to a body which isn't sent.
+        let replayed = client(ContentLengthPolicy::Error).execute(None, head);
+        assert_eq!(replayed.unwrap().headers.content_length(), Some(10));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hop_by_hop_headers() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_hop_by_hop_headers");
+        let client = |keep: bool| {
+            ReplayClient::with_settings(
+                RecordingTarget::Dir(dir.clone()),
+                ReplaySettings {
+                    keep_hop_by_hop_headers: keep,
+                    ..ReplaySettings::default()
+                },
+            )
+        };
+        let request = get("https://example.com/chunked");
+        let response = ok(&request, "ok")
+            .with_header("Transfer-Encoding", "chunked")
+            .with_header("Connection", "keep-alive")
+            .with_header("X-Request-Id", "1");
+        let data = ReplayData::new(request.clone(), response, BTreeMap::new());
+        client(false).store_data(&data).unwrap();
+
+        let stripped = client(false).execute(None, request.clone()).unwrap();
+        assert!(stripped.headers.get_raw("Transfer-Encoding").is_none());
+        assert!(stripped.headers.get_raw("Connection").is_none());
+        assert!(stripped.headers.get_raw("X-Request-Id").is_some());
+
+        let kept = client(true).execute(None, request).unwrap();
+        assert!(kept.headers.get_raw("Transfer-Encoding").is_some());
+        assert!(kept.headers.get_raw("Connection").is_some());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignore_user_agent() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_ignore_user_agent");
+        let client = ReplayClient::with_settings(
+            RecordingTarget::Dir(dir.clone()),
+            ReplaySettings {
+                ignore_user_agent: true,
+                ..ReplaySettings::default()
+            },
+        );
+        let request = get("https://example.com/agent");
+        let data = ReplayData::new(request.clone(), ok(&request, "ok"), BTreeMap::new());
+        client.store_data(&data).unwrap();
+
+        let mut upgraded = request;
+        upgraded.headers.set_raw("User-Agent", "my-app/2.0");
+        assert_eq!(client.execute(None, upgraded).unwrap().body, "ok");
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn max_body_size() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_max_body_size");
+        let client = |limit| {
+            ReplayClient::with_settings(
+                RecordingTarget::Dir(dir.clone()),
+                ReplaySettings {
+                    max_body_size: Some(limit),
+                    ..ReplaySettings::default()
+                },
+            )
+        };
+        let (base_url, server) = serve(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\ntruncated",
+                "HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nrejected",
+            ],
+        );
+        let truncated = format!("{}/truncated", base_url);
+        let rejected = format!("{}/rejected", base_url);
+
+        let recording = client(BodyLimit::Truncate(4));
+        // The caller still sees the whole body.
+        assert_eq!(recording.get(&truncated[..]).send().unwrap().body, "truncated");
+        let file = replay_file(&recording, get(&truncated));
+        assert_eq!(file["annotations"]["reqwest_mock.truncated_body"], "9 bytes");
+        let offline = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        assert_eq!(offline.get(&truncated[..]).send().unwrap().body, "trun");
+
+        let recording = client(BodyLimit::Error(4));
+        assert!(recording.get(&rejected[..]).send().is_err());
+        server.join().unwrap();
+        let path = recording.replay_file_path(&recording.normalize(get(&rejected)));
+        assert!(!path.exists());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dropped without calling `commit()` or `abort()`")]
+    fn recording_session_dropped() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_recording_session_dropped");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir));
+        let request = get("https://example.com/dropped");
+
+        // Sessions without any recorded requests can be dropped.
+        drop(client.start_session());
+
+        let _session = client.start_session();
+        let data = ReplayData::new(request.clone(), ok(&request, "ok"), BTreeMap::new());
+        client.store_data(&data).unwrap();
+    }
+
+    #[test]
+    fn context() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_context");
+        let client = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let (base_url, server) = serve(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            ],
+        );
+        let implicit = format!("{}/implicit", base_url);
+        let explicit = format!("{}/explicit", base_url);
+
+        // The test harness names the thread after the test.
+        client.get(&implicit[..]).send().unwrap();
+        client.set_context("users can log in");
+        client.get(&explicit[..]).send().unwrap();
+        server.join().unwrap();
+
+        let file = replay_file(&client, get(&implicit));
+        assert_eq!(
+            file["annotations"]["reqwest_mock.context"],
+            "client::replay::tests::context"
+        );
+        let file = replay_file(&client, get(&explicit));
+        assert_eq!(file["annotations"]["reqwest_mock.context"], "users can log in");
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn journal() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_journal");
+        let client = || {
+            ReplayClient::with_settings(
+                RecordingTarget::Dir(dir.clone()),
+                ReplaySettings {
+                    journal: true,
+                    ..ReplaySettings::default()
+                },
+            )
+        };
+        let committed = get("https://example.com/committed");
+        let crashed = get("https://example.com/crashed");
+        let journal = dir.join("journal.jsonl");
+
+        let recording = client();
+        let session = recording.start_session();
+        let data = ReplayData::new(committed.clone(), ok(&committed, "ok"), BTreeMap::new());
+        recording.store_data(&data).unwrap();
+        assert!(journal.exists());
+        session.commit().unwrap();
+        assert!(!journal.exists());
+
+        // Simulate a test crashing in the middle of writing the second entry.
+        let recording = client();
+        let session = recording.start_session();
+        let data = ReplayData::new(crashed.clone(), ok(&crashed, "ok"), BTreeMap::new());
+        recording.store_data(&data).unwrap();
+        ::std::mem::forget(session);
+        let mut f = OpenOptions::new().append(true).open(&journal).unwrap();
+        f.write_all(b"{\"request\": {").unwrap();
+
+        let recovering = client();
+        assert_eq!(recovering.compact_journal().unwrap(), 1);
+        assert!(!journal.exists());
+        assert!(recovering.replay_file_path(&crashed).exists());
+        assert_eq!(recovering.compact_journal().unwrap(), 0);
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn group() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_group");
+        let recording = ReplayClient::recording(RecordingTarget::Dir(dir.clone()));
+        let (base_url, server) = serve(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            ],
+        );
+        let initiate = format!("{}/initiate", base_url);
+        let part = format!("{}/part", base_url);
+
+        let upload = recording.group("upload");
+        recording.get(&initiate[..]).send().unwrap();
+        let parts = recording.group("parts");
+        recording.get(&part[..]).send().unwrap();
+        parts.finish().unwrap();
+        upload.finish().unwrap();
+        server.join().unwrap();
+        let file = replay_file(&recording, get(&initiate));
+        assert_eq!(file["annotations"]["reqwest_mock.group"], "upload");
+        let file = replay_file(&recording, get(&part));
+        assert_eq!(file["annotations"]["reqwest_mock.group"], "upload/parts");
+
+        // Replaying only part of the operation fails the verification.
+        let replaying = ReplayClient::new(RecordingTarget::Dir(dir.clone()));
+        let upload = replaying.group("upload");
+        replaying.get(&initiate[..]).send().unwrap();
+        let error = upload.finish().unwrap_err();
+        assert!(error.to_string().contains("/part"));
+        assert!(replaying.group_path().is_none());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Detection of replay clients in the same process using the same replay files in conflicting
+//! ways, which would otherwise silently corrupt them.
+
+use client::replay::{RecordingTarget, ReplaySettings};
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How a client uses its replay files.
+#[derive(Clone, Debug, PartialEq)]
+struct Usage {
+    id: usize,
+    path: PathBuf,
+
+    /// Whether the path is a single replay file.
+    file: bool,
+    offline: bool,
+    matcher: String,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static REGISTRY: Mutex<Vec<Usage>> = Mutex::new(Vec::new());
+
+/// Registers a client with the target and settings, returns its id and a description of the
+/// conflict with a client registered before, if any.
+///
+/// Clients conflict if one uses the path as a replay file and the other as a directory, or if
+/// both record but match requests differently, as they would record the same requests
+/// differently. Offline clients only read the replay files, so they never conflict with clients
+/// using the same directory otherwise.
+pub(super) fn register(
+    target: &RecordingTarget,
+    settings: &ReplaySettings,
+) -> (usize, Option<String>) {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let (path, file) = match *target {
+        RecordingTarget::File(ref file) => (file.clone(), true),
+        RecordingTarget::Dir(ref dir) |
+        RecordingTarget::TemplatedDir(ref dir, _) => (dir.clone(), false),
+        RecordingTarget::Static(_) => return (id, None),
+    };
+    let usage = Usage {
+        id: id,
+        path: normalize(&path),
+        file: file,
+        offline: settings.offline,
+        matcher: settings.matcher_fingerprint(),
+    };
+
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let conflict = registry
+        .iter()
+        .filter(|other| other.path == usage.path)
+        .filter_map(|other| conflict(other, &usage))
+        .next();
+    registry.push(usage);
+    (id, conflict)
+}
+
+/// Returns the absolute form of the path, so different spellings of the same path are detected
+/// as the same, e.g. `replay` and `./replay` or `../crate/replay`.
+///
+/// `.` and `..` are resolved lexically and symlinks by canonicalizing the longest part of the
+/// path which exists, as the replay files themselves might not have been created yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut absolute = env::current_dir().unwrap_or_default();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component.as_os_str()),
+        }
+    }
+
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return absolute.clone(),
+        }
+    }
+    let mut normalized = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+    normalized.extend(missing.iter().rev());
+    normalized
+}
+
+/// Removes the client from the registry.
+pub(super) fn unregister(id: usize) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    registry.retain(|usage| usage.id != id);
+}
+
+fn conflict(other: &Usage, usage: &Usage) -> Option<String> {
+    let reason = if other.file != usage.file {
+        "one as a replay file and the other as a directory"
+    } else if !other.offline && !usage.offline && other.matcher != usage.matcher {
+        "both recording with different settings for matching requests"
+    } else {
+        return None;
+    };
+    Some(format!(
+        "another ReplayClient of this process uses {} as well, {}",
+        usage.path.display(),
+        reason
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register() {
+        let target = RecordingTarget::dir("replay/registry");
+        let recording = ReplaySettings {
+            offline: false,
+            ..ReplaySettings::default()
+        };
+
+        let (first, conflict) = super::register(&target, &recording);
+        assert_eq!(conflict, None);
+        let (second, conflict) = super::register(&target, &recording);
+        assert_eq!(conflict, None);
+        let ignore_scheme = ReplaySettings {
+            ignore_scheme: true,
+            ..recording.clone()
+        };
+        let (third, conflict) = super::register(&target, &ignore_scheme);
+        assert!(conflict.unwrap().contains("matching"));
+
+        for id in &[first, second, third] {
+            unregister(*id);
+        }
+    }
+
+    /// Tests sharing a fixtures directory commonly replay in one place while re-recording in
+    /// another, possibly with different settings.
+    #[test]
+    fn offline_and_recording() {
+        let target = RecordingTarget::dir("replay/registry_shared");
+        let offline = ReplaySettings {
+            ignore_scheme: true,
+            ..ReplaySettings::default()
+        };
+        let recording = ReplaySettings {
+            offline: false,
+            ..ReplaySettings::default()
+        };
+
+        let (first, conflict) = super::register(&target, &offline);
+        assert_eq!(conflict, None);
+        let (second, conflict) = super::register(&target, &recording);
+        assert_eq!(conflict, None);
+        unregister(first);
+        unregister(second);
+    }
+
+    #[test]
+    fn normalize() {
+        let dir = Path::new("replay/normalize");
+        assert_eq!(super::normalize(dir), super::normalize(Path::new("./replay/normalize")));
+        assert_eq!(
+            super::normalize(dir),
+            super::normalize(Path::new("replay/other/../normalize/"))
+        );
+        assert!(super::normalize(dir).is_absolute());
+
+        let settings = ReplaySettings::default();
+        let (first, _) = super::register(&RecordingTarget::file("./replay/n.json"), &settings);
+        let (second, conflict) = super::register(&RecordingTarget::dir("replay/n.json"), &settings);
+        assert!(conflict.unwrap().contains("directory"));
+        unregister(first);
+        unregister(second);
+    }
+}
@@ -0,0 +1,341 @@
+use super::scrub::IdScrubbing;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+use twox_hash::XxHash;
+
+/// Define what happens when a request, which was already recorded, is recorded again. This is
+/// the case after calling `ReplayClient::force_record_next`.
+///
+/// Either way, if the new response is identical to an already recorded one the replay file is
+/// left untouched.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RerecordMode {
+    /// The recorded response is replaced by the new one.
+    Replace,
+
+    /// The new response is appended to the recorded ones, which are then replayed in sequence,
+    /// repeating the last one once all were replayed.
+    Sequence,
+}
+
+/// Define what happens when the `Content-Length` header of a replayed response doesn't match the
+/// length of its body, which commonly happens after editing a replay file by hand.
+///
+/// Responses to `HEAD` requests and `304 Not Modified` responses are never checked, as their
+/// `Content-Length` refers to a body which isn't sent.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ContentLengthPolicy {
+    /// The header is replayed as recorded.
+    Ignore,
+
+    /// The header is set to the length of the body.
+    Fix,
+
+    /// Replaying the response results in an error.
+    Error,
+}
+
+/// Defines which headers of recorded responses are written to the replay files, which keeps
+/// replay files stable when servers send headers changing with every response.
+///
+/// Header names are compared case-insensitively.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderPolicy {
+    /// Remove hop-by-hop headers like `Transfer-Encoding` and `Connection`.
+    pub strip_hop_by_hop: bool,
+
+    /// Headers which are never recorded.
+    pub deny: Vec<String>,
+
+    /// If set, only these headers are recorded.
+    pub allow: Option<Vec<String>>,
+}
+
+impl HeaderPolicy {
+    /// Record all headers as they were received, which is the default.
+    pub fn raw() -> Self {
+        HeaderPolicy {
+            strip_hop_by_hop: false,
+            deny: Vec::new(),
+            allow: None,
+        }
+    }
+
+    /// Strip hop-by-hop headers as well as `Date`, `Server`, `X-Request-Id` and similar headers
+    /// which change with every response.
+    pub fn stable() -> Self {
+        HeaderPolicy {
+            strip_hop_by_hop: true,
+            deny: ["Date", "Server", "X-Request-Id", "X-Correlation-Id", "X-Amzn-Trace-Id",
+                   "X-Runtime", "Age", "Expires", "Set-Cookie"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+            allow: None,
+        }
+    }
+
+    /// Returns true if the header with this name should be recorded.
+    pub fn records(&self, name: &str) -> bool {
+        let contains = |list: &[String]| list.iter().any(|h| h.eq_ignore_ascii_case(name));
+
+        let hop_by_hop = super::HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name));
+        if self.strip_hop_by_hop && hop_by_hop {
+            return false;
+        }
+        if contains(&self.deny) {
+            return false;
+        }
+        self.allow.as_ref().map_or(true, |allow| contains(allow))
+    }
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        HeaderPolicy::raw()
+    }
+}
+
+/// Limits the size of recorded response bodies, so a test accidentally downloading a huge file
+/// doesn't end up writing a huge replay file. The size is given in bytes.
+///
+/// The response returned to the caller is never affected.
+///
+/// TODO: Storing oversized bodies outside of the replay file would require a format for
+///       referencing external files.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BodyLimit {
+    /// Record only the beginning of the body, annotating the replay file with
+    /// `reqwest_mock.truncated_body` holding the original size.
+    Truncate(usize),
+
+    /// Don't record the response, returning an `ErrorKind::BodyTooLarge` error instead. The
+    /// body is only read up to the limit.
+    Error(usize),
+}
+
+/// Defines what the `Date` header of replayed responses is rewritten to, so code checking the
+/// freshness of responses doesn't reject responses recorded a long time ago.
+///
+/// The `Expires` and `Last-Modified` headers are shifted by the same amount, so they stay
+/// consistent with the `Date` header.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DateRewrite {
+    /// The current time.
+    Now,
+
+    /// A fixed point in time.
+    Fixed(SystemTime),
+}
+
+/// Defines how request bodies are stored in replay files, for requests with huge or sensitive
+/// bodies.
+///
+/// Requests are matched against the stored form of the body, so replaying works either way.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum RequestBodyPolicy {
+    /// Store the body as it is, which is the default.
+    Record,
+
+    /// Store only a digest of the body, requests still only match if their body is identical.
+    Hash,
+
+    /// Don't store the body at all, requests match regardless of their body.
+    Omit,
+}
+
+/// How the bodies of recorded responses are stored, see `ReplaySettings::body_formats`.
+///
+/// Whatever the format, bodies are always replayed byte for byte as they were recorded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BodyFormat {
+    /// Base64 encoded, the default.
+    Base64,
+
+    /// As text, bodies which aren't valid UTF-8 are stored base64 encoded.
+    Text,
+
+    /// As JSON embedded into the replay file, which is written pretty-printed. Bodies which
+    /// wouldn't be restored byte for byte that way, e.g. because of their whitespace or the order
+    /// of their keys, are stored base64 encoded with the JSON rendered alongside.
+    Json,
+
+    /// Base64 encoded, with the fields of the protobuf message rendered alongside. As the schema
+    /// isn't known, fields are listed by their number.
+    Protobuf,
+}
+
+/// Some settings for the `ReplayClient`.
+///
+/// Missing fields take their default value when deserializing, see `ReplayConfig`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplaySettings {
+    /// Specifies what happens when an already recorded request is recorded again.
+    pub rerecord: RerecordMode,
+
+    /// If set and a request wasn't recorded, the most similar recorded request is replayed
+    /// instead, as long as its similarity in percent reaches this threshold.
+    ///
+    /// The similarity is based on the url, the headers and the body (comparing individual fields
+    /// of JSON bodies), so recordings keep working while an API adds benign fields. Fuzzy
+    /// matches are logged at the info level.
+    ///
+    /// The `Vary` header of recorded responses is honored, i. e. the request headers it names
+    /// have to be equal for a recorded request to be considered at all.
+    pub fuzzy_threshold: Option<u8>,
+
+    /// If set, the `Date` header of replayed responses is rewritten, default is `None`.
+    pub rewrite_date: Option<DateRewrite>,
+
+    /// Specifies how mismatches between the `Content-Length` header and the body of replayed
+    /// responses are handled.
+    pub content_length: ContentLengthPolicy,
+
+    /// Replay hop-by-hop headers like `Transfer-Encoding` and `Connection` as recorded. By default
+    /// they are removed from replayed responses, as their body is always replayed contiguously
+    /// and reqwest wouldn't expose them either. Only useful for low-level testing.
+    pub keep_hop_by_hop_headers: bool,
+
+    /// Specifies which headers of responses are recorded.
+    pub record_headers: HeaderPolicy,
+
+    /// Never perform live requests, requests which weren't recorded result in an
+    /// `ErrorKind::NetworkAccess` error instead, which guarantees tests are hermetic.
+    ///
+    /// Default is `true`, set it to `false` (or use `ReplayClient::recording`) to record
+    /// requests which weren't recorded yet.
+    pub offline: bool,
+
+    /// Remove the `User-Agent` header from requests before matching and recording them, so
+    /// version bumps of the application don't invalidate all replay files.
+    pub ignore_user_agent: bool,
+
+    /// Match `Authorization` headers as equal if they use the same scheme (e.g. `Bearer` or
+    /// `Basic`), regardless of the credentials. Only the scheme is recorded, so tokens which are
+    /// refreshed between runs neither invalidate replay files nor end up in them.
+    pub ignore_authorization_credentials: bool,
+
+    /// If set, ids in the path and JSON body of requests are replaced by placeholders before
+    /// they are matched and recorded. Default is `None`.
+    pub scrub_ids: Option<IdScrubbing>,
+
+    /// Match `http://` and `https://` urls as equal, recording them as `https://`. Useful when
+    /// recording through a TLS-terminating proxy.
+    ///
+    /// Explicit default ports are always ignored, e.g. `https://host:443/` matches
+    /// `https://host/`.
+    pub ignore_scheme: bool,
+
+    /// Limit for the size of recorded response bodies, default is no limit.
+    pub max_body_size: Option<BodyLimit>,
+
+    /// Specifies how request bodies are stored.
+    pub request_body: RequestBodyPolicy,
+
+    /// Append the requests recorded during a `RecordingSession` to a journal file right away, so
+    /// they survive a crash of the test. The journal is removed once the session is committed or
+    /// aborted, see `ReplayClient::compact_journal` for recovering its requests otherwise.
+    pub journal: bool,
+
+    /// Treat requests as unrecorded if their replay file was recorded with different matching
+    /// settings, so they are recorded again. By default only a warning is logged.
+    ///
+    /// See `ReplaySettings::matcher_fingerprint` for the settings which are considered.
+    pub rerecord_on_matcher_change: bool,
+
+    /// Decompress request bodies with a supported `Content-Encoding` (see `Compression`) before
+    /// matching and recording them, so requests match regardless of how their body was
+    /// compressed and replay files contain readable bodies.
+    pub decompress_request_body: bool,
+
+    /// Formats for storing the bodies of responses, by their media type (e.g.
+    /// `application/json`). Bodies of other media types are stored base64 encoded.
+    pub body_formats: BTreeMap<String, BodyFormat>,
+}
+
+impl ReplaySettings {
+    /// Returns a fingerprint of the settings affecting which requests are matched, i.e.
+    /// `fuzzy_threshold`, `ignore_user_agent`, `ignore_authorization_credentials`, `scrub_ids`,
+    /// `ignore_scheme`, `request_body` and `decompress_request_body`. It is stored in every
+    /// replay file, so replay files recorded with other settings can be detected.
+    pub fn matcher_fingerprint(&self) -> String {
+        let mut hasher = XxHash::with_seed(42);
+        self.fuzzy_threshold.hash(&mut hasher);
+        self.ignore_user_agent.hash(&mut hasher);
+        self.ignore_authorization_credentials.hash(&mut hasher);
+        self.scrub_ids.hash(&mut hasher);
+        self.ignore_scheme.hash(&mut hasher);
+        self.request_body.hash(&mut hasher);
+        // Only hashed if enabled, so the fingerprints of existing replay files stay the same.
+        if self.decompress_request_body {
+            self.decompress_request_body.hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        ReplaySettings {
+            rerecord: RerecordMode::Replace,
+            fuzzy_threshold: None,
+            rewrite_date: None,
+            content_length: ContentLengthPolicy::Fix,
+            keep_hop_by_hop_headers: false,
+            record_headers: HeaderPolicy::default(),
+            offline: true,
+            ignore_user_agent: false,
+            ignore_authorization_credentials: false,
+            scrub_ids: None,
+            ignore_scheme: false,
+            max_body_size: None,
+            request_body: RequestBodyPolicy::Record,
+            journal: false,
+            rerecord_on_matcher_change: false,
+            decompress_request_body: false,
+            body_formats: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_policy() {
+        let raw = HeaderPolicy::raw();
+        assert!(raw.records("Date"));
+        assert!(raw.records("Transfer-Encoding"));
+
+        let stable = HeaderPolicy::stable();
+        assert!(!stable.records("date"));
+        assert!(!stable.records("Transfer-Encoding"));
+        assert!(stable.records("Content-Type"));
+
+        let allow = HeaderPolicy {
+            allow: Some(vec!["Content-Type".to_string()]),
+            ..HeaderPolicy::raw()
+        };
+        assert!(allow.records("content-type"));
+        assert!(!allow.records("Date"));
+    }
+
+    #[test]
+    fn matcher_fingerprint() {
+        let default = ReplaySettings::default();
+        let recording = ReplaySettings {
+            offline: false,
+            ..ReplaySettings::default()
+        };
+        let ignore_scheme = ReplaySettings {
+            ignore_scheme: true,
+            ..ReplaySettings::default()
+        };
+        assert_eq!(default.matcher_fingerprint(), recording.matcher_fingerprint());
+        assert_ne!(default.matcher_fingerprint(), ignore_scheme.matcher_fingerprint());
+    }
+}
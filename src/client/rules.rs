@@ -0,0 +1,153 @@
+use client::Client;
+use config::ClientConfig;
+use error::{Error, ErrorKind};
+use request::Request;
+use response::Response;
+use std::io::Read;
+
+struct RequestRule {
+    name: String,
+    check: Box<dyn Fn(&Request) -> bool + Send + Sync>,
+}
+
+/// Wraps another `Client` and checks every request against rules before executing it.
+///
+/// Teams can use this to enforce their API standards in tests, e.g. that every request carries a
+/// request id. Requests violating a rule aren't executed, instead an `ErrorKind::Validation`
+/// error naming the rule is returned.
+///
+/// # Examples
+///
+/// ```
+/// use reqwest_mock::{Client, RuleClient, StubClient, StubSettings};
+///
+/// let client = RuleClient::new(StubClient::new(StubSettings::default()))
+///     .require_https()
+///     .require_header("X-Request-Id");
+///
+/// assert!(client.get("http://example.com/").send().is_err());
+/// ```
+pub struct RuleClient<C: Client> {
+    inner: C,
+    rules: Vec<RequestRule>,
+}
+
+impl<C: Client> RuleClient<C> {
+    /// Create a new `RuleClient` wrapping the provided client, without any rules yet.
+    pub fn new(inner: C) -> Self {
+        RuleClient {
+            inner: inner,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Add a rule, requests for which `check` returns false are rejected.
+    pub fn rule<N, F>(mut self, name: N, check: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(RequestRule {
+            name: name.into(),
+            check: Box::new(check),
+        });
+        self
+    }
+
+    /// Reject requests without the header.
+    pub fn require_header(self, header: &str) -> Self {
+        let header = header.to_string();
+        let name = format!("requests must have the {} header", header);
+        self.rule(name, move |r| r.headers.get_raw(&header).is_some())
+    }
+
+    /// Reject requests to `http://` urls.
+    pub fn require_https(self) -> Self {
+        self.rule("requests must use https", |r| r.url.scheme() == "https")
+    }
+
+    /// Returns the wrapped client.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Checks the request against all rules. The body of streamed requests isn't available to
+    /// the rules, as it would have to be buffered.
+    fn check(&self, request: &Request) -> Result<(), Error> {
+        match self.rules.iter().find(|r| !(r.check)(request)) {
+            Some(rule) => Err(
+                ErrorKind::Validation(rule.name.clone(), request.url.to_string()).into(),
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<C: Client> Client for RuleClient<C> {
+    fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        self.check(&request)?;
+        self.inner.execute(config, request)
+    }
+
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+        body: Box<dyn Read + Send>,
+    ) -> Result<Response, Error> {
+        self.check(&request)?;
+        self.inner.execute_streaming(config, request, body)
+    }
+
+    fn config(&self) -> &ClientConfig {
+        self.inner.config()
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        self.inner.config_mut()
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.inner.requests()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{StubClient, StubSettings, StubStrictness};
+    use reqwest::Url;
+    use reqwest::header::Headers;
+
+    #[test]
+    fn rules() {
+        let mut inner = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("https://example.com/").unwrap();
+        inner.stub(url.clone()).response().body("ok").mock();
+        let client = RuleClient::new(inner).require_https().require_header("X-Request-Id");
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Request-Id", "1");
+        let response = client.get(url.clone()).headers(headers.clone()).send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "ok");
+
+        let violated = |result: Result<Response, Error>| match *result.unwrap_err().kind() {
+            ErrorKind::Validation(ref rule, _) => rule.clone(),
+            ref kind => panic!("unexpected error: {}", kind),
+        };
+        assert_eq!(
+            violated(client.get("http://example.com/").headers(headers).send()),
+            "requests must use https"
+        );
+        assert_eq!(
+            violated(client.get(url).send()),
+            "requests must have the X-Request-Id header"
+        );
+
+        // Rejected requests aren't executed.
+        assert_eq!(client.requests().len(), 1);
+    }
+}
@@ -0,0 +1,84 @@
+use client::Client;
+use error::Error;
+use futures::future::{self, FutureResult};
+use futures::{Async, Poll};
+use http;
+use request::Request;
+use tower_service::Service;
+
+/// Adapts any `Client` to a `tower_service::Service` of `http` requests, so stubbed or replayed
+/// responses can be used inside of services composed with tower layers. Available with the
+/// `tower` feature.
+///
+/// Requests are executed synchronously by the wrapped client, the returned future is always
+/// ready. The uri of the requests has to be absolute.
+pub struct ClientService<C: Client> {
+    inner: C,
+}
+
+impl<C: Client> ClientService<C> {
+    /// Create a new `ClientService` executing requests with the provided client.
+    pub fn new(inner: C) -> Self {
+        ClientService { inner: inner }
+    }
+
+    /// Returns the wrapped client.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Client> Service<http::Request<Vec<u8>>> for ClientService<C> {
+    type Response = http::Response<Vec<u8>>;
+    type Error = Error;
+    type Future = FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, request: http::Request<Vec<u8>>) -> Self::Future {
+        let result = Request::from_http(request)
+            .and_then(|request| self.inner.execute(None, request))
+            .and_then(|response| response.into_http());
+        future::result(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{StubClient, StubSettings, StubStrictness};
+    use futures::Future;
+    use reqwest::{Method, Url};
+
+    #[test]
+    fn call() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        client
+            .stub(Url::parse("https://example.com/users").unwrap())
+            .method(Method::Post)
+            .response()
+            .status_code(::reqwest::StatusCode::Created)
+            .body("created")
+            .mock();
+        let mut service = ClientService::new(client);
+        assert!(service.poll_ready().unwrap().is_ready());
+
+        let request = http::Request::builder()
+            .method("POST")
+            .uri("https://example.com/users")
+            .body(b"{}".to_vec())
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.body(), b"created");
+
+        // Relative uris can't be executed.
+        let request = http::Request::builder().uri("/users").body(Vec::new()).unwrap();
+        assert!(service.call(request).wait().is_err());
+    }
+}
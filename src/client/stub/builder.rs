@@ -1,7 +1,10 @@
 use IntoBody;
-use client::stub::{StubClient, StubKey, StubResponse};
+use bytes::Bytes;
+use client::stub::{Absence, Fault, StubClient, StubKey, StubResponse};
+use client::stub::negotiation::Negotiation;
 use reqwest::header::{Header, Headers};
 use reqwest::{Method, StatusCode, Url};
+use std::time::Duration;
 
 /// A request stub builder to be used in conjunction with `StubClient`.
 ///
@@ -14,8 +17,14 @@ pub struct RequestStubber<'cl> {
     url: Url,
 
     _method: Option<Method>,
-    _body: Option<Vec<u8>>,
+    _body: Option<Bytes>,
     _headers: Option<Headers>,
+    _negotiation: Option<Negotiation>,
+    _session: Option<String>,
+    _attempt: Option<usize>,
+    _segments: Option<Vec<(usize, String)>>,
+    _forbidden: Vec<Absence>,
+    _priority: i32,
 }
 
 impl<'cl> RequestStubber<'cl> {
@@ -26,6 +35,12 @@ impl<'cl> RequestStubber<'cl> {
             _method: None,
             _body: None,
             _headers: None,
+            _negotiation: None,
+            _session: None,
+            _attempt: None,
+            _segments: None,
+            _forbidden: Vec::new(),
+            _priority: 0,
         }
     }
 
@@ -59,6 +74,97 @@ impl<'cl> RequestStubber<'cl> {
         self
     }
 
+    /// Require requests using this stub not to have the header, e.g. to verify privacy
+    /// requirements. Requests with the header result in an `ErrorKind::Validation` error
+    /// naming it.
+    ///
+    /// Unlike `header` this isn't used for matching, so it works with every `StubStrictness`.
+    pub fn without_header<S: Into<String>>(mut self, name: S) -> Self {
+        self._forbidden.push(Absence::Header(name.into()));
+        self
+    }
+
+    /// Require requests using this stub to have no or an empty body, like `without_header`.
+    pub fn with_empty_body(mut self) -> Self {
+        self._forbidden.push(Absence::Body);
+        self
+    }
+
+    /// Only use the response if the `Accept` header of the request accepts this media type.
+    ///
+    /// This way multiple responses can be stubbed for the same request, with the one preferred by
+    /// the request being returned. If no stub is acceptable the stub registered without
+    /// `when_accept` is used, if any.
+    pub fn when_accept<S: Into<String>>(mut self, media_type: S) -> Self {
+        self._negotiation = Some(Negotiation {
+            header: "Accept",
+            value: media_type.into(),
+        });
+        self
+    }
+
+    /// Like `when_accept`, but for the language tags of the `Accept-Language` header.
+    pub fn when_accept_language<S: Into<String>>(mut self, language: S) -> Self {
+        self._negotiation = Some(Negotiation {
+            header: "Accept-Language",
+            value: language.into(),
+        });
+        self
+    }
+
+    /// Only use the response for requests of this session, see `StubClient::session_affinity`.
+    pub fn for_session<S: Into<String>>(mut self, session: S) -> Self {
+        self._session = Some(session.into());
+        self
+    }
+
+    /// Only use the response for the n-th request matching this stub, starting at 1. Other
+    /// requests use the stub registered without an attempt.
+    ///
+    /// Together with `ResponseStubber::connection_refused` this allows testing fallback logic,
+    /// e.g. failing the first attempt at the primary host and stubbing its mirror, which is
+    /// just another url.
+    pub fn attempt(mut self, n: usize) -> Self {
+        if n == 0 {
+            panic!("Attempts are counted starting at 1.");
+        }
+        self._attempt = Some(n);
+        self
+    }
+
+    /// Match the path of requests by the segment at `index` (starting at 0) instead of the whole
+    /// path, e.g. `path_segment(0, "users")` and `path_segment(2, "posts")` for
+    /// `/users/{id}/posts`.
+    ///
+    /// Requests then match if their path has as many segments as the one of the stubbed url and
+    /// the given segments, the others may be anything. Templated responses can refer to them with
+    /// `{{path.N}}`. Stubs for the exact path are preferred over stubs matching segments.
+    pub fn path_segment<S: Into<String>>(mut self, index: usize, value: S) -> Self {
+        let count = self.url.path_segments().map_or(0, |s| s.count());
+        if index >= count {
+            panic!(
+                "Tried matching path segment {} of {}, which only has {} segments.",
+                index,
+                self.url,
+                count
+            );
+        }
+
+        let mut segments = self._segments.take().unwrap_or_default();
+        segments.retain(|&(i, _)| i != index);
+        segments.push((index, value.into()));
+        segments.sort();
+        self._segments = Some(segments);
+        self
+    }
+
+    /// Set the priority of the stub, default is 0. If multiple stubs match a request the one
+    /// with the highest priority is used, see `StubClient::explain` for the details.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self._priority = priority;
+        self
+    }
+
     /// Stub the response to this request.
     pub fn response(self) -> ResponseStubber<'cl> {
         ResponseStubber {
@@ -68,11 +174,24 @@ impl<'cl> RequestStubber<'cl> {
                 method: self._method,
                 body: self._body,
                 headers: self._headers.map(|hs| ::helper::serialize_headers(&hs)),
+                session: self._session,
+                attempt: self._attempt,
+                segments: self._segments,
             },
+            negotiation: self._negotiation,
+            forbidden: self._forbidden,
+            priority: self._priority,
 
             _status_code: StatusCode::Ok,
+            _interim: Vec::new(),
+            _trailers: Headers::new(),
             _body: None,
             _headers: Headers::new(),
+            _templated: false,
+            _ranged: false,
+            _respond_early: None,
+            _fault: None,
+            _reset_after: None,
         }
     }
 }
@@ -82,10 +201,20 @@ impl<'cl> RequestStubber<'cl> {
 pub struct ResponseStubber<'cl> {
     client: &'cl mut StubClient,
     req: StubKey,
+    negotiation: Option<Negotiation>,
+    forbidden: Vec<Absence>,
+    priority: i32,
 
     _status_code: StatusCode,
-    _body: Option<Vec<u8>>,
+    _interim: Vec<StatusCode>,
+    _trailers: Headers,
+    _body: Option<Bytes>,
     _headers: Headers,
+    _templated: bool,
+    _ranged: bool,
+    _respond_early: Option<usize>,
+    _fault: Option<Fault>,
+    _reset_after: Option<usize>,
 }
 
 impl<'cl> ResponseStubber<'cl> {
@@ -101,6 +230,60 @@ impl<'cl> ResponseStubber<'cl> {
         self
     }
 
+    /// Precede the response by an interim `1xx` response, e.g. `100 Continue` to complete the
+    /// `Expect: 100-continue` handshake of an upload, see `Response::interim`.
+    ///
+    /// Panics if the status isn't informational.
+    pub fn interim(mut self, status: StatusCode) -> Self {
+        if !status.is_informational() {
+            panic!("Tried stubbing {} as an interim response.", status);
+        }
+        self._interim.push(status);
+        self
+    }
+
+    /// Render the body as a template for every request, replacing placeholders by parts of the
+    /// request. This way echo-style fakes can be stubbed without writing a client.
+    ///
+    /// The following placeholders are supported:
+    ///
+    /// - `{{method}}`, `{{url}}` and `{{body}}`: the method, url and body of the request.
+    /// - `{{path.N}}`: the `N`-th segment of the path, starting at 0.
+    /// - `{{query.name}}`: the value of the query parameter `name`.
+    /// - `{{json.body.a.b}}`: the field `a.b` of the JSON request body, array elements are
+    ///   selected by their index. Strings are inserted without quotes, other values as JSON.
+    ///
+    /// Placeholders referring to parts missing in the request are replaced by nothing.
+    ///
+    /// For example `{"id": {{path.1}}, "user": {{json.body}}}` responds with the posted user.
+    pub fn templated(mut self) -> Self {
+        self._templated = true;
+        self
+    }
+
+    /// Serve byte ranges of the body like a server supporting resumable downloads: requests with
+    /// a `Range` header get `206 Partial Content` with the requested part of the body, or
+    /// `416 Range Not Satisfiable`. All responses advertise `Accept-Ranges: bytes`.
+    ///
+    /// Only single ranges are served, requests for multiple ranges get the full body.
+    pub fn serve_ranges(mut self) -> Self {
+        self._ranged = true;
+        self
+    }
+
+    /// Respond once `after_bytes` bytes of the request body were received, like servers
+    /// rejecting large uploads early, e.g. with `413 Payload Too Large`. The response asks to
+    /// close the connection.
+    ///
+    /// Only bodies streamed with `RequestBuilder::body_reader` are affected, the rest of them
+    /// isn't read. With a `StubStrictness` matching bodies, the stub is matched against the
+    /// first `after_bytes` bytes of the body, as only these are read.
+    pub fn respond_early(mut self, after_bytes: usize) -> Self {
+        self._respond_early = Some(after_bytes);
+        self._headers.set_raw("Connection", "close");
+        self
+    }
+
     /// Add a header to the response.
     pub fn header<H: Header>(mut self, header: H) -> Self {
         self._headers.set(header);
@@ -113,13 +296,546 @@ impl<'cl> ResponseStubber<'cl> {
         self
     }
 
+    /// Add a trailer sent after the body, e.g. the `grpc-status` of a gRPC response, see
+    /// `Response::trailers`. The trailer is announced by the `Trailer` header of the response.
+    pub fn trailer<K: Into<String>, V: Into<Vec<u8>>>(mut self, name: K, value: V) -> Self {
+        let name = name.into();
+        let announced = match self._headers.get_raw("Trailer").and_then(|raw| raw.one()) {
+            Some(announced) => format!("{}, {}", String::from_utf8_lossy(announced), name),
+            None => name.clone(),
+        };
+        self._headers.set_raw("Trailer", announced);
+        self._trailers.set_raw(name, value.into());
+        self
+    }
+
+    /// Respond with a redirect to the url, which can also be relative to the url of the request.
+    ///
+    /// Redirects are followed by the `StubClient` according to `ClientConfig::redirect`, just
+    /// like a live request would, so a stub has to be registered for the target of the redirect
+    /// too. This way chains of redirects can be stubbed as well.
+    pub fn redirects_to(mut self, url: &str, status: StatusCode) -> Self {
+        self._status_code = status;
+        self._headers.set_raw("Location", url.to_string());
+        self
+    }
+
+    /// Mark the response as cacheable for the duration, by setting `Cache-Control: max-age=...`.
+    pub fn cacheable_for(mut self, duration: Duration) -> Self {
+        self._headers.set_raw(
+            "Cache-Control",
+            format!("max-age={}", duration.as_secs()),
+        );
+        self
+    }
+
+    /// Set the `ETag` header of the response, the tag is quoted if it isn't already.
+    pub fn with_etag(mut self, etag: &str) -> Self {
+        let etag = if etag.ends_with('"') {
+            etag.to_string()
+        } else {
+            format!("\"{}\"", etag)
+        };
+        self._headers.set_raw("ETag", etag);
+        self
+    }
+
+    /// Respond with `304 Not Modified` and without body.
+    pub fn not_modified(mut self) -> Self {
+        self._status_code = StatusCode::NotModified;
+        self._body = None;
+        self
+    }
+
+    /// Respond like a load balancer whose backend is down, with `502 Bad Gateway` and an HTML
+    /// error page instead of the body the API would send.
+    pub fn load_balancer_502(mut self) -> Self {
+        self._status_code = StatusCode::BadGateway;
+        self._headers.set_raw("Content-Type", "text/html");
+        self._body = Some(Bytes::from_static(
+            b"<html>\r\n<head><title>502 Bad Gateway</title></head>\r\n<body>\r\n\
+              <center><h1>502 Bad Gateway</h1></center>\r\n<hr><center>nginx</center>\r\n\
+              </body>\r\n</html>\r\n",
+        ));
+        self
+    }
+
+    /// Respond with a JSON error envelope like `{"error": {"code": 422, "message": "..."}}`.
+    pub fn json_error(mut self, status: StatusCode, message: &str) -> Self {
+        let envelope = json!({
+            "error": {
+                "code": u16::from(status),
+                "message": message,
+            }
+        });
+        self._status_code = status;
+        self._headers.set_raw("Content-Type", "application/json");
+        self._body = Some(Bytes::from(envelope.to_string()));
+        self
+    }
+
+    /// Respond with only the first half of the body, but a `Content-Length` of the whole body,
+    /// as if the connection was closed while the body was transferred.
+    pub fn truncated_body<B: IntoBody>(mut self, body: B) -> Self {
+        let mut body = body.into_body();
+        self._headers.set_raw("Content-Length", body.len().to_string());
+        let half = body.len() / 2;
+        body.truncate(half);
+        self._body = Some(body);
+        self
+    }
+
+    /// Respond with an HTML maintenance page and status `200 OK`, as sent by misconfigured
+    /// proxies and captive portals in place of the expected content.
+    pub fn wrong_content_type(mut self) -> Self {
+        self._status_code = StatusCode::Ok;
+        self._headers.set_raw("Content-Type", "text/html; charset=utf-8");
+        self._body = Some(Bytes::from_static(
+            b"<!DOCTYPE html>\n<html><body><h1>Down for maintenance</h1></body></html>\n",
+        ));
+        self
+    }
+
+    /// Deliver only the first `n` bytes of the body, see `Response::reset_after`.
+    pub fn reset_after(mut self, n: usize) -> Self {
+        self._reset_after = Some(n);
+        self
+    }
+
+    /// Fail the request like a host refusing the connection would, with an `io::Error` of kind
+    /// `ConnectionRefused`, instead of responding.
+    pub fn connection_refused(mut self) -> Self {
+        self._fault = Some(Fault::ConnectionRefused);
+        self
+    }
+
+    /// Fail the request like a host name which can't be resolved would, with an
+    /// `ErrorKind::Dns` error, instead of responding.
+    pub fn dns_failure(mut self) -> Self {
+        self._fault = Some(Fault::Dns);
+        self
+    }
+
+    /// Fail the request like a failed TLS handshake would, with an `ErrorKind::TlsHandshake`
+    /// error, instead of responding.
+    pub fn tls_handshake_failure(mut self) -> Self {
+        self._fault = Some(Fault::TlsHandshake);
+        self
+    }
+
+    /// Fail the request like a certificate issued for another host name would, with an
+    /// `ErrorKind::CertificateMismatch` error, instead of responding.
+    pub fn certificate_mismatch(mut self) -> Self {
+        self._fault = Some(Fault::CertificateMismatch);
+        self
+    }
+
     /// Register the mock in the client.
-    pub fn mock(self) {
+    pub fn mock(mut self) {
+        let mut reset_at = None;
+        if let Some(n) = self._reset_after {
+            match self._body {
+                Some(ref mut body) if body.len() > n => {
+                    self._headers.set_raw("Content-Length", body.len().to_string());
+                    body.truncate(n);
+                    reset_at = Some(n);
+                }
+                _ => {}
+            }
+        }
+
         let resp = StubResponse {
             status_code: self._status_code,
+            interim: self._interim,
+            trailers: self._trailers,
+            reset_at: reset_at,
             body: self._body,
             headers: self._headers,
+            negotiation: self.negotiation,
+            templated: self._templated,
+            ranged: self._ranged,
+            respond_early: self._respond_early,
+            fault: self._fault,
+            forbidden: self.forbidden,
+            priority: self.priority,
         };
         self.client.register_stub(self.req, resp);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use client::{Client, StubClient, StubSettings, StubStrictness};
+    use error::ErrorKind;
+    use reqwest::Url;
+    use reqwest::header::Headers;
+
+    #[test]
+    fn absence() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/track").unwrap();
+        client
+            .stub(url.clone())
+            .without_header("Cookie")
+            .with_empty_body()
+            .response()
+            .mock();
+
+        assert!(client.get(url.clone()).send().is_ok());
+
+        let mut cookie = Headers::new();
+        cookie.set_raw("Cookie", "session=1");
+        let error = client.get(url.clone()).headers(cookie).send().unwrap_err();
+        match *error.kind() {
+            ErrorKind::Validation(ref rule, _) => assert!(rule.contains("Cookie")),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        assert!(client.post(url).body("data").send().is_err());
+    }
+
+    #[test]
+    fn interim() {
+        use reqwest::StatusCode;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/upload").unwrap();
+        client
+            .stub(url.clone())
+            .response()
+            .interim(StatusCode::Continue)
+            .status_code(StatusCode::Created)
+            .mock();
+
+        let mut expect = Headers::new();
+        expect.set_raw("Expect", "100-continue");
+        let response = client.post(url).headers(expect).body("data").send().unwrap();
+        assert_eq!(response.status, StatusCode::Created);
+        assert_eq!(response.interim, vec![StatusCode::Continue]);
+        assert!(response.continued());
+    }
+
+    #[test]
+    fn trailers() {
+        use header_ext::HeadersExt;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/grpc").unwrap();
+        client
+            .stub(url.clone())
+            .response()
+            .trailer("grpc-status", "0")
+            .trailer("grpc-message", "OK")
+            .mock();
+
+        let response = client.get(url).send().unwrap();
+        assert_eq!(response.headers.get_str("Trailer"), Some("grpc-status, grpc-message"));
+        assert_eq!(response.trailers().get_str("grpc-status"), Some("0"));
+        assert_eq!(response.trailers().get_str("grpc-message"), Some("OK"));
+    }
+
+    #[test]
+    fn reset_after() {
+        use reqwest::Method;
+        use std::io::{ErrorKind as IoErrorKind, Read};
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/download").unwrap();
+        client
+            .stub(url.clone())
+            .method(Method::Get)
+            .response()
+            .body(vec![1u8; 100])
+            .reset_after(10)
+            .mock();
+        client
+            .stub(url.clone())
+            .method(Method::Head)
+            .response()
+            .header(::reqwest::header::ContentLength(100))
+            .mock();
+
+        let mut body = Vec::new();
+        let response = client.get(url.clone()).send().unwrap();
+        let err = response.reader().read_to_end(&mut body).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::ConnectionReset);
+        assert_eq!(body.len(), 10);
+
+        let mut body = Vec::new();
+        let response = client.head(url).send().unwrap();
+        response.reader().read_to_end(&mut body).unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn redirects() {
+        use reqwest::StatusCode;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = |path: &str| Url::parse("http://example.com/").unwrap().join(path).unwrap();
+        client
+            .stub(url("a"))
+            .response()
+            .redirects_to("/b", StatusCode::MovedPermanently)
+            .mock();
+        client
+            .stub(url("b"))
+            .response()
+            .redirects_to("c", StatusCode::Found)
+            .mock();
+        client.stub(url("c")).attempt(1).response().body("first").mock();
+        client.stub(url("c")).response().body("later").mock();
+
+        let response = client.get(url("a")).send().unwrap();
+        assert_eq!(response.url, url("c"));
+        assert_eq!(response.body_to_utf8().unwrap(), "first");
+
+        // Following the redirects neither counted as an attempt nor is part of the history.
+        let response = client.get(url("c")).send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "first");
+        let urls: Vec<Url> = client.requests().into_iter().map(|r| r.url).collect();
+        assert_eq!(urls, vec![url("a"), url("c")]);
+    }
+
+    #[test]
+    fn respond_early() {
+        use reqwest::{Method, StatusCode};
+        use std::io::{Cursor, Read};
+        use std::sync::{Arc, Mutex};
+
+        /// Counts the bytes read from it.
+        struct Counting(Cursor<Vec<u8>>, Arc<Mutex<usize>>);
+
+        impl Read for Counting {
+            fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+                let n = self.0.read(buf)?;
+                *self.1.lock().unwrap() += n;
+                Ok(n)
+            }
+        }
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/upload").unwrap();
+        client
+            .stub(url.clone())
+            .response()
+            .status_code(StatusCode::PayloadTooLarge)
+            .respond_early(1024)
+            .mock();
+
+        let read = Arc::new(Mutex::new(0));
+        let body = Counting(Cursor::new(vec![0; 1024 * 1024]), read.clone());
+        let response = client.post(url).body_reader(body).send().unwrap();
+        assert_eq!(response.status, StatusCode::PayloadTooLarge);
+        assert_eq!(*read.lock().unwrap(), 1024);
+
+        // Matching bodies, the stub is matched against the part of the body it reads.
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::BodyMethodUrl,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/upload").unwrap();
+        client
+            .stub(url.clone())
+            .method(Method::Post)
+            .body(vec![0; 1024])
+            .response()
+            .status_code(StatusCode::PayloadTooLarge)
+            .respond_early(1024)
+            .mock();
+
+        let read = Arc::new(Mutex::new(0));
+        let body = Counting(Cursor::new(vec![0; 1024 * 1024]), read.clone());
+        let response = client.post(url).body_reader(body).send().unwrap();
+        assert_eq!(response.status, StatusCode::PayloadTooLarge);
+        assert_eq!(*read.lock().unwrap(), 1024);
+    }
+
+    #[test]
+    fn caching() {
+        use header_ext::HeadersExt;
+        use reqwest::StatusCode;
+        use std::time::Duration;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = |path: &str| Url::parse("http://example.com/").unwrap().join(path).unwrap();
+        client
+            .stub(url("fresh"))
+            .response()
+            .body("data")
+            .cacheable_for(Duration::from_secs(60))
+            .with_etag("v1")
+            .mock();
+        client
+            .stub(url("unchanged"))
+            .response()
+            .body("data")
+            .with_etag("\"v2\"")
+            .not_modified()
+            .mock();
+
+        let response = client.get(url("fresh")).send().unwrap();
+        assert_eq!(response.headers.get_str("Cache-Control"), Some("max-age=60"));
+        assert_eq!(response.headers.get_str("ETag"), Some("\"v1\""));
+        assert_eq!(response.body, "data");
+
+        let response = client.get(url("unchanged")).send().unwrap();
+        assert_eq!(response.status, StatusCode::NotModified);
+        assert_eq!(response.headers.get_str("ETag"), Some("\"v2\""));
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn session_affinity() {
+        use client::SessionAffinity;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        client.session_affinity(SessionAffinity::Cookie("session".to_string()));
+        let url = Url::parse("http://example.com/cart").unwrap();
+        client.stub(url.clone()).response().body("empty").mock();
+        client
+            .stub(url.clone())
+            .for_session("alice")
+            .response()
+            .body("apples")
+            .mock();
+
+        let cookie = |value: &str| {
+            let mut headers = Headers::new();
+            headers.set_raw("Cookie", value.to_string());
+            headers
+        };
+        let body = |headers: Headers| client.get(url.clone()).headers(headers).send().unwrap().body;
+        assert_eq!(body(cookie("theme=dark; session=alice")), "apples");
+        assert_eq!(body(cookie("session=bob")), "empty");
+        assert_eq!(body(Headers::new()), "empty");
+    }
+
+    #[test]
+    fn error_responses() {
+        use header_ext::HeadersExt;
+        use reqwest::StatusCode;
+        use serde_json::Value;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = |path: &str| Url::parse("http://example.com/").unwrap().join(path).unwrap();
+        client.stub(url("down")).response().load_balancer_502().mock();
+        client
+            .stub(url("invalid"))
+            .response()
+            .json_error(StatusCode::UnprocessableEntity, "name is missing")
+            .mock();
+        client.stub(url("cut")).response().truncated_body("abcdefgh").mock();
+        client.stub(url("portal")).response().wrong_content_type().mock();
+
+        let response = client.get(url("down")).send().unwrap();
+        assert_eq!(response.status, StatusCode::BadGateway);
+        assert_eq!(response.headers.content_type(), Some("text/html"));
+
+        let response = client.get(url("invalid")).send().unwrap();
+        assert_eq!(response.status, StatusCode::UnprocessableEntity);
+        let envelope: Value = ::serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(envelope["error"]["code"], 422);
+        assert_eq!(envelope["error"]["message"], "name is missing");
+
+        let response = client.get(url("cut")).send().unwrap();
+        assert_eq!(response.headers.get_str("Content-Length"), Some("8"));
+        assert_eq!(response.body, "abcd");
+
+        let response = client.get(url("portal")).send().unwrap();
+        assert_eq!(response.status, StatusCode::Ok);
+        assert!(response.headers.content_type().unwrap().starts_with("text/html"));
+    }
+
+    #[test]
+    fn attempts() {
+        use std::io::ErrorKind as IoErrorKind;
+
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://primary.example.com/data").unwrap();
+        client.stub(url.clone()).attempt(1).response().connection_refused().mock();
+        client.stub(url.clone()).attempt(3).response().body("third").mock();
+        client.stub(url.clone()).response().body("data").mock();
+
+        match *client.get(url.clone()).send().unwrap_err().kind() {
+            ErrorKind::Io(ref e) => assert_eq!(e.kind(), IoErrorKind::ConnectionRefused),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        let bodies: Vec<_> = (0..3)
+            .map(|_| client.get(url.clone()).send().unwrap().body)
+            .collect();
+        assert_eq!(bodies, vec!["data", "third", "data"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "starting at 1")]
+    fn attempt_zero() {
+        let mut client = StubClient::new(StubSettings::default());
+        let _ = client.stub(Url::parse("http://example.com/").unwrap()).attempt(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no stub or recorded request matched the GET request")]
+    fn failure_style_panic() {
+        use config::FailureStyle;
+
+        let mut client = StubClient::new(StubSettings::default());
+        client.config_mut().failure_style = FailureStyle::Panic;
+        let _ = client.get("http://example.com/unknown").send();
+    }
+
+    #[test]
+    fn transport_faults() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = |host: &str| Url::parse(&format!("https://{}/", host)).unwrap();
+        client.stub(url("unknown.example.com")).response().dns_failure().mock();
+        client.stub(url("tls.example.com")).response().tls_handshake_failure().mock();
+        client.stub(url("other.example.com")).response().certificate_mismatch().mock();
+
+        let error = |host: &str| client.get(url(host)).send().unwrap_err();
+        match *error("unknown.example.com").kind() {
+            ErrorKind::Dns(ref host) => assert_eq!(host, "unknown.example.com"),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        match *error("tls.example.com").kind() {
+            ErrorKind::TlsHandshake(ref host, _) => assert_eq!(host, "tls.example.com"),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        match *error("other.example.com").kind() {
+            ErrorKind::CertificateMismatch(ref host) => assert_eq!(host, "other.example.com"),
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+    }
+}
@@ -1,30 +1,158 @@
-use client::Client;
-use config::ClientConfig;
-use error::Error;
+use bytes::Bytes;
+use client::{Client, Coverage, DirectClient};
+use config::{ClientConfig, RedirectPolicy};
+use error::{Error, ErrorKind, ResultExt};
+use header_ext::HeadersExt;
+use metrics::ResponseSource;
 use request::Request;
 use reqwest::header::Headers;
 use reqwest::{Method, Url, StatusCode};
 use response::Response;
 use std::collections::{HashMap, BTreeMap};
+use std::io::{self, Read};
+use std::sync::Mutex;
 
 mod settings;
-pub use self::settings::{StubStrictness, StubDefault, StubSettings};
+pub use self::settings::{SessionAffinity, StubStrictness, StubDefault, StubSettings};
 
 mod builder;
 pub use self::builder::{RequestStubber, ResponseStubber};
 
-#[derive(Hash, PartialEq, Eq)]
+mod negotiation;
+use self::negotiation::Negotiation;
+
+mod openapi;
+
+mod pagination;
+pub use self::pagination::Pagination;
+
+mod priority;
+
+mod range;
+
+mod segments;
+
+mod template;
+
+#[derive(Clone, Hash, PartialEq, Eq)]
 struct StubKey {
     url: Url,
     method: Option<Method>,
-    body: Option<Vec<u8>>,
+    body: Option<Bytes>,
     headers: Option<BTreeMap<String, String>>,
+
+    /// The session the stub is restricted to, see `StubClient::session_affinity`.
+    session: Option<String>,
+
+    /// The attempt the stub is restricted to, see `RequestStubber::attempt`.
+    attempt: Option<usize>,
+
+    /// If set, the path of the url is matched by these segments only, see
+    /// `RequestStubber::path_segment`.
+    segments: Option<Vec<(usize, String)>>,
 }
 
 struct StubResponse {
     status_code: StatusCode,
-    body: Option<Vec<u8>>,
+    interim: Vec<StatusCode>,
+    trailers: Headers,
+    reset_at: Option<usize>,
+    body: Option<Bytes>,
     headers: Headers,
+    negotiation: Option<Negotiation>,
+    templated: bool,
+    ranged: bool,
+    respond_early: Option<usize>,
+    fault: Option<Fault>,
+    forbidden: Vec<Absence>,
+    priority: i32,
+}
+
+/// Failures of the transport simulated instead of responding, see
+/// `ResponseStubber::connection_refused` and the following methods.
+#[derive(Clone, Copy, Debug)]
+enum Fault {
+    ConnectionRefused,
+    Dns,
+    TlsHandshake,
+    CertificateMismatch,
+}
+
+/// Parts which have to be absent from requests using a stub, see
+/// `RequestStubber::without_header` and `RequestStubber::with_empty_body`.
+#[derive(Clone, Debug)]
+enum Absence {
+    Header(String),
+    Body,
+}
+
+/// A registered stub, the body of templated responses is rendered for each request.
+struct Stub {
+    response: Response,
+    templated: bool,
+
+    /// See `ResponseStubber::serve_ranges`.
+    ranged: bool,
+
+    /// See `ResponseStubber::respond_early`.
+    respond_early: Option<usize>,
+    fault: Option<Fault>,
+    forbidden: Vec<Absence>,
+
+    /// See `RequestStubber::priority`.
+    priority: i32,
+
+    /// The number of stubs registered before this one.
+    order: usize,
+}
+
+impl Stub {
+    fn respond(&self, request: &Request) -> Result<(Response, ResponseSource), Error> {
+        for absence in &self.forbidden {
+            let rule = match *absence {
+                Absence::Header(ref name) if request.headers.get_raw(name).is_some() => {
+                    format!("requests must not have the {} header", name)
+                }
+                Absence::Body if request.body.as_ref().map_or(false, |b| !b.is_empty()) => {
+                    "requests must have an empty body".to_string()
+                }
+                _ => continue,
+            };
+            return Err(ErrorKind::Validation(rule, request.url.to_string()).into());
+        }
+
+        let host = request.url.host_str().unwrap_or_default().to_string();
+        match self.fault {
+            Some(Fault::ConnectionRefused) => {
+                return Err(
+                    io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("stubbed connection refused for {}", request.url),
+                    ).into(),
+                );
+            }
+            Some(Fault::Dns) => return Err(ErrorKind::Dns(host).into()),
+            Some(Fault::TlsHandshake) => {
+                return Err(
+                    ErrorKind::TlsHandshake(host, "stubbed handshake failure".to_string()).into(),
+                );
+            }
+            Some(Fault::CertificateMismatch) => {
+                return Err(ErrorKind::CertificateMismatch(host).into());
+            }
+            None => {}
+        }
+
+        let mut response = self.response.clone();
+        if self.templated {
+            let body = template::render(&String::from_utf8_lossy(&response.body), request);
+            response.body = Bytes::from(body);
+        }
+        if self.ranged {
+            range::serve(&mut response, request);
+        }
+        Ok((response, ResponseSource::Stubbed))
+    }
 }
 
 /// A client which allows you to stub out the response to a request explicitly.
@@ -56,8 +184,24 @@ struct StubResponse {
 /// ```
 pub struct StubClient {
     config: ClientConfig,
-    stubs: HashMap<StubKey, Response>,
+    stubs: HashMap<StubKey, Stub>,
+    /// Stubs only used if the request accepts them, see `RequestStubber::when_accept`.
+    negotiated: HashMap<StubKey, Vec<(Negotiation, Stub)>>,
     settings: StubSettings,
+    direct: DirectClient,
+    affinity: Option<SessionAffinity>,
+
+    /// How many requests were made for each stub key, see `RequestStubber::attempt`.
+    attempts: Mutex<HashMap<StubKey, usize>>,
+
+    /// How many times each stub was used, see `StubClient::coverage`.
+    hits: Mutex<HashMap<StubKey, usize>>,
+
+    /// All requests executed by this client, see `Client::requests`.
+    history: Mutex<Vec<Request>>,
+
+    /// The number of stubs registered so far.
+    registered: usize,
 }
 
 impl StubClient {
@@ -69,7 +213,14 @@ impl StubClient {
         StubClient {
             config: ClientConfig::default(),
             stubs: HashMap::new(),
+            negotiated: HashMap::new(),
             settings: stub_settings,
+            direct: DirectClient::new(),
+            affinity: None,
+            attempts: Mutex::new(HashMap::new()),
+            hits: Mutex::new(HashMap::new()),
+            history: Mutex::new(Vec::new()),
+            registered: 0,
         }
     }
 
@@ -86,6 +237,56 @@ impl StubClient {
         RequestStubber::new(self, url)
     }
 
+    /// Identify the session of requests by a cookie or header, so stubs registered with
+    /// `RequestStubber::for_session` only apply to the requests of that session. This way the
+    /// logical users of a test can each see their own state of the fake server.
+    ///
+    /// Stubs registered for a session take precedence over ones for all sessions.
+    pub fn session_affinity(&mut self, affinity: SessionAffinity) {
+        self.affinity = Some(affinity);
+    }
+
+    /// Reports which stubs were used by the requests made so far and how often. Stubs which are
+    /// never used usually mean the code under test doesn't reach the endpoint.
+    pub fn coverage(&self) -> Coverage {
+        let hits = self.hits.lock().unwrap();
+        let entries = self.stubs
+            .keys()
+            .chain(self.negotiated.keys())
+            .map(|key| {
+                let method = key.method.as_ref().map_or("*".to_string(), |m| m.to_string());
+                let n = hits.get(key).cloned().unwrap_or(0);
+                (format!("{} {}", method, key.url), n)
+            })
+            .collect();
+        Coverage::new(entries)
+    }
+
+    /// Returns the session of the request, see `session_affinity`.
+    fn session(&self, request: &Request) -> Option<String> {
+        match self.affinity {
+            Some(SessionAffinity::Header(ref name)) => {
+                request.headers.get_str(name).map(|v| v.to_string())
+            }
+            Some(SessionAffinity::Cookie(ref name)) => {
+                request
+                    .headers
+                    .get_all_str("Cookie")
+                    .into_iter()
+                    .flat_map(|line| line.split(';'))
+                    .filter_map(|pair| {
+                        let mut pair = pair.splitn(2, '=');
+                        match (pair.next(), pair.next()) {
+                            (Some(n), Some(v)) if n.trim() == name => Some(v.trim().to_string()),
+                            _ => None,
+                        }
+                    })
+                    .next()
+            }
+            None => None,
+        }
+    }
+
     /// Return the appropriate `StubKey` for the provided request.
     fn stub_key(&self, request: &Request) -> StubKey {
         match self.settings.strictness {
@@ -95,6 +296,9 @@ impl StubClient {
                     method: Some(request.method.clone()),
                     body: request.body.clone(),
                     headers: Some(::helper::serialize_headers(&request.headers)),
+                    session: None,
+                    attempt: None,
+                    segments: None,
                 }
             }
             StubStrictness::BodyMethodUrl => {
@@ -103,6 +307,9 @@ impl StubClient {
                     method: Some(request.method.clone()),
                     body: request.body.clone(),
                     headers: None,
+                    session: None,
+                    attempt: None,
+                    segments: None,
                 }
             }
             StubStrictness::HeadersMethodUrl => {
@@ -111,6 +318,9 @@ impl StubClient {
                     method: Some(request.method.clone()),
                     body: None,
                     headers: Some(::helper::serialize_headers(&request.headers)),
+                    session: None,
+                    attempt: None,
+                    segments: None,
                 }
             }
             StubStrictness::MethodUrl => {
@@ -119,6 +329,9 @@ impl StubClient {
                     method: Some(request.method.clone()),
                     body: None,
                     headers: None,
+                    session: None,
+                    attempt: None,
+                    segments: None,
                 }
             }
             StubStrictness::Url => {
@@ -127,6 +340,9 @@ impl StubClient {
                     method: None,
                     body: None,
                     headers: None,
+                    session: None,
+                    attempt: None,
+                    segments: None,
                 }
             }
         }
@@ -182,44 +398,51 @@ impl StubClient {
             url: key.url.clone(),
             status: value.status_code,
             headers: value.headers,
-            body: value.body.unwrap_or_else(Vec::new),
+            body: value.body.unwrap_or_else(Bytes::new),
+            elapsed: None,
+            replay: None,
+            interim: value.interim,
+            trailers: value.trailers,
+            reset_at: value.reset_at,
+        };
+        let stub = Stub {
+            response: response,
+            templated: value.templated,
+            ranged: value.ranged,
+            respond_early: value.respond_early,
+            fault: value.fault,
+            forbidden: value.forbidden,
+            priority: value.priority,
+            order: self.registered,
         };
-        self.stubs.insert(key, response);
+        self.registered += 1;
+        match value.negotiation {
+            Some(negotiation) => {
+                let variants = self.negotiated.entry(key).or_insert_with(Vec::new);
+                if variants.iter().any(|&(ref n, _)| n.header != negotiation.header) {
+                    panic!(
+                        "Tried registering stubs negotiating different headers for the same \
+                         request."
+                    );
+                }
+                variants.push((negotiation, stub));
+            }
+            None => {
+                self.stubs.insert(key, stub);
+            }
+        }
     }
 }
 
 impl Client for StubClient {
     fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
-        // Check if there is a recorded stub for the request.
-        let key = self.stub_key(&request);
-        match self.stubs.get(&key) {
-            Some(resp) => Ok(resp.clone()),
-            None => {
-                match self.settings.default {
-                    StubDefault::Panic => {
-                        // TODO provide more diagonistics using log crate.
-                        panic!(
-                            "Requested {}, without having provided a stub for it.",
-                            request.url
-                        );
-                    }
-                    StubDefault::Error => {
-                        // TODO provide more diagonistics using log crate.
-                        Err(
-                            format!(
-                                "Requested {}, without having provided a stub for it.",
-                                request.url
-                            ).into(),
-                        )
-                    }
-                    StubDefault::PerformRequest => {
-                        use client::DirectClient;
-                        let client = DirectClient::new();
-                        client.execute(config, request)
-                    }
-                }
-            }
-        }
+        let metrics_config = config.unwrap_or_else(|| &self.config);
+        let result = ::metrics::instrument(metrics_config, request, |request| {
+            let (mut response, source) = self.follow_redirects(config, request)?;
+            ::compression::decompress_response(metrics_config, &mut response)?;
+            Ok((response, source))
+        });
+        metrics_config.failure_style.check(result)
     }
 
     fn config(&self) -> &ClientConfig {
@@ -229,4 +452,147 @@ impl Client for StubClient {
     fn config_mut(&mut self) -> &mut ClientConfig {
         &mut self.config
     }
+
+    /// Reads the body into memory, except for stubs responding early, which only read as much
+    /// of the body as they are configured to, see `ResponseStubber::respond_early`.
+    fn execute_streaming(
+        &self,
+        config: Option<&ClientConfig>,
+        mut request: Request,
+        mut body: Box<dyn Read + Send>,
+    ) -> Result<Response, Error> {
+        // Stubs responding early are matched against the part of the body they read, so the
+        // body is read up to each of their limits in turn.
+        let negotiated = self.negotiated.values().flat_map(|v| v.iter().map(|v| &v.1));
+        let mut limits: Vec<usize> = self.stubs
+            .values()
+            .chain(negotiated)
+            .filter_map(|stub| stub.respond_early)
+            .collect();
+        limits.sort();
+        limits.dedup();
+
+        let mut buf = Vec::new();
+        for n in limits {
+            if buf.len() < n {
+                (&mut body).take((n - buf.len()) as u64).read_to_end(&mut buf)?;
+            }
+            request.body = Some(Bytes::from(buf.clone()));
+            let attempt = self.next_attempt(&request);
+            let candidate = self.candidates(&request, attempt).into_iter().next();
+            if candidate.and_then(|c| c.stub.respond_early) == Some(n) {
+                return self.execute(config, request);
+            }
+            if buf.len() < n {
+                // The whole body was read already.
+                break;
+            }
+        }
+
+        body.read_to_end(&mut buf)?;
+        request.body = Some(Bytes::from(buf));
+        self.execute(config, request)
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.history.lock().unwrap().clone()
+    }
+}
+
+impl StubClient {
+    /// Returns the stubbed response to the request, following stubbed redirects.
+    fn follow_redirects(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+    ) -> Result<(Response, ResponseSource), Error> {
+        let limit = match config.unwrap_or_else(|| &self.config).redirect {
+            RedirectPolicy::Limit(n) => n,
+            RedirectPolicy::None => 0,
+        };
+
+        // Only the request itself counts as an attempt and is part of the history, the requests
+        // following its redirects don't.
+        let key = self.stub_key(&request);
+        let mut attempt = {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(key).or_insert(0);
+            *attempt += 1;
+            *attempt
+        };
+        self.history.lock().unwrap().push(request.clone());
+
+        let mut request = request;
+        let mut redirects = 0;
+        loop {
+            let (response, source) = self.respond(config, request.clone(), attempt)?;
+
+            let location = match response.headers.get_str("Location") {
+                Some(location) if response.status.is_redirection() => location.to_string(),
+                _ => return Ok((response, source)),
+            };
+            if redirects == limit {
+                if limit == 0 {
+                    return Ok((response, source));
+                }
+                return Err(format!("Too many redirects, stopped at {}.", request.url).into());
+            }
+            redirects += 1;
+
+            let url = request.url.join(&location).chain_err(|| "invalid redirect location")?;
+            debug!("Following stubbed redirect from {} to {}.", request.url, url);
+            request.url = url;
+            // Like browsers and reqwest, only keep the method and body for 307 and 308.
+            match u16::from(response.status) {
+                307 | 308 => {}
+                _ => {
+                    if request.method != Method::Head {
+                        request.method = Method::Get;
+                    }
+                    request.body = None;
+                }
+            }
+            attempt = self.next_attempt(&request);
+        }
+    }
+
+    /// Returns the stubbed response to the request as its `attempt`, without following
+    /// redirects.
+    fn respond(
+        &self,
+        config: Option<&ClientConfig>,
+        request: Request,
+        attempt: usize,
+    ) -> Result<(Response, ResponseSource), Error> {
+        // Check if there is a stub for the request.
+        if let Some(candidate) = self.candidates(&request, attempt).into_iter().next() {
+            *self.hits.lock().unwrap().entry(candidate.key.clone()).or_insert(0) += 1;
+            return candidate.stub.respond(&request);
+        }
+
+        match self.settings.default {
+            StubDefault::Panic => {
+                // TODO provide more diagonistics using log crate.
+                panic!(
+                    "Requested {}, without having provided a stub for it.",
+                    request.url
+                );
+            }
+            StubDefault::Error => {
+                // TODO provide more diagonistics using log crate.
+                Err(
+                    ErrorKind::Unmatched(
+                        request.method.to_string(),
+                        request.url.to_string(),
+                    ).into(),
+                )
+            }
+            StubDefault::PerformRequest => {
+                // Fall back to our own config, the direct client only has the default one.
+                let config = config.unwrap_or_else(|| &self.config);
+                let response = self.direct.send_live(config, request)?;
+                Ok((response, ResponseSource::Live))
+            }
+        }
+    }
 }
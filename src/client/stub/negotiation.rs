@@ -0,0 +1,94 @@
+//! Content negotiation for stubs registered with `RequestStubber::when_accept` and
+//! `RequestStubber::when_accept_language`.
+
+use reqwest::header::Headers;
+use std::cmp::Ordering;
+
+/// A response variant is chosen if the request's value of `header` accepts `value`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Negotiation {
+    pub header: &'static str,
+    pub value: String,
+}
+
+/// Returns the index of the variant preferred by the request, going through the ranges of the
+/// request's header in the order of their quality.
+pub fn choose(headers: &Headers, variants: &[Negotiation]) -> Option<usize> {
+    let header = match variants.first() {
+        Some(n) => n.header,
+        None => return None,
+    };
+    let value = match headers.get_raw(header).and_then(|raw| raw.one()) {
+        Some(value) => String::from_utf8_lossy(value).into_owned(),
+        None => return None,
+    };
+
+    let mut ranges: Vec<(&str, f32)> = value
+        .split(',')
+        .map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let name = parts.next().unwrap_or("");
+            let quality = parts
+                .filter_map(|p| p.strip_prefix("q=").and_then(|q| q.parse().ok()))
+                .next()
+                .unwrap_or(1.);
+            (name, quality)
+        })
+        .filter(|&(_, quality)| quality > 0.)
+        .collect();
+    // A stable sort, so ranges of equal quality keep their order.
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+    ranges.iter().filter_map(|&(range, _)| {
+        variants.iter().position(|n| accepts(range, &n.value))
+    }).next()
+}
+
+/// Whether a media range or language range of a request accepts the value.
+fn accepts(range: &str, value: &str) -> bool {
+    let range = range.to_lowercase();
+    let value = value.to_lowercase();
+
+    if range == value || range == "*" || range == "*/*" {
+        true
+    } else if range.ends_with("/*") {
+        value.starts_with(&range[..range.len() - 1])
+    } else {
+        // Language ranges also match more specific tags.
+        value.starts_with(&format!("{}-", range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> Headers {
+        let mut headers = Headers::new();
+        headers.set_raw("Accept", value.to_string());
+        headers
+    }
+
+    #[test]
+    fn choose() {
+        let variants = [
+            Negotiation {
+                header: "Accept",
+                value: "application/json".to_string(),
+            },
+            Negotiation {
+                header: "Accept",
+                value: "application/xml".to_string(),
+            },
+        ];
+
+        assert_eq!(super::choose(&accept("application/xml"), &variants), Some(1));
+        assert_eq!(
+            super::choose(&accept("application/json;q=0.5, application/xml"), &variants),
+            Some(1)
+        );
+        assert_eq!(super::choose(&accept("text/html, */*;q=0.1"), &variants), Some(0));
+        assert_eq!(super::choose(&accept("text/html"), &variants), None);
+        assert_eq!(super::choose(&Headers::new(), &variants), None);
+    }
+}
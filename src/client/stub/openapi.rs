@@ -0,0 +1,226 @@
+use client::stub::{StubClient, StubStrictness};
+use error::{Error, ErrorKind, ResultExt};
+use reqwest::header::Headers;
+use reqwest::{Method, StatusCode, Url};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+const METHODS: &'static [&'static str] = &["get", "put", "post", "delete", "options", "head",
+                                           "patch", "trace"];
+
+impl StubClient {
+    /// Register stubs for all operations of an OpenAPI 3 specification (in its JSON form), using
+    /// the examples of the specification as responses.
+    ///
+    /// For every operation the lowest documented `2xx` response (or the first documented one) is
+    /// used, with the `example` of its content, the first of its `examples` or the `example` of
+    /// its schema as body. Path parameters are filled in using the examples of the parameters,
+    /// operations where that isn't possible are skipped.
+    ///
+    /// The stubs are registered relative to `base_url`, and can be overridden by registering
+    /// another stub for the same request afterwards. This requires `StubStrictness::MethodUrl` or
+    /// `StubStrictness::Url`.
+    ///
+    /// Returns the number of registered stubs.
+    pub fn stub_openapi(&mut self, base_url: &Url, spec: &Value) -> Result<usize, Error> {
+        let with_method = match self.settings.strictness {
+            StubStrictness::MethodUrl => true,
+            StubStrictness::Url => false,
+            ref other => {
+                return Err(
+                    ErrorKind::Configuration(format!(
+                        "can't generate OpenAPI stubs with {:?}, use MethodUrl or Url",
+                        other
+                    )).into(),
+                )
+            }
+        };
+
+        let paths = match spec.get("paths").and_then(Value::as_object) {
+            Some(paths) => paths,
+            None => {
+                return Err(
+                    ErrorKind::Configuration("OpenAPI specification has no `paths`".to_string())
+                        .into(),
+                )
+            }
+        };
+
+        let mut count = 0;
+        for (path, item) in paths {
+            for name in METHODS {
+                let operation = match item.get(name) {
+                    Some(op) => op,
+                    None => continue,
+                };
+
+                let path = match fill_path_params(path, item, operation) {
+                    Some(p) => p,
+                    None => {
+                        warn!(
+                            "Skipping {} {}, missing examples for path parameters.",
+                            name,
+                            path
+                        );
+                        continue;
+                    }
+                };
+                let url = Url::parse(&format!(
+                    "{}{}",
+                    base_url.as_str().trim_end_matches('/'),
+                    path
+                )).chain_err(|| "invalid url")?;
+                let (status, content_type, body) = example_response(operation);
+
+                let mut request = self.stub(url);
+                if with_method {
+                    let method = Method::from_str(&name.to_uppercase()).unwrap();
+                    request = request.method(method);
+                }
+                let mut response = request.response().status_code(status);
+                if let Some(content_type) = content_type {
+                    let mut headers = Headers::new();
+                    headers.set_raw("Content-Type", content_type);
+                    response = response.headers(headers);
+                }
+                if let Some(body) = body {
+                    response = response.body(body);
+                }
+                response.mock();
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// Replaces `{name}` placeholders with the examples of the path parameters, which can be
+/// specified both on the path item and the operation.
+fn fill_path_params(path: &str, item: &Value, operation: &Value) -> Option<String> {
+    let mut filled = path.to_string();
+    let params = item.get("parameters")
+        .and_then(Value::as_array)
+        .into_iter()
+        .chain(operation.get("parameters").and_then(Value::as_array))
+        .flat_map(|ps| ps.iter());
+
+    for param in params {
+        if param.get("in").and_then(Value::as_str) != Some("path") {
+            continue;
+        }
+        let name = param.get("name").and_then(Value::as_str)?;
+        let example = param.get("example").or_else(|| {
+            param.get("schema").and_then(|s| s.get("example"))
+        })?;
+        let example = match *example {
+            Value::String(ref s) => s.clone(),
+            ref other => other.to_string(),
+        };
+        filled = filled.replace(&format!("{{{}}}", name), &example);
+    }
+
+    if filled.contains('{') { None } else { Some(filled) }
+}
+
+/// Returns the status code, content type and body of the example response of an operation.
+fn example_response(operation: &Value) -> (StatusCode, Option<String>, Option<String>) {
+    let responses = operation.get("responses").and_then(Value::as_object);
+    let mut codes: Vec<(u16, &Value)> = responses
+        .into_iter()
+        .flat_map(|rs| rs.iter())
+        .filter_map(|(code, resp)| code.parse().ok().map(|c| (c, resp)))
+        .collect();
+    codes.sort_by_key(|&(code, _)| (code / 100 != 2, code));
+
+    let (code, response) = match codes.first() {
+        Some(&(code, response)) => (code, response),
+        None => return (StatusCode::Ok, None, None),
+    };
+    let status = StatusCode::try_from(code).unwrap_or(StatusCode::Ok);
+
+    let content = match response.get("content").and_then(Value::as_object) {
+        Some(content) => content,
+        None => return (status, None, None),
+    };
+    let (content_type, media) = match content.get("application/json") {
+        Some(media) => ("application/json", media),
+        None => {
+            match content.iter().next() {
+                Some((ct, media)) => (ct.as_str(), media),
+                None => return (status, None, None),
+            }
+        }
+    };
+
+    let example = media
+        .get("example")
+        .or_else(|| {
+            media
+                .get("examples")
+                .and_then(Value::as_object)
+                .and_then(|examples: &Map<String, Value>| examples.values().next())
+                .and_then(|e| e.get("value"))
+        })
+        .or_else(|| media.get("schema").and_then(|s| s.get("example")));
+    let body = example.map(|e| match *e {
+        Value::String(ref s) if !content_type.contains("json") => s.clone(),
+        ref other => other.to_string(),
+    });
+
+    (status, Some(content_type.to_string()), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{Client, StubSettings};
+
+    #[test]
+    fn stub_openapi() {
+        let spec = json!({
+            "paths": {
+                "/pets/{id}": {
+                    "parameters": [{"name": "id", "in": "path", "example": 7}],
+                    "get": {
+                        "responses": {
+                            "404": {"description": "not found"},
+                            "200": {
+                                "content": {
+                                    "application/json": {"example": {"name": "Rex"}}
+                                }
+                            }
+                        }
+                    },
+                    "delete": {"responses": {"204": {"description": "deleted"}}}
+                },
+                "/owners/{name}": {
+                    "get": {
+                        "parameters": [{"name": "name", "in": "path"}],
+                        "responses": {"200": {"description": "owner"}}
+                    }
+                }
+            }
+        });
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        let base_url = Url::parse("https://example.com/api/").unwrap();
+
+        // The owner has no example for its path parameter and is skipped.
+        assert_eq!(client.stub_openapi(&base_url, &spec).unwrap(), 2);
+
+        let response = client.get("https://example.com/api/pets/7").send().unwrap();
+        assert_eq!(response.status, StatusCode::Ok);
+        assert_eq!(
+            response.headers.get_raw("Content-Type").unwrap().one(),
+            Some(&b"application/json"[..])
+        );
+        assert_eq!(response.body_to_utf8().unwrap(), r#"{"name":"Rex"}"#);
+
+        let response = client.delete("https://example.com/api/pets/7").send().unwrap();
+        assert_eq!(response.status, StatusCode::NoContent);
+        assert!(client.get("https://example.com/api/owners/jane").send().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use client::stub::{StubClient, StubStrictness};
+use error::{Error, ErrorKind};
+use reqwest::header::Headers;
+use reqwest::{Method, Url};
+use serde_json::Value;
+
+/// How the pages of an endpoint stubbed with `StubClient::stub_paginated` are addressed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pagination {
+    /// Pages are selected by the query parameters `page` (starting at 1) and `limit`. Every page
+    /// is a JSON array of items, with a `Link` header pointing at the next page.
+    PageLimit,
+
+    /// Pages are selected by the opaque `cursor` query parameter. Every page is a JSON object
+    /// holding the items in `items` and the cursor of the next page (or `null`) in
+    /// `next_cursor`.
+    Cursor,
+}
+
+impl StubClient {
+    /// Register stubs serving `items` in pages of `page_size` items from `url`, so code following
+    /// pagination can be tested against datasets of any size.
+    ///
+    /// The first page is also served for `url` itself. Requests are expected to use the `GET`
+    /// method, which requires `StubStrictness::MethodUrl` or `StubStrictness::Url`.
+    ///
+    /// Returns the number of pages.
+    pub fn stub_paginated(
+        &mut self,
+        url: &Url,
+        items: &[Value],
+        page_size: usize,
+        pagination: Pagination,
+    ) -> Result<usize, Error> {
+        let with_method = match self.settings.strictness {
+            StubStrictness::MethodUrl => true,
+            StubStrictness::Url => false,
+            ref other => {
+                return Err(
+                    ErrorKind::Configuration(format!(
+                        "can't generate pagination stubs with {:?}, use MethodUrl or Url",
+                        other
+                    )).into(),
+                )
+            }
+        };
+        if page_size == 0 {
+            return Err(ErrorKind::Configuration("page size must not be 0".to_string()).into());
+        }
+
+        let pages: Vec<&[Value]> = if items.is_empty() {
+            vec![items]
+        } else {
+            items.chunks(page_size).collect()
+        };
+
+        for (i, page) in pages.iter().enumerate() {
+            let page_url = |i: usize| {
+                let mut page_url = url.clone();
+                match pagination {
+                    Pagination::PageLimit => {
+                        page_url
+                            .query_pairs_mut()
+                            .append_pair("page", &(i + 1).to_string())
+                            .append_pair("limit", &page_size.to_string());
+                    }
+                    Pagination::Cursor => {
+                        page_url.query_pairs_mut().append_pair("cursor", &cursor(i));
+                    }
+                }
+                page_url
+            };
+            let has_next = i + 1 < pages.len();
+
+            let mut headers = Headers::new();
+            headers.set_raw("Content-Type", "application/json");
+            let body = match pagination {
+                Pagination::PageLimit => {
+                    if has_next {
+                        headers.set_raw("Link", format!("<{}>; rel=\"next\"", page_url(i + 1)));
+                    }
+                    Value::Array(page.to_vec())
+                }
+                Pagination::Cursor => {
+                    json!({
+                        "items": page.to_vec(),
+                        "next_cursor": if has_next { Some(cursor(i + 1)) } else { None },
+                    })
+                }
+            };
+
+            let mut urls = vec![page_url(i)];
+            if i == 0 {
+                urls.push(url.clone());
+            }
+            for stub_url in urls {
+                let mut request = self.stub(stub_url);
+                if with_method {
+                    request = request.method(Method::Get);
+                }
+                request
+                    .response()
+                    .headers(headers.clone())
+                    .body(body.to_string())
+                    .mock();
+            }
+        }
+
+        Ok(pages.len())
+    }
+}
+
+/// The cursor of the page with the index.
+fn cursor(index: usize) -> String {
+    format!("page{}", index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{Client, StubSettings};
+    use header_ext::HeadersExt;
+
+    #[test]
+    fn page_limit() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/items").unwrap();
+        let items: Vec<Value> = (0..5).map(Value::from).collect();
+        assert_eq!(
+            client.stub_paginated(&url, &items, 2, Pagination::PageLimit).unwrap(),
+            3
+        );
+
+        let first = client.get(url).send().unwrap();
+        assert_eq!(first.body_to_utf8().unwrap(), "[0,1]");
+        assert_eq!(
+            first.headers.get_str("Link"),
+            Some("<http://example.com/items?page=2&limit=2>; rel=\"next\"")
+        );
+
+        let last = client.get("http://example.com/items?page=3&limit=2").send().unwrap();
+        assert_eq!(last.body_to_utf8().unwrap(), "[4]");
+        assert_eq!(last.headers.get_str("Link"), None);
+    }
+}
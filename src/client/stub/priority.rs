@@ -0,0 +1,212 @@
+use client::stub::{Stub, StubClient, StubKey};
+use client::stub::negotiation::{self, Negotiation};
+use request::Request;
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+/// A stub matching a request, see `StubClient::candidates`.
+pub(super) struct Candidate<'a> {
+    pub key: &'a StubKey,
+    pub stub: &'a Stub,
+    negotiation: Option<&'a Negotiation>,
+
+    /// Lower ranks are more specific, see `StubClient::candidates`.
+    rank: usize,
+}
+
+impl StubClient {
+    /// Returns the stubs matching the request as its `attempt`, the one which is used first.
+    ///
+    /// Stubs are ordered by their priority, then by how specific they are and finally by the
+    /// order they were registered in. Stubs for the session of the request are more specific
+    /// than the ones for any session, stubs for the attempt more specific than the ones for any
+    /// attempt, stubs for the exact path more specific than the ones matching path segments and
+    /// negotiated stubs more specific than the ones without negotiation.
+    pub(super) fn candidates(&self, request: &Request, attempt: usize) -> Vec<Candidate> {
+        let key = self.stub_key(request);
+        let mut sessions = vec![None];
+        if let Some(session) = self.session(request) {
+            sessions.insert(0, Some(session));
+        }
+
+        let mut candidates = Vec::new();
+        let mut rank = 0;
+        for session in sessions {
+            for &attempt in &[Some(attempt), None] {
+                let mut key = key.clone();
+                key.session = session.clone();
+                key.attempt = attempt;
+
+                if let Some((key, variants)) = self.negotiated.get_key_value(&key) {
+                    let negotiations: Vec<Negotiation> =
+                        variants.iter().map(|v| v.0.clone()).collect();
+                    if let Some(i) = negotiation::choose(&request.headers, &negotiations) {
+                        candidates.push(Candidate {
+                            key: key,
+                            stub: &variants[i].1,
+                            negotiation: Some(&variants[i].0),
+                            rank: rank,
+                        });
+                    }
+                }
+                if let Some((key, stub)) = self.stubs.get_key_value(&key) {
+                    candidates.push(Candidate {
+                        key: key,
+                        stub: stub,
+                        negotiation: None,
+                        rank: rank + 1,
+                    });
+                }
+
+                // Stubs matching path segments are only found by trying them all.
+                for (pattern, variants) in &self.negotiated {
+                    if !pattern.matches_segments(&key) {
+                        continue;
+                    }
+                    let negotiations: Vec<Negotiation> =
+                        variants.iter().map(|v| v.0.clone()).collect();
+                    if let Some(i) = negotiation::choose(&request.headers, &negotiations) {
+                        candidates.push(Candidate {
+                            key: pattern,
+                            stub: &variants[i].1,
+                            negotiation: Some(&variants[i].0),
+                            rank: rank + 2,
+                        });
+                    }
+                }
+                for (pattern, stub) in &self.stubs {
+                    if pattern.matches_segments(&key) {
+                        candidates.push(Candidate {
+                            key: pattern,
+                            stub: stub,
+                            negotiation: None,
+                            rank: rank + 3,
+                        });
+                    }
+                }
+                rank += 4;
+            }
+        }
+
+        candidates.sort_by_key(|c| (Reverse(c.stub.priority), c.rank, c.stub.order));
+        candidates
+    }
+
+    /// Returns the attempt the request would be if it was executed now.
+    pub(super) fn next_attempt(&self, request: &Request) -> usize {
+        let key = self.stub_key(request);
+        self.attempts.lock().unwrap().get(&key).cloned().unwrap_or(0) + 1
+    }
+
+    /// Lists all registered stubs by their priority and then the order they were registered in.
+    ///
+    /// For the stubs matching the same request, stubs for a session or attempt and negotiated
+    /// stubs are preferred over the general ones if their priority is equal, use `explain` to
+    /// find out which stub is used for a specific request.
+    pub fn matching_order(&self) -> Vec<String> {
+        let mut stubs: Vec<(&StubKey, &Stub, Option<&Negotiation>)> = self.stubs
+            .iter()
+            .map(|(key, stub)| (key, stub, None))
+            .collect();
+        for (key, variants) in &self.negotiated {
+            stubs.extend(variants.iter().map(|v| (key, &v.1, Some(&v.0))));
+        }
+
+        stubs.sort_by_key(|s| (Reverse(s.1.priority), s.1.order));
+        stubs
+            .into_iter()
+            .map(|(key, stub, negotiation)| describe(key, stub, negotiation))
+            .collect()
+    }
+
+    /// Explains which stubs match the request and which one of them would be used, in the
+    /// order they are tried. Useful to debug stubs shadowing each other.
+    pub fn explain(&self, request: &Request) -> String {
+        let attempt = self.next_attempt(request);
+        let candidates = self.candidates(request, attempt);
+
+        let mut explanation = String::new();
+        if candidates.is_empty() {
+            let _ = writeln!(
+                explanation,
+                "No stub matches the {} request of URL {}.",
+                request.method,
+                request.url
+            );
+        } else {
+            let _ = writeln!(
+                explanation,
+                "Stubs matching the {} request of URL {} (attempt {}):",
+                request.method,
+                request.url,
+                attempt
+            );
+        }
+        for (i, candidate) in candidates.iter().enumerate() {
+            let _ = writeln!(
+                explanation,
+                "{} {}",
+                if i == 0 { "used:    " } else { "shadowed:" },
+                describe(candidate.key, candidate.stub, candidate.negotiation)
+            );
+        }
+        explanation
+    }
+}
+
+fn describe(key: &StubKey, stub: &Stub, negotiation: Option<&Negotiation>) -> String {
+    let method = key.method.as_ref().map_or("*".to_string(), |m| m.to_string());
+    let mut description = format!(
+        "#{} {} {} (priority {})",
+        stub.order,
+        method,
+        key.url,
+        stub.priority
+    );
+    if let Some(ref session) = key.session {
+        let _ = write!(description, " session {}", session);
+    }
+    if let Some(attempt) = key.attempt {
+        let _ = write!(description, " attempt {}", attempt);
+    }
+    if let Some(ref segments) = key.segments {
+        let segments: Vec<String> = segments
+            .iter()
+            .map(|&(i, ref value)| format!("{}={}", i, value))
+            .collect();
+        let _ = write!(description, " segments {}", segments.join(","));
+    }
+    if let Some(negotiation) = negotiation {
+        let _ = write!(description, " {}: {}", negotiation.header, negotiation.value);
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{Client, StubSettings, StubStrictness};
+    use reqwest::Url;
+
+    #[test]
+    fn priority() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("http://example.com/").unwrap();
+        client.stub(url.clone()).attempt(1).response().body("first").mock();
+        client.stub(url.clone()).priority(1).response().body("general").mock();
+
+        assert_eq!(client.matching_order().len(), 2);
+        assert!(client.matching_order()[0].contains("(priority 1)"));
+
+        let request = client.get(url.clone()).build().unwrap();
+        let explanation = client.explain(&request);
+        assert!(explanation.contains("used:     #1"));
+        assert!(explanation.contains("shadowed: #0"));
+
+        let response = client.get(url).send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "general");
+    }
+}
@@ -0,0 +1,124 @@
+//! Serving byte ranges of stubbed bodies, see `ResponseStubber::serve_ranges`.
+
+use header_ext::HeadersExt;
+use request::Request;
+use reqwest::StatusCode;
+use response::Response;
+
+/// Turns the full response into the partial one requested by the `Range` header of the request.
+///
+/// Only single ranges are served, requests for multiple ranges get the full response, which
+/// servers are allowed to do. Unsatisfiable ranges are answered with
+/// `416 Range Not Satisfiable`.
+pub fn serve(response: &mut Response, request: &Request) {
+    response.headers.set_raw("Accept-Ranges", "bytes");
+    if response.status != StatusCode::Ok {
+        return;
+    }
+    let range = match request.headers.get_str("Range") {
+        Some(range) => range,
+        None => return,
+    };
+
+    let len = response.body.len() as u64;
+    match parse(range, len) {
+        Some(Some((start, end))) => {
+            response.status = StatusCode::PartialContent;
+            response.headers.set_raw(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, len),
+            );
+            response.body = response.body.slice(start as usize, end as usize + 1);
+        }
+        Some(None) => {
+            response.status = StatusCode::RangeNotSatisfiable;
+            response.headers.set_raw("Content-Range", format!("bytes */{}", len));
+            response.body.clear();
+        }
+        None => return,
+    }
+    response.headers.set_raw("Content-Length", response.body.len().to_string());
+}
+
+/// Parses a `Range` header for a body of `len` bytes into the inclusive range to serve.
+///
+/// Returns `None` if the header can't be served as a single range and `Some(None)` if the range
+/// isn't satisfiable.
+fn parse(range: &str, len: u64) -> Option<Option<(u64, u64)>> {
+    let spec = range.trim();
+    if !spec.starts_with("bytes=") || spec.contains(',') {
+        return None;
+    }
+    let mut bounds = spec["bytes=".len()..].splitn(2, '-');
+    let (first, last) = (bounds.next()?.trim(), bounds.next()?.trim());
+
+    let range = match (first.is_empty(), last.is_empty()) {
+        // `bytes=-500`: the last 500 bytes.
+        (true, false) => {
+            let suffix: u64 = last.parse().ok()?;
+            if suffix == 0 {
+                None
+            } else {
+                Some((len.saturating_sub(suffix), len.saturating_sub(1)))
+            }
+        }
+        // `bytes=500-`: everything from byte 500.
+        (false, true) => Some((first.parse().ok()?, len.saturating_sub(1))),
+        (false, false) => {
+            let (start, end): (u64, u64) = (first.parse().ok()?, last.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            Some((start, end.min(len.saturating_sub(1))))
+        }
+        (true, true) => return None,
+    };
+    Some(range.filter(|&(start, _)| start < len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use reqwest::header::Headers;
+    use reqwest::{Method, Url};
+
+    #[test]
+    fn parse() {
+        assert_eq!(super::parse("bytes=0-99", 1000), Some(Some((0, 99))));
+        assert_eq!(super::parse("bytes=900-", 1000), Some(Some((900, 999))));
+        assert_eq!(super::parse("bytes=-100", 1000), Some(Some((900, 999))));
+        assert_eq!(super::parse("bytes=990-2000", 1000), Some(Some((990, 999))));
+        assert_eq!(super::parse("bytes=1000-", 1000), Some(None));
+        assert_eq!(super::parse("bytes=0-1,5-6", 1000), None);
+        assert_eq!(super::parse("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn serve() {
+        let url = Url::parse("http://example.com/file").unwrap();
+        let mut request = Request {
+            url: url.clone(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        request.headers.set_raw("Range", "bytes=2-4");
+        let mut response = Response {
+            url: url,
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from("0123456789"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+
+        super::serve(&mut response, &request);
+        assert_eq!(response.status, StatusCode::PartialContent);
+        assert_eq!(response.body, Bytes::from("234"));
+        assert_eq!(response.headers.get_str("Content-Range"), Some("bytes 2-4/10"));
+    }
+}
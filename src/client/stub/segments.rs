@@ -0,0 +1,61 @@
+use client::stub::StubKey;
+
+impl StubKey {
+    /// Returns true if this is the key of a stub matching path segments, see
+    /// `RequestStubber::path_segment`, and it matches the key of a request.
+    pub(super) fn matches_segments(&self, key: &StubKey) -> bool {
+        let segments = match self.segments {
+            Some(ref segments) => segments,
+            None => return false,
+        };
+        let same_origin = self.url.scheme() == key.url.scheme() &&
+            self.url.host_str() == key.url.host_str() &&
+            self.url.port_or_known_default() == key.url.port_or_known_default();
+        let same_request = self.url.query() == key.url.query() && self.method == key.method &&
+            self.body == key.body && self.headers == key.headers &&
+            self.session == key.session && self.attempt == key.attempt;
+        if !same_origin || !same_request {
+            return false;
+        }
+
+        let pattern = self.url.path_segments().map_or(0, |s| s.count());
+        let path: Vec<&str> = key.url.path_segments().map_or_else(Vec::new, |s| s.collect());
+        path.len() == pattern &&
+            segments.iter().all(
+                |&(i, ref value)| path.get(i) == Some(&value.as_str()),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::{Client, StubClient, StubSettings, StubStrictness};
+    use reqwest::Url;
+
+    #[test]
+    fn path_segment() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::Url,
+            ..StubSettings::default()
+        });
+        client
+            .stub(Url::parse("http://example.com/users/_/posts").unwrap())
+            .path_segment(0, "users")
+            .path_segment(2, "posts")
+            .response()
+            .body("posts of {{path.1}}")
+            .templated()
+            .mock();
+        client
+            .stub(Url::parse("http://example.com/users/1/posts").unwrap())
+            .response()
+            .body("exact")
+            .mock();
+
+        let body = |url: &str| client.get(url).send().map(|r| r.body_to_utf8().unwrap());
+        assert_eq!(body("http://example.com/users/42/posts").unwrap(), "posts of 42");
+        assert_eq!(body("http://example.com/users/1/posts").unwrap(), "exact");
+        assert!(body("http://example.com/users/42/likes").is_err());
+        assert!(body("http://example.com/users/42/posts/1").is_err());
+    }
+}
@@ -33,10 +33,21 @@ pub enum StubDefault {
     /// Panic if such a request is made.
     Panic,
 
-    /// Return an `Err` if such a request is made.
+    /// Return an `Err` if such a request is made, or panic if the `failure_style` of the config
+    /// is `FailureStyle::Panic`.
     Error,
 }
 
+/// Identifies the session of a request, see `StubClient::session_affinity`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SessionAffinity {
+    /// The value of the cookie with this name.
+    Cookie(String),
+
+    /// The value of the header with this name.
+    Header(String),
+}
+
 /// Some settings for the `StubClient`.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StubSettings {
@@ -0,0 +1,93 @@
+//! Rendering of templated stub responses, see `ResponseStubber::templated`.
+
+use request::Request;
+use serde_json::Value;
+
+/// Replaces the `{{...}}` placeholders in the template by the parts of the request they refer
+/// to, see `ResponseStubber::templated` for the supported placeholders.
+pub fn render(template: &str, request: &Request) -> String {
+    let json = request.body.as_ref().and_then(
+        |body| ::serde_json::from_slice(body).ok(),
+    );
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        rendered.push_str(&rest[..start]);
+        let expression = rest[start + 2..end].trim();
+        rendered.push_str(&lookup(expression, request, json.as_ref()).unwrap_or_default());
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Returns the value of a single placeholder, `None` if the request doesn't contain it.
+fn lookup(expression: &str, request: &Request, json: Option<&Value>) -> Option<String> {
+    let mut parts = expression.split('.');
+    match parts.next() {
+        Some("method") => Some(request.method.to_string()),
+        Some("url") => Some(request.url.to_string()),
+        Some("body") => {
+            request.body.as_ref().map(
+                |body| String::from_utf8_lossy(body).into_owned(),
+            )
+        }
+        Some("path") => {
+            let index: usize = parts.next()?.parse().ok()?;
+            request.url.path_segments()?.nth(index).map(
+                |s| s.to_string(),
+            )
+        }
+        Some("query") => request.query(parts.next()?),
+        Some("json") => {
+            if parts.next() != Some("body") {
+                return None;
+            }
+            let mut value = json?;
+            for part in parts {
+                value = match *value {
+                    Value::Array(ref items) => items.get(part.parse::<usize>().ok()?)?,
+                    _ => value.get(part)?,
+                };
+            }
+            match *value {
+                Value::String(ref s) => Some(s.clone()),
+                ref other => Some(other.to_string()),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use reqwest::header::Headers;
+    use reqwest::{Method, Url};
+
+    #[test]
+    fn render_captures() {
+        let request = Request {
+            url: Url::parse("https://example.com/api/users/42?id=7").unwrap(),
+            method: Method::Post,
+            headers: Headers::new(),
+            body: Some(Bytes::from(&br#"{"name": "Ann", "tags": ["a", "b"], "age": 30}"#[..])),
+        };
+
+        assert_eq!(
+            render("{{method}} {{path.2}} {{query.id}} {{ json.body.name }}", &request),
+            "POST 42 7 Ann"
+        );
+        assert_eq!(
+            render("{{json.body.tags.1}} {{json.body.age}} {{json.body.tags}}", &request),
+            r#"b 30 ["a","b"]"#
+        );
+        assert_eq!(render("[{{query.missing}}] {{path", &request), "[] {{path");
+    }
+}
@@ -0,0 +1,343 @@
+use client::Client;
+use config::ClientConfig;
+use error::{Error, ErrorKind, ResultExt};
+use jsonschema::JSONSchema;
+use request::Request;
+use reqwest::{Method, StatusCode, Url};
+use response::Response;
+use serde_json::Value;
+use std::str::FromStr;
+
+struct SchemaRule {
+    method: Method,
+    /// The URL path, possibly containing `{name}` placeholders.
+    path: String,
+    /// The documented status, `None` for any status.
+    status: Option<StatusCode>,
+    schema: Option<JSONSchema>,
+}
+
+/// Wraps another `Client` and validates the JSON bodies of its responses against schemas.
+///
+/// This is a lightweight form of consumer-driven contract testing: regardless of whether the
+/// responses are live, replayed or stubbed they have to adhere to the documented schema, otherwise
+/// an `ErrorKind::SchemaViolation` error is returned listing all the violations. Responses to a
+/// documented operation with a status which isn't documented are violations as well.
+///
+/// Only available with the `json-schema` feature enabled.
+pub struct ValidatingClient<C: Client> {
+    inner: C,
+    rules: Vec<SchemaRule>,
+}
+
+impl<C: Client> ValidatingClient<C> {
+    /// Create a new `ValidatingClient` wrapping the provided client, without any schemas yet.
+    pub fn new(inner: C) -> Self {
+        ValidatingClient {
+            inner: inner,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Validate the responses to requests with the provided method and URL path against the
+    /// provided JSON schema, regardless of their status.
+    ///
+    /// The path can contain `{name}` placeholders like the paths of an OpenAPI specification,
+    /// which match any non-empty part of a path segment.
+    pub fn schema(&mut self, method: Method, path: &str, schema: &Value) -> Result<(), Error> {
+        self.add_rule(method, path, None, Some(schema))
+    }
+
+    /// Validate the responses with the provided status to requests with the provided method and
+    /// URL path against the provided JSON schema, see `schema`.
+    pub fn response_schema(
+        &mut self,
+        method: Method,
+        path: &str,
+        status: StatusCode,
+        schema: &Value,
+    ) -> Result<(), Error> {
+        self.add_rule(method, path, Some(status), Some(schema))
+    }
+
+    /// Validate the responses to all operations of an OpenAPI 3 specification (in its JSON form)
+    /// against the schemas of their `application/json` content.
+    ///
+    /// The paths of the specification are relative to `base_url`. The `default` response
+    /// documents all statuses, otherwise responses with undocumented statuses are violations.
+    /// References (`$ref`) of the schemas aren't resolved.
+    ///
+    /// Returns the number of operations.
+    pub fn openapi(&mut self, base_url: &Url, spec: &Value) -> Result<usize, Error> {
+        let paths = match spec.get("paths").and_then(Value::as_object) {
+            Some(paths) => paths,
+            None => {
+                return Err(
+                    ErrorKind::Configuration("OpenAPI specification has no `paths`".to_string())
+                        .into(),
+                )
+            }
+        };
+        let base_path = base_url.path().trim_end_matches('/');
+
+        let mut count = 0;
+        for (path, item) in paths {
+            let operations = item.as_object().into_iter().flat_map(|ops| ops.iter());
+            for (name, operation) in operations {
+                // Besides operations a path item contains parameters, a summary etc.
+                let responses = match operation.get("responses").and_then(Value::as_object) {
+                    Some(responses) => responses,
+                    None => continue,
+                };
+                let method = Method::from_str(&name.to_uppercase()).chain_err(|| {
+                    ErrorKind::Configuration(format!("invalid method {:?} in OpenAPI", name))
+                })?;
+                let path = format!("{}{}", base_path, path);
+
+                for (code, response) in responses {
+                    let status = match code.as_str() {
+                        "default" => None,
+                        code => {
+                            let status = code.parse().ok().and_then(
+                                |c| StatusCode::try_from(c).ok(),
+                            );
+                            match status {
+                                Some(status) => Some(status),
+                                None => {
+                                    warn!("Skipping unsupported response {} of {}", code, path);
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    let schema = response
+                        .get("content")
+                        .and_then(|c| c.get("application/json"))
+                        .and_then(|media| media.get("schema"));
+                    self.add_rule(method.clone(), &path, status, schema)?;
+                }
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn add_rule(
+        &mut self,
+        method: Method,
+        path: &str,
+        status: Option<StatusCode>,
+        schema: Option<&Value>,
+    ) -> Result<(), Error> {
+        let schema = match schema {
+            Some(schema) => {
+                Some(JSONSchema::compile(schema).map_err(|e| {
+                    Error::from(format!("invalid JSON schema: {}", e))
+                })?)
+            }
+            None => None,
+        };
+
+        self.rules.push(SchemaRule {
+            method: method,
+            path: path.to_string(),
+            status: status,
+            schema: schema,
+        });
+        Ok(())
+    }
+
+    /// Returns the wrapped client.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Client> Client for ValidatingClient<C> {
+    fn execute(&self, config: Option<&ClientConfig>, request: Request) -> Result<Response, Error> {
+        let method = request.method.clone();
+        let path = request.url.path().to_string();
+        let response = self.inner.execute(config, request)?;
+
+        let rules: Vec<&SchemaRule> = self.rules
+            .iter()
+            .filter(|r| r.method == method && path_matches(&r.path, &path))
+            .collect();
+        if rules.is_empty() {
+            return Ok(response);
+        }
+
+        let documented: Vec<&SchemaRule> = rules
+            .into_iter()
+            .filter(|r| r.status.as_ref().map_or(true, |s| *s == response.status))
+            .collect();
+        if documented.is_empty() {
+            let violation = format!("undocumented status {}", response.status);
+            return Err(
+                ErrorKind::SchemaViolation(response.url.to_string(), vec![violation]).into(),
+            );
+        }
+
+        for schema in documented.iter().filter_map(|r| r.schema.as_ref()) {
+            let body: Value = ::serde_json::from_slice(&response.body)?;
+            let violations: Vec<String> = match schema.validate(&body) {
+                Ok(()) => continue,
+                Err(errors) => {
+                    errors
+                        .map(|e| format!("{}: {}", e.instance_path, e))
+                        .collect()
+                }
+            };
+
+            return Err(
+                ErrorKind::SchemaViolation(response.url.to_string(), violations).into(),
+            );
+        }
+
+        Ok(response)
+    }
+
+    fn config(&self) -> &ClientConfig {
+        self.inner.config()
+    }
+
+    fn config_mut(&mut self) -> &mut ClientConfig {
+        self.inner.config_mut()
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.inner.requests()
+    }
+}
+
+/// Checks whether a URL path matches a path which can contain `{name}` placeholders.
+fn path_matches(template: &str, path: &str) -> bool {
+    let templates: Vec<&str> = template.split('/').collect();
+    let segments: Vec<&str> = path.split('/').collect();
+    templates.len() == segments.len() &&
+        templates.iter().zip(segments).all(
+            |(t, s)| segment_matches(t, s),
+        )
+}
+
+fn segment_matches(template: &str, segment: &str) -> bool {
+    let start = match template.find('{') {
+        Some(start) => start,
+        None => return template == segment,
+    };
+    let end = match template[start..].find('}') {
+        Some(end) => start + end,
+        None => return template == segment,
+    };
+    if !segment.starts_with(&template[..start]) {
+        return false;
+    }
+
+    // A placeholder matches at least one character.
+    let rest = &template[end + 1..];
+    let segment = &segment[start..];
+    (1..segment.len() + 1).any(|i| {
+        segment.is_char_boundary(i) && segment_matches(rest, &segment[i..])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{StubClient, StubSettings, StubStrictness};
+
+    fn stubbed(status: StatusCode, body: &str) -> StubClient {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("https://example.com/api/pets/7").unwrap();
+        client
+            .stub(url)
+            .method(Method::Get)
+            .response()
+            .status_code(status)
+            .body(body)
+            .mock();
+        client
+    }
+
+    fn spec() -> Value {
+        json!({
+            "paths": {
+                "/pets/{id}": {
+                    "parameters": [{"name": "id", "in": "path"}],
+                    "get": {
+                        "responses": {
+                            "200": {
+                                "content": {
+                                    "application/json": {
+                                        "schema": {
+                                            "type": "object",
+                                            "required": ["name"],
+                                            "properties": {"name": {"type": "string"}}
+                                        }
+                                    }
+                                }
+                            },
+                            "404": {"description": "not found"}
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn validating(inner: StubClient) -> ValidatingClient<StubClient> {
+        let mut client = ValidatingClient::new(inner);
+        let base_url = Url::parse("https://example.com/api/").unwrap();
+        assert_eq!(client.openapi(&base_url, &spec()).unwrap(), 1);
+        client
+    }
+
+    #[test]
+    fn valid_response() {
+        let client = validating(stubbed(StatusCode::Ok, r#"{"name": "Rex"}"#));
+        let response = client.get("https://example.com/api/pets/7").send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), r#"{"name": "Rex"}"#);
+
+        let client = validating(stubbed(StatusCode::NotFound, ""));
+        client.get("https://example.com/api/pets/7").send().unwrap();
+    }
+
+    #[test]
+    fn schema_violation() {
+        let client = validating(stubbed(StatusCode::Ok, r#"{"name": 7}"#));
+        let err = client.get("https://example.com/api/pets/7").send().unwrap_err();
+        match *err.kind() {
+            ErrorKind::SchemaViolation(ref url, ref violations) => {
+                assert_eq!(url, "https://example.com/api/pets/7");
+                assert_eq!(violations.len(), 1);
+                assert!(violations[0].starts_with("/name: "));
+            }
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn undocumented_status() {
+        let client = validating(stubbed(StatusCode::InternalServerError, "oops"));
+        let err = client.get("https://example.com/api/pets/7").send().unwrap_err();
+        match *err.kind() {
+            ErrorKind::SchemaViolation(_, ref violations) => {
+                assert_eq!(violations, &vec!["undocumented status 500 Internal Server Error"]);
+            }
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn templated_paths() {
+        assert!(path_matches("/pets/{id}", "/pets/7"));
+        assert!(path_matches("/pets/{id}.json", "/pets/7.json"));
+        assert!(!path_matches("/pets/{id}", "/pets/"));
+        assert!(!path_matches("/pets/{id}", "/pets/7/toys"));
+        assert!(!path_matches("/pets/{id}.json", "/pets/7.xml"));
+    }
+}
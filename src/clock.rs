@@ -0,0 +1,79 @@
+//! A source of time, which can be mocked in tests.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The clock used by a `Client` whenever it has to wait or measure time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Clock {
+    /// The real system clock, sleeping actually blocks the current thread.
+    System,
+
+    /// A clock which only advances when it is slept on, so tests don't have to wait.
+    Mock(MockClock),
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::System
+    }
+}
+
+impl Clock {
+    /// Shorthand to create a new `Clock::Mock`.
+    pub fn mock() -> Self {
+        Clock::Mock(MockClock::new())
+    }
+
+    /// Returns the current instant of this clock.
+    pub fn now(&self) -> Instant {
+        match *self {
+            Clock::System => Instant::now(),
+            Clock::Mock(ref mock) => mock.start + mock.elapsed(),
+        }
+    }
+
+    /// Wait for the provided duration.
+    pub fn sleep(&self, duration: Duration) {
+        match *self {
+            Clock::System => thread::sleep(duration),
+            Clock::Mock(ref mock) => mock.advance(duration),
+        }
+    }
+}
+
+/// A clock which doesn't advance on its own.
+///
+/// Clones of a `MockClock` share their time, so you can keep a clone around to check how long
+/// the client would have waited.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::from_secs(0))),
+        }
+    }
+
+    /// Returns the total time this clock was advanced by.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Advance the clock by the provided duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl PartialEq for MockClock {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.elapsed, &other.elapsed)
+    }
+}
@@ -0,0 +1,218 @@
+//! Content codings for compressing request bodies, see `RequestBuilder::compress`, and
+//! decompressing response bodies.
+
+use bytes::Bytes;
+use config::ClientConfig;
+use error::Error;
+use header_ext::HeadersExt;
+use response::Response;
+use std::io::{Cursor, Read};
+
+/// A content coding, as named by the `Content-Encoding` header.
+///
+/// Brotli and zstd require the `brotli` and `zstd` features.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// `gzip`
+    Gzip,
+
+    /// `deflate`, i. e. the zlib format.
+    Deflate,
+
+    /// `br`
+    #[cfg(feature = "brotli")]
+    Brotli,
+
+    /// `zstd`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression for the value of a `Content-Encoding` header, if it is supported.
+    pub fn from_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Compression::Gzip),
+            "deflate" => Some(Compression::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(Compression::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `Content-Encoding` header for this compression.
+    pub fn encoding(&self) -> &'static str {
+        match *self {
+            Compression::Gzip => "gzip",
+            Compression::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Returns a reader compressing the data read from `reader`.
+    ///
+    /// The output is deterministic, e.g. the gzip header doesn't contain a timestamp, so
+    /// compressed bodies can be recorded and matched.
+    pub fn encoder<R>(&self, reader: R) -> Result<Box<dyn Read + Send>, Error>
+    where
+        R: Read + Send + 'static,
+    {
+        use flate2::read::{GzEncoder, ZlibEncoder};
+
+        let level = ::flate2::Compression::default();
+        Ok(match *self {
+            Compression::Gzip => Box::new(GzEncoder::new(reader, level)),
+            Compression::Deflate => Box::new(ZlibEncoder::new(reader, level)),
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => Box::new(::brotli::CompressorReader::new(reader, 4096, 5, 22)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(::zstd::stream::read::Encoder::new(reader, 0)?),
+        })
+    }
+
+    /// Returns a reader decompressing the data read from `reader`.
+    pub fn decoder<R>(&self, reader: R) -> Result<Box<dyn Read + Send>, Error>
+    where
+        R: Read + Send + 'static,
+    {
+        use flate2::read::{GzDecoder, ZlibDecoder};
+
+        Ok(match *self {
+            Compression::Gzip => Box::new(GzDecoder::new(reader)),
+            Compression::Deflate => Box::new(ZlibDecoder::new(reader)),
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => Box::new(::brotli::Decompressor::new(reader, 4096)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Box::new(::zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+
+    /// Compresses the data.
+    pub fn compress(&self, data: &[u8]) -> Result<Bytes, Error> {
+        read_all(self.encoder(Cursor::new(data.to_vec()))?)
+    }
+
+    /// Decompresses the data.
+    pub fn decompress(&self, data: &[u8]) -> Result<Bytes, Error> {
+        read_all(self.decoder(Cursor::new(data.to_vec()))?)
+    }
+
+    /// Returns whether response bodies compressed this way are decompressed automatically
+    /// according to the config.
+    fn decompressed(&self, config: &ClientConfig) -> bool {
+        match *self {
+            Compression::Gzip => config.gzip,
+            Compression::Deflate => false,
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => config.brotli,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => config.zstd,
+        }
+    }
+}
+
+/// Decompresses the body of the response if its `Content-Encoding` is decompressed
+/// automatically according to the config, removing the `Content-Encoding` and `Content-Length`
+/// headers like reqwest does for gzip.
+///
+/// Live gzip responses are already decompressed by reqwest, this handles the other encodings
+/// and stubbed responses, so stubs can serve compressed bodies.
+pub(crate) fn decompress_response(
+    config: &ClientConfig,
+    response: &mut Response,
+) -> Result<(), Error> {
+    let compression = response.headers.get_str("Content-Encoding").and_then(
+        Compression::from_encoding,
+    );
+    match compression {
+        Some(compression) if compression.decompressed(config) && !response.body.is_empty() => {
+            response.body = compression.decompress(&response.body)?;
+            response.headers.remove_raw("Content-Encoding");
+            response.headers.remove_raw("Content-Length");
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn read_all(mut reader: Box<dyn Read + Send>) -> Result<Bytes, Error> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(Bytes::from(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roundtrip(compression: Compression) {
+        let data = b"reqwest_mock reqwest_mock reqwest_mock";
+        let compressed = compression.compress(data).unwrap();
+        assert_ne!(&compressed[..], &data[..]);
+        assert_eq!(compressed, compression.compress(data).unwrap());
+        assert_eq!(&compression.decompress(&compressed).unwrap()[..], &data[..]);
+        assert_eq!(
+            Compression::from_encoding(compression.encoding()),
+            Some(compression)
+        );
+    }
+
+    /// Stubs a compressed response, which is decompressed unless `disable` turns that off.
+    fn assert_stub_decompressed<F: Fn(&mut ClientConfig)>(compression: Compression, disable: F) {
+        use client::{Client, StubClient, StubSettings};
+        use reqwest::Url;
+        use reqwest::header::Headers;
+
+        let mut client = StubClient::new(StubSettings::default());
+        let url = Url::parse("http://example.com/compressed").unwrap();
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Encoding", compression.encoding());
+        client
+            .stub(url.clone())
+            .response()
+            .headers(headers)
+            .body(compression.compress(b"decompressed").unwrap())
+            .mock();
+
+        let response = client.get(url.clone()).send().unwrap();
+        assert_eq!(response.body_to_utf8().unwrap(), "decompressed");
+        assert!(response.headers.get_raw("Content-Encoding").is_none());
+
+        disable(client.config_mut());
+        let response = client.get(url).send().unwrap();
+        assert_eq!(
+            response.headers.get_str("Content-Encoding"),
+            Some(compression.encoding())
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        assert_roundtrip(Compression::Gzip);
+        assert_roundtrip(Compression::Deflate);
+    }
+
+    #[test]
+    fn stubbed_response() {
+        assert_stub_decompressed(Compression::Gzip, |config| config.gzip = false);
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn brotli() {
+        assert_roundtrip(Compression::Brotli);
+        assert_stub_decompressed(Compression::Brotli, |config| config.brotli = false);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd() {
+        assert_roundtrip(Compression::Zstd);
+        assert_stub_decompressed(Compression::Zstd, |config| config.zstd = false);
+    }
+}
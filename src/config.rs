@@ -1,15 +1,35 @@
 //! Some types used to configure a `Client` instance.
 
+use clock::Clock;
+use error::Error;
+use metrics::Metrics;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 /// Configures some parameters for a `Client` instance.
-#[derive(Clone, Debug)]
+///
+/// The config can be (de)serialized, e.g. as part of a `ReplayConfig`. Missing fields take their
+/// default value, `clock` and `metrics` are never (de)serialized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ClientConfig {
     /// Enable auto gzip decompression checking the `ContentEncoding` response header.
     ///
     /// Default is enabled.
     pub gzip: bool,
 
+    /// Enable auto brotli decompression checking the `Content-Encoding` response header.
+    ///
+    /// Default is enabled.
+    #[cfg(feature = "brotli")]
+    pub brotli: bool,
+
+    /// Enable auto zstd decompression checking the `Content-Encoding` response header.
+    ///
+    /// Default is enabled.
+    #[cfg(feature = "zstd")]
+    pub zstd: bool,
+
     /// `RedirectPolicy` for this client.
     ///
     /// Default will follow up to 10 redirects.
@@ -23,16 +43,94 @@ pub struct ClientConfig {
     pub referer: bool,
 
     /// Timeout for both the read and write operations of a client.
+    ///
+    /// This applies to every single operation on the socket, so the request as a whole can take
+    /// longer than this. Use `total_timeout` to limit the duration of the whole request.
+    #[serde(with = "::duration::option")]
     pub timeout: Option<Duration>,
+
+    /// Deadline for connecting and receiving the response head.
+    ///
+    /// As reqwest 0.7 can't abort connecting, this is checked once the response head was
+    /// received, returning an `ErrorKind::TimedOut` error if the deadline was exceeded.
+    #[serde(with = "::duration::option")]
+    pub connect_timeout: Option<Duration>,
+
+    /// Maximum duration of waiting for a single chunk of the response body.
+    ///
+    /// It is checked whenever a chunk was read, returning an `ErrorKind::TimedOut` error without
+    /// reading the rest of the body if it took longer.
+    #[serde(with = "::duration::option")]
+    pub read_timeout: Option<Duration>,
+
+    /// Deadline for the whole request, including reading the response body.
+    ///
+    /// As a blocking request can't be aborted midway, this is checked once the response head was
+    /// received and whenever a chunk of the body was read, returning an `ErrorKind::TimedOut`
+    /// error without reading the rest of the body if the deadline was exceeded.
+    ///
+    /// Replayed responses whose recorded duration exceeds it result in the same error, so
+    /// timeout handling can be tested with recordings of slow responses.
+    #[serde(with = "::duration::option")]
+    pub total_timeout: Option<Duration>,
+
+    /// Automatically retry requests answered with `429 Too Many Requests` or
+    /// `503 Service Unavailable` after waiting for the duration of their `Retry-After` header.
+    ///
+    /// Default is `None`, i. e. such responses are returned as they are.
+    pub retry_after: Option<RetryAfterPolicy>,
+
+    /// Value of the `User-Agent` header sent with every request which doesn't set one itself.
+    ///
+    /// Default is `None`, i. e. the one of reqwest is used.
+    pub user_agent: Option<String>,
+
+    /// Headers sent with every request which doesn't set them itself.
+    ///
+    /// The `ReplayClient` annotates recorded requests with the names of the headers whose value
+    /// came from here, so diffs of replay files show whether a change came from the config or
+    /// the call site. Default is empty.
+    pub default_headers: BTreeMap<String, String>,
+
+    /// The clock used whenever the client has to wait.
+    ///
+    /// Default is `Clock::System`, use `Clock::mock()` in tests to avoid actually waiting.
+    #[serde(skip)]
+    pub clock: Clock,
+
+    /// Receives events about the performed requests.
+    ///
+    /// Default ignores all events.
+    #[serde(skip)]
+    pub metrics: Metrics,
+
+    /// Whether requests matching neither a stub nor a recorded request and failed verifications
+    /// panic or return an error.
+    ///
+    /// Default is `FailureStyle::Error`.
+    pub failure_style: FailureStyle,
 }
 
 impl Default for ClientConfig {
     fn default() -> Self {
         ClientConfig {
             gzip: true,
+            #[cfg(feature = "brotli")]
+            brotli: true,
+            #[cfg(feature = "zstd")]
+            zstd: true,
             redirect: RedirectPolicy::default(),
             referer: true,
             timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            total_timeout: None,
+            retry_after: None,
+            user_agent: None,
+            default_headers: BTreeMap::new(),
+            clock: Clock::System,
+            metrics: Metrics::default(),
+            failure_style: FailureStyle::Error,
         }
     }
 }
@@ -45,8 +143,67 @@ impl ClientConfig {
     // TODO implement builder pattern
 }
 
+/// Specifies how surprises of the matching are surfaced, i.e. errors where
+/// `Error::is_fixture_problem` is true and failed verifications like
+/// `ReplayClient::verify_no_network`.
+///
+/// Recorded sequences of responses never run out, their last response is repeated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FailureStyle {
+    /// Panic with the error, failing the test right where the surprise happened.
+    Panic,
+
+    /// Return the error, so it can be handled or bubbled up by the test.
+    Error,
+}
+
+impl FailureStyle {
+    /// Returns the error, or panics with it for `FailureStyle::Panic`.
+    pub(crate) fn fail<T>(&self, error: Error) -> Result<T, Error> {
+        match *self {
+            FailureStyle::Panic => panic!("{}", error),
+            FailureStyle::Error => Err(error),
+        }
+    }
+
+    /// Like `fail` for errors of the result which are fixture problems, other results are
+    /// returned as they are.
+    pub(crate) fn check<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        result.or_else(|e| if e.is_fixture_problem() {
+            self.fail(e)
+        } else {
+            Err(e)
+        })
+    }
+}
+
+/// Specifies how requests asking to be retried using `Retry-After` are handled.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryAfterPolicy {
+    /// Maximum number of retries of a single request.
+    ///
+    /// Default is 3.
+    pub max_retries: u32,
+
+    /// Responses asking to wait longer than this are returned without retrying.
+    ///
+    /// Default is 60 seconds.
+    #[serde(with = "::duration")]
+    pub max_wait: Duration,
+}
+
+impl Default for RetryAfterPolicy {
+    fn default() -> Self {
+        RetryAfterPolicy {
+            max_retries: 3,
+            max_wait: Duration::from_secs(60),
+        }
+    }
+}
+
 /// Specifies how to hande redirects.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RedirectPolicy {
     Limit(usize),
     None,
@@ -58,6 +215,7 @@ impl Default for RedirectPolicy {
     }
 }
 
+#[cfg(feature = "client")]
 impl From<RedirectPolicy> for ::reqwest::RedirectPolicy {
     fn from(p: RedirectPolicy) -> Self {
         match p {
@@ -66,3 +224,31 @@ impl From<RedirectPolicy> for ::reqwest::RedirectPolicy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorKind;
+
+    fn unmatched() -> Result<(), Error> {
+        Err(ErrorKind::Unmatched("GET".to_string(), "http://example.com/".to_string()).into())
+    }
+
+    #[test]
+    fn failure_style_error() {
+        assert!(FailureStyle::Error.check(unmatched()).is_err());
+        assert!(FailureStyle::Error.check(Ok(())).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "no stub or recorded request matched")]
+    fn failure_style_panic() {
+        let _ = FailureStyle::Panic.check(unmatched());
+    }
+
+    #[test]
+    fn failure_style_panic_other_errors() {
+        let timeout: Result<(), Error> = Err(ErrorKind::TimedOut(Duration::from_secs(1)).into());
+        assert!(FailureStyle::Panic.check(timeout).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+/// A cookie set by a response, see `Response::cookies`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+
+    /// The attributes like `Path` or `HttpOnly` in the order they were specified, attributes
+    /// without value (flags) have `None` as value.
+    pub attributes: Vec<(String, Option<String>)>,
+}
+
+impl Cookie {
+    /// Parses the value of a `Set-Cookie` header, returns `None` if it has no `name=value` pair.
+    pub fn parse(header: &str) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let mut pair = parts.next()?.splitn(2, '=');
+        let name = pair.next()?.trim();
+        let value = pair.next()?.trim().trim_matches('"');
+        if name.is_empty() {
+            return None;
+        }
+
+        let attributes = parts
+            .filter(|attr| !attr.trim().is_empty())
+            .map(|attr| {
+                let mut attr = attr.splitn(2, '=');
+                let name = attr.next().unwrap_or("").trim().to_string();
+                (name, attr.next().map(|v| v.trim().to_string()))
+            })
+            .collect();
+
+        Some(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            attributes: attributes,
+        })
+    }
+
+    /// Returns the value of the attribute with the name (compared case-insensitively).
+    ///
+    /// Returns `Some("")` for flags which are present.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_ref().map_or("", |v| v.as_str()))
+    }
+
+    /// Returns the `Domain` attribute.
+    pub fn domain(&self) -> Option<&str> {
+        self.attribute("Domain")
+    }
+
+    /// Returns the `Path` attribute.
+    pub fn path(&self) -> Option<&str> {
+        self.attribute("Path")
+    }
+
+    /// Returns true if the `Secure` flag is set.
+    pub fn secure(&self) -> bool {
+        self.attribute("Secure").is_some()
+    }
+
+    /// Returns true if the `HttpOnly` flag is set.
+    pub fn http_only(&self) -> bool {
+        self.attribute("HttpOnly").is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cookie = Cookie::parse("session=abc123; Path=/; Domain=example.com; Secure; HttpOnly")
+            .unwrap();
+        assert_eq!(cookie.name, "session");
+        assert_eq!(cookie.value, "abc123");
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert!(cookie.secure());
+        assert!(cookie.http_only());
+        assert_eq!(cookie.attribute("max-age"), None);
+
+        assert_eq!(Cookie::parse("invalid"), None);
+    }
+}
@@ -0,0 +1,393 @@
+//! The data model of replay files, independent of reqwest.
+//!
+//! This module is available even with the default `client` feature disabled, so tooling which
+//! only has to inspect replay files (fixture checkers, CLIs, ...) doesn't need to pull in reqwest
+//! and its TLS stack:
+//!
+//! ```toml
+//! [dependencies]
+//! reqwest_mock = { version = "0.3", default-features = false }
+//! ```
+
+use error::Error;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use twox_hash::XxHash;
+
+/// The version of the storage format, stored as `format_version` in replay files. Files of other
+/// versions are discarded and recorded again by the `ReplayClient`.
+pub const FORMAT_VERSION: u8 = 3;
+
+/// The contents of a single replay file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub request: RequestData,
+    pub response: ResponseData,
+
+    /// Further responses recorded for the same request, which are replayed in sequence.
+    #[serde(default)]
+    pub sequence: Vec<ResponseData>,
+
+    /// User provided annotations.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+
+    /// Further replay files included by this one, relative to its directory.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Fingerprint of the matching settings the file was recorded with.
+    #[serde(default)]
+    pub matcher: Option<String>,
+
+    /// When the request was recorded, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub recorded_at: Option<u64>,
+
+    pub format_version: u8,
+}
+
+impl ReplayFile {
+    /// Read a replay file.
+    ///
+    /// Bodies stored in readable formats, see `ReplaySettings::body_formats`, are decoded.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, ::serde_json::Error> {
+        Self::from_value(::serde_json::from_reader(reader)?)
+    }
+
+    /// Parse the contents of a replay file, see `from_reader`.
+    pub fn from_value(mut value: Value) -> Result<Self, ::serde_json::Error> {
+        decode_bodies(&mut value)?;
+        ::serde_json::from_value(value)
+    }
+}
+
+/// The fields of a stored response holding its body and, if it is stored in a readable format,
+/// the format and a rendering which is only there to be read by humans.
+pub(crate) const F_BODY: &'static str = "body";
+pub(crate) const F_BODY_FORMAT: &'static str = "body_format";
+pub(crate) const F_BODY_RENDERING: &'static str = "body_rendering";
+
+/// Restores the bodies of the responses of a stored replay file to their base64 encoding, so
+/// the file can be deserialized.
+fn decode_bodies(value: &mut Value) -> Result<(), ::serde_json::Error> {
+    if let Some(response) = value.get_mut("response") {
+        decode_body(response)?;
+    }
+    if let Some(&mut Value::Array(ref mut sequence)) = value.get_mut("sequence") {
+        for response in sequence {
+            decode_body(response)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode_body(stored: &mut Value) -> Result<(), ::serde_json::Error> {
+    use serde::de::Error as DeError;
+
+    let stored = match stored.as_object_mut() {
+        Some(stored) => stored,
+        None => return Ok(()),
+    };
+    stored.remove(F_BODY_RENDERING);
+    let format = match stored.remove(F_BODY_FORMAT) {
+        Some(format) => format,
+        None => return Ok(()),
+    };
+
+    let body = stored.remove(F_BODY).unwrap_or(Value::Null);
+    let bytes = match (format.as_str(), body) {
+        (Some("text"), Value::String(text)) => text.into_bytes(),
+        (Some("json"), json) => ::serde_json::to_vec(&json)?,
+        (format, _) => {
+            return Err(DeError::custom(
+                format!("invalid body stored as {:?}", format),
+            ))
+        }
+    };
+    stored.insert(F_BODY.to_string(), Value::String(::base64::encode(&bytes)));
+    Ok(())
+}
+
+/// Writes the body of a recorded response to a standalone file, so binary payloads can be
+/// inspected with external tools.
+///
+/// `replay` is either a replay file or a directory of replay files, in which case the file
+/// named after the fingerprint of the request is used, e.g. `8e1c0d4ea2b5f3a1`. If the
+/// request was recorded as a sequence, `index` selects the response, starting at 0.
+///
+/// See the `extract` example for a command line interface.
+pub fn extract_body<P: AsRef<Path>, Q: AsRef<Path>>(
+    replay: P,
+    fingerprint: Option<&str>,
+    index: usize,
+    out: Q,
+) -> Result<(), Error> {
+    let replay = replay.as_ref();
+    let file = match fingerprint {
+        Some(fingerprint) if replay.is_dir() => replay.join(format!("{}.json", fingerprint)),
+        _ => replay.to_path_buf(),
+    };
+
+    let data = ReplayFile::from_reader(File::open(&file)?)?;
+    let response = if index == 0 {
+        &data.response
+    } else {
+        data.sequence.get(index - 1).ok_or_else(|| {
+            format!("{:?} holds only {} responses", file, data.sequence.len() + 1)
+        })?
+    };
+
+    File::create(out)?.write_all(&response.body)?;
+    Ok(())
+}
+
+/// A recorded request.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RequestData {
+    pub url: String,
+    pub method: String,
+    pub headers: BTreeMap<String, HeaderValue>,
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+    /// The query parameters of the url in the order they appear in.
+    ///
+    /// Only stored for matching and asserting on single parameters, the url stays authoritative
+    /// and the parameters aren't part of the fingerprint.
+    #[serde(default)]
+    pub query: Vec<(String, String)>,
+}
+
+/// The methods in the order they are declared by hyper, as their position is part of the hash.
+const METHODS: &'static [&'static str] = &["OPTIONS", "GET", "POST", "PUT", "DELETE", "HEAD",
+                                           "TRACE", "CONNECT", "PATCH"];
+
+impl RequestData {
+    /// A stable hash of the request, the same as `Request::fingerprint`.
+    ///
+    /// Replay directories name the file of a request after it, e.g. `8e1c0d4ea2b5f3a1.json`.
+    pub fn fingerprint(&self) -> u64 {
+        // TODO: took this hash function as unlike DefaultHasher it is specified.
+        //       however more evaluation should be done before settling on this
+        //       one as the hasher for the stable release.
+        let mut hasher = XxHash::with_seed(42);
+        self.url.hash(&mut hasher);
+        // Hashed like the derived `Hash` of `Method`, so existing replay files keep their names.
+        match METHODS.iter().position(|m| *m == self.method) {
+            Some(i) => (i as isize).hash(&mut hasher),
+            None => {
+                (METHODS.len() as isize).hash(&mut hasher);
+                self.method.hash(&mut hasher);
+            }
+        }
+        let headers: BTreeMap<&str, Cow<str>> = self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_text()))
+            .collect();
+        headers.hash(&mut hasher);
+        self.body.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the first value of the query parameter with the provided name.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|&&(ref k, _)| k == name).map(
+            |&(_, ref v)| v.as_str(),
+        )
+    }
+
+    /// Whether both requests have the same query parameters, regardless of their order.
+    pub fn same_query(&self, other: &RequestData) -> bool {
+        let mut own: Vec<&(String, String)> = self.query.iter().collect();
+        let mut others: Vec<&(String, String)> = other.query.iter().collect();
+        own.sort();
+        others.sort();
+        own == others
+    }
+}
+
+/// A recorded response.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResponseData {
+    pub url: String,
+    pub status: u16,
+    pub headers: BTreeMap<String, HeaderValue>,
+    #[serde(with = "base64_body")]
+    pub body: Vec<u8>,
+
+    /// How long it took to perform the request when it was recorded.
+    #[serde(default, with = "::duration::option")]
+    pub elapsed: Option<Duration>,
+
+    /// The status codes of the interim `1xx` responses received before this one.
+    #[serde(default)]
+    pub interim: Vec<u16>,
+
+    /// The trailers sent after the body.
+    #[serde(default)]
+    pub trailers: BTreeMap<String, HeaderValue>,
+}
+
+/// A header value as stored in replay files.
+///
+/// Header values may legally contain arbitrary bytes, values which aren't valid UTF-8 are stored
+/// base64 encoded so they survive the round trip. Headers sent on multiple lines, e.g.
+/// `Set-Cookie`, are stored as an array of the lines, as joining them would change their meaning.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HeaderValue {
+    Text(String),
+    Lines(Vec<String>),
+    Binary { base64: String },
+}
+
+impl HeaderValue {
+    /// Returns the raw bytes of the header value, the lines of multi-line values joined by `, `.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ::base64::DecodeError> {
+        match *self {
+            HeaderValue::Text(ref s) => Ok(s.as_bytes().to_vec()),
+            HeaderValue::Lines(ref lines) => Ok(lines.join(", ").into_bytes()),
+            HeaderValue::Binary { base64: ref encoded } => ::base64::decode(encoded),
+        }
+    }
+
+    /// Returns the raw lines of the header value.
+    pub fn to_lines(&self) -> Result<Vec<Vec<u8>>, ::base64::DecodeError> {
+        match *self {
+            HeaderValue::Lines(ref lines) => {
+                Ok(lines.iter().map(|line| line.as_bytes().to_vec()).collect())
+            }
+            _ => Ok(vec![self.to_bytes()?]),
+        }
+    }
+
+    /// Returns the header value as text, replacing invalid UTF-8 sequences.
+    fn to_text(&self) -> Cow<str> {
+        match *self {
+            HeaderValue::Text(ref s) => Cow::Borrowed(s),
+            HeaderValue::Lines(ref lines) => Cow::Owned(lines.join(", ")),
+            HeaderValue::Binary { .. } => {
+                let bytes = self.to_bytes().unwrap_or_default();
+                Cow::Owned(String::from_utf8_lossy(&bytes).into_owned())
+            }
+        }
+    }
+}
+
+/// Response bodies are stored base64 encoded.
+mod base64_body {
+    use base64;
+    use serde::de::{Deserialize, Deserializer, Error as DeError, Unexpected};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S: Serializer>(body: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(&s).map_err(|_| DeError::invalid_value(Unexpected::Str(&s), &"base64"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readable_bodies() {
+        let stored = br#"{
+            "request": {"url": "https://example.com/", "method": "GET", "headers": {}},
+            "response": {
+                "url": "https://example.com/",
+                "status": 200,
+                "headers": {},
+                "body": {"a": [1, 2]},
+                "body_format": "json"
+            },
+            "sequence": [{
+                "url": "https://example.com/",
+                "status": 200,
+                "headers": {},
+                "body": "text",
+                "body_format": "text",
+                "body_rendering": "text"
+            }],
+            "recorded_at": 1500000000,
+            "format_version": 3
+        }"#;
+
+        let file = ReplayFile::from_reader(&stored[..]).unwrap();
+        assert_eq!(file.response.body, b"{\"a\":[1,2]}".to_vec());
+        assert_eq!(file.sequence[0].body, b"text".to_vec());
+        assert_eq!(file.recorded_at, Some(1_500_000_000));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let request = |method: &str, header: &str| {
+            let mut headers = BTreeMap::new();
+            headers.insert("Accept".to_string(), HeaderValue::Text(header.to_string()));
+            RequestData {
+                url: "https://example.com/".to_string(),
+                method: method.to_string(),
+                headers: headers,
+                body: None,
+                query: Vec::new(),
+            }
+        };
+
+        let get = request("GET", "*/*");
+        assert_eq!(get.fingerprint(), get.clone().fingerprint());
+        assert_ne!(get.fingerprint(), request("POST", "*/*").fingerprint());
+        assert_ne!(get.fingerprint(), request("GET", "text/html").fingerprint());
+        assert_ne!(request("PURGE", "*/*").fingerprint(), request("PROPFIND", "*/*").fingerprint());
+    }
+
+    #[test]
+    fn extract_body() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_extract_body");
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let stored = br#"{
+            "request": {"url": "https://example.com/", "method": "GET", "headers": {}},
+            "response": {
+                "url": "https://example.com/",
+                "status": 200,
+                "headers": {},
+                "body": "first",
+                "body_format": "text"
+            },
+            "sequence": [{
+                "url": "https://example.com/",
+                "status": 200,
+                "headers": {},
+                "body": "second",
+                "body_format": "text"
+            }],
+            "format_version": 3
+        }"#;
+        File::create(dir.join("8e1c0d4ea2b5f3a1.json"))
+            .unwrap()
+            .write_all(stored)
+            .unwrap();
+        let out = dir.join("body");
+        let extracted = |index| {
+            super::extract_body(&dir, Some("8e1c0d4ea2b5f3a1"), index, &out).map(|_| {
+                let mut body = String::new();
+                File::open(&out).unwrap().read_to_string(&mut body).unwrap();
+                body
+            })
+        };
+
+        assert_eq!(extracted(0).unwrap(), "first");
+        assert_eq!(extracted(1).unwrap(), "second");
+        assert!(extracted(2).is_err());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! HTTP Digest access authentication (RFC 7616), see `RequestBuilder::digest_auth`.
+
+use header_ext::HeadersExt;
+use request::Request;
+use response::Response;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use twox_hash::XxHash;
+
+/// Returns the value of the `Authorization` header answering the digest challenge of the
+/// response, or `None` if the response doesn't contain a supported challenge.
+///
+/// The client nonce is derived from the server nonce, instead of being random, so the
+/// authenticated request can be recorded and replayed.
+pub fn authorization(
+    request: &Request,
+    response: &Response,
+    username: &str,
+    password: &str,
+) -> Option<String> {
+    let challenge = response.headers.get_str("WWW-Authenticate")?;
+    match challenge.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("digest ") => {}
+        _ => return None,
+    }
+    let params = parse_params(&challenge[7..]);
+
+    let algorithm = params.get("algorithm").map_or("MD5", |a| a.as_str());
+    if !algorithm.eq_ignore_ascii_case("MD5") {
+        debug!("Unsupported digest algorithm: {}", algorithm);
+        return None;
+    }
+    let realm = params.get("realm")?;
+    let nonce = params.get("nonce")?;
+    let qop = params.get("qop").map(|qop| {
+        qop.split(',').any(|q| q.trim() == "auth")
+    });
+    if qop == Some(false) {
+        debug!("Unsupported digest qop: {:?}", params.get("qop"));
+        return None;
+    }
+
+    let mut uri = request.url.path().to_string();
+    if let Some(query) = request.url.query() {
+        uri = format!("{}?{}", uri, query);
+    }
+
+    let mut hasher = XxHash::with_seed(42);
+    hasher.write(nonce.as_bytes());
+    let cnonce = format!("{:016x}", hasher.finish());
+
+    let digest = response_digest(
+        username,
+        password,
+        realm,
+        nonce,
+        request.method.as_ref(),
+        &uri,
+        if qop.is_some() { Some(&cnonce) } else { None },
+    );
+
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm=MD5, \
+         response=\"{}\"",
+        username,
+        realm,
+        nonce,
+        uri,
+        digest
+    );
+    if qop.is_some() {
+        header.push_str(&format!(", qop=auth, nc=00000001, cnonce=\"{}\"", cnonce));
+    }
+    if let Some(opaque) = params.get("opaque") {
+        header.push_str(&format!(", opaque=\"{}\"", opaque));
+    }
+    Some(header)
+}
+
+/// Computes the `response` parameter, using `qop=auth` if a client nonce is provided.
+fn response_digest(
+    username: &str,
+    password: &str,
+    realm: &str,
+    nonce: &str,
+    method: &str,
+    uri: &str,
+    cnonce: Option<&str>,
+) -> String {
+    let md5 = |s: String| format!("{:x}", ::md5::compute(s));
+
+    let ha1 = md5(format!("{}:{}:{}", username, realm, password));
+    let ha2 = md5(format!("{}:{}", method, uri));
+    match cnonce {
+        Some(cnonce) => md5(format!("{}:{}:00000001:{}:auth:{}", ha1, nonce, cnonce, ha2)),
+        None => md5(format!("{}:{}:{}", ha1, nonce, ha2)),
+    }
+}
+
+/// Parses the comma separated `name=value` parameters of a challenge, values may be quoted.
+fn parse_params(s: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut chars = s.chars().peekable();
+
+    loop {
+        let name: String = chars
+            .by_ref()
+            .skip_while(|c| *c == ',' || c.is_whitespace())
+            .take_while(|c| *c != '=')
+            .collect();
+        if name.is_empty() {
+            return params;
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ',').collect();
+        }
+        params.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_params() {
+        let params = super::parse_params(
+            r#"realm="test, realm", qop="auth,auth-int", nonce=abc, opaque="5ccc""#,
+        );
+        assert_eq!(params["realm"], "test, realm");
+        assert_eq!(params["qop"], "auth,auth-int");
+        assert_eq!(params["nonce"], "abc");
+        assert_eq!(params["opaque"], "5ccc");
+    }
+
+    #[test]
+    fn response_digest() {
+        // The example of RFC 2617.
+        assert_eq!(
+            super::response_digest(
+                "Mufasa",
+                "Circle Of Life",
+                "testrealm@host.com",
+                "dcd98b7102dd2f0e8b11d0f600bfb0c093",
+                "GET",
+                "/dir/index.html",
+                Some("0a4f113b"),
+            ),
+            "6629fae49393a05397450978507c4ef1"
+        );
+    }
+}
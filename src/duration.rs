@@ -0,0 +1,172 @@
+//! Human-friendly (de)serialization of `Duration` values, for use with `#[serde(with = "...")]`.
+//!
+//! Durations are serialized as strings like `"30s"` or `"1500ms"`. When deserializing, strings
+//! with the units `ms`, `s`, `m` and `h`, plain numbers of seconds and serde's default struct
+//! representation (`{"secs": 30, "nanos": 0}`) are accepted, so existing files and hand-edited
+//! ones both keep working.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate reqwest_mock;
+//! #[macro_use]
+//! extern crate serde_derive;
+//! extern crate serde_json;
+//!
+//! use std::time::Duration;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Settings {
+//!     #[serde(with = "reqwest_mock::duration")]
+//!     timeout: Duration,
+//! }
+//!
+//! # fn main() {
+//! let settings: Settings = serde_json::from_str(r#"{"timeout": "2m"}"#).unwrap();
+//! assert_eq!(settings.timeout, Duration::from_secs(120));
+//! assert_eq!(serde_json::to_string(&settings).unwrap(), r#"{"timeout":"120s"}"#);
+//! # }
+//! ```
+
+use serde::de::{Deserialize, Deserializer, Error as DeError, Unexpected};
+use serde::ser::Serializer;
+use std::time::Duration;
+
+pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format_duration(duration))
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Seconds(f64),
+        Struct { secs: u64, nanos: u32 },
+    }
+
+    match Repr::deserialize(deserializer)? {
+        Repr::Text(s) => {
+            parse_duration(&s).ok_or_else(|| {
+                DeError::invalid_value(Unexpected::Str(&s), &"a duration like \"30s\"")
+            })
+        }
+        Repr::Seconds(secs) => {
+            if secs >= 0.0 {
+                Ok(from_secs_f64(secs))
+            } else {
+                Err(DeError::invalid_value(
+                    Unexpected::Float(secs),
+                    &"a positive number of seconds",
+                ))
+            }
+        }
+        Repr::Struct { secs, nanos } => Ok(Duration::new(secs, nanos)),
+    }
+}
+
+/// The same for `Option<Duration>`, use with `#[serde(default, with = "...::option")]`.
+pub mod option {
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::Serializer;
+    use std::time::Duration;
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *duration {
+            Some(ref d) => serializer.serialize_some(&super::format_duration(d)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super")] Duration);
+
+        let wrapper: Option<Wrapper> = Option::deserialize(deserializer)?;
+        Ok(wrapper.map(|Wrapper(d)| d))
+    }
+}
+
+fn format_duration(duration: &Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else if duration.subsec_nanos() % 1_000_000 == 0 {
+        format!(
+            "{}ms",
+            duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+        )
+    } else {
+        format!(
+            "{}ns",
+            duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+        )
+    }
+}
+
+fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_digit(10) || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let secs = match unit.trim() {
+        "ns" => number / 1e9,
+        "ms" => number / 1e3,
+        "s" | "" => number,
+        "m" => number * 60.,
+        "h" => number * 3600.,
+        _ => return None,
+    };
+    Some(from_secs_f64(secs))
+}
+
+fn from_secs_f64(secs: f64) -> Duration {
+    let whole = secs.trunc();
+    Duration::new(whole as u64, ((secs - whole) * 1e9).round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration() {
+        assert_eq!(super::parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(super::parse_duration("1500ms"), Some(Duration::from_millis(1500)));
+        assert_eq!(super::parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(super::parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(super::parse_duration("42"), Some(Duration::from_secs(42)));
+        assert_eq!(super::parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn format_duration() {
+        assert_eq!(super::format_duration(&Duration::from_secs(30)), "30s");
+        assert_eq!(super::format_duration(&Duration::from_millis(1500)), "1500ms");
+        assert_eq!(super::format_duration(&Duration::new(0, 42)), "42ns");
+    }
+
+    #[test]
+    fn deserialize_legacy() {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super")] Duration);
+
+        let Wrapper(d) = ::serde_json::from_str(r#"{"secs": 3, "nanos": 0}"#).unwrap();
+        assert_eq!(d, Duration::from_secs(3));
+        let Wrapper(d) = ::serde_json::from_str("0.25").unwrap();
+        assert_eq!(d, Duration::from_millis(250));
+    }
+}
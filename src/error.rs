@@ -10,11 +10,148 @@ error_chain! {
 
     foreign_links {
         Io(::std::io::Error);
-        Reqwest(::reqwest::Error);
+        Reqwest(::reqwest::Error) #[cfg(feature = "client")];
         SerdeJson(::serde_json::Error);
         FromUtf8(::std::string::FromUtf8Error);
     }
 
     errors {
+        TimedOut(elapsed: ::std::time::Duration) {
+            description("request timed out")
+            display("request exceeded its timeout, it took {:?}", elapsed)
+        }
+
+        DeadlineExceeded(url: String) {
+            description("deadline exceeded before sending the request")
+            display("deadline of the request to {} passed before it was sent", url)
+        }
+
+        SchemaViolation(url: String, violations: Vec<String>) {
+            description("response body violates JSON schema")
+            display("response body of {} violates JSON schema:\n{}", url, violations.join("\n"))
+        }
+
+        Configuration(description: String) {
+            description("invalid configuration")
+            display("invalid configuration: {}", description)
+        }
+
+        Fixture(description: String) {
+            description("invalid replay data")
+            display("invalid replay data: {}", description)
+        }
+
+        Unmatched(method: String, url: String) {
+            description("no stub or recorded request matched")
+            display("no stub or recorded request matched the {} request of URL {}", method, url)
+        }
+
+        Validation(rule: String, url: String) {
+            description("request violates a rule")
+            display("request to {} violates the rule: {}", url, rule)
+        }
+
+        Dns(host: String) {
+            description("host name could not be resolved")
+            display("host name {} could not be resolved", host)
+        }
+
+        TlsHandshake(host: String, reason: String) {
+            description("TLS handshake failed")
+            display("TLS handshake with {} failed: {}", host, reason)
+        }
+
+        CertificateMismatch(host: String) {
+            description("certificate doesn't match the host name")
+            display("certificate presented by {} doesn't match its host name", host)
+        }
+
+        BodyTooLarge(url: String, limit: usize) {
+            description("response body exceeds the size limit")
+            display("response body of {} exceeds the limit of {} bytes", url, limit)
+        }
+
+        NetworkAccess(method: String, url: String) {
+            description("live request attempted while offline")
+            display("{} request of URL {} wasn't recorded and the network may not be used", method, url)
+        }
+    }
+}
+
+/// Broad categories of errors, which tell apart problems of the test setup from problems of the
+/// code under test.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorCategory {
+    /// The client or its stubs were configured incorrectly.
+    Configuration,
+
+    /// Replay data is missing parts, outdated or invalid.
+    Fixture,
+
+    /// A request matched neither a stub nor a recorded request.
+    Match,
+
+    /// Performing a live request failed.
+    Transport,
+
+    /// Anything else, e.g. a response violating its schema.
+    Other,
+}
+
+impl Error {
+    /// Returns the category of the error.
+    pub fn category(&self) -> ErrorCategory {
+        match *self.kind() {
+            ErrorKind::Configuration(..) => ErrorCategory::Configuration,
+            ErrorKind::Fixture(..) => ErrorCategory::Fixture,
+            ErrorKind::Unmatched(..) |
+            ErrorKind::NetworkAccess(..) => ErrorCategory::Match,
+            #[cfg(feature = "client")]
+            ErrorKind::Reqwest(..) => ErrorCategory::Transport,
+            ErrorKind::TimedOut(..) |
+            ErrorKind::DeadlineExceeded(..) |
+            ErrorKind::Dns(..) |
+            ErrorKind::TlsHandshake(..) |
+            ErrorKind::CertificateMismatch(..) |
+            ErrorKind::Io(..) => ErrorCategory::Transport,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    /// Returns true if the error is caused by replay data or stubs not fitting the requests,
+    /// which usually means the requests have to be recorded again.
+    pub fn is_fixture_problem(&self) -> bool {
+        match self.category() {
+            ErrorCategory::Fixture | ErrorCategory::Match => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn category() {
+        let err: Error = ErrorKind::Unmatched("GET".to_string(), "http://example.com/".to_string())
+            .into();
+        assert_eq!(err.category(), ErrorCategory::Match);
+        assert!(err.is_fixture_problem());
+
+        let err: Error = ErrorKind::TimedOut(::std::time::Duration::from_secs(1)).into();
+        assert_eq!(err.category(), ErrorCategory::Transport);
+        assert!(!err.is_fixture_problem());
+
+        let err: Error = ErrorKind::Dns("example.invalid".to_string()).into();
+        assert_eq!(err.category(), ErrorCategory::Transport);
+
+        let err: Error = "something else".into();
+        assert_eq!(err.category(), ErrorCategory::Other);
+
+        // Only JSON errors of replay files are wrapped as fixture problems, not those of bodies.
+        let err: Error = ::serde_json::from_str::<u8>("body").unwrap_err().into();
+        assert_eq!(err.category(), ErrorCategory::Other);
+        assert!(!err.is_fixture_problem());
     }
 }
@@ -0,0 +1,71 @@
+//! Convenience accessors for `Headers`, not requiring the use of typed headers.
+
+use reqwest::header::Headers;
+use std::str;
+
+/// Extension trait providing string based accessors on `Headers`.
+///
+/// This is mostly useful when writing assertions against recorded requests and responses, where
+/// going through the typed header machinery is unnecessarily verbose.
+pub trait HeadersExt {
+    /// Returns the value of the header with the provided name if it is present exactly once and
+    /// is valid UTF-8.
+    fn get_str(&self, name: &str) -> Option<&str>;
+
+    /// Returns all values of the header with the provided name which are valid UTF-8.
+    fn get_all_str(&self, name: &str) -> Vec<&str>;
+
+    /// Returns the value of the `Content-Type` header.
+    fn content_type(&self) -> Option<&str> {
+        self.get_str("Content-Type")
+    }
+
+    /// Returns the value of the `Content-Length` header if it is a valid number.
+    fn content_length(&self) -> Option<u64> {
+        self.get_str("Content-Length").and_then(
+            |s| s.trim().parse().ok(),
+        )
+    }
+}
+
+impl HeadersExt for Headers {
+    fn get_str(&self, name: &str) -> Option<&str> {
+        self.get_raw(name).and_then(|raw| raw.one()).and_then(|value| {
+            str::from_utf8(value).ok()
+        })
+    }
+
+    fn get_all_str(&self, name: &str) -> Vec<&str> {
+        match self.get_raw(name) {
+            Some(raw) => raw.iter().filter_map(|v| str::from_utf8(v).ok()).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{ContentLength, ContentType};
+
+    #[test]
+    fn typed_headers() {
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        headers.set(ContentLength(42));
+
+        assert_eq!(headers.content_type(), Some("application/json"));
+        assert_eq!(headers.content_length(), Some(42));
+        assert_eq!(headers.get_str("X-Missing"), None);
+    }
+
+    #[test]
+    fn multiple_values() {
+        let mut headers = Headers::new();
+        headers.append_raw("X-Multi", b"one".to_vec());
+        headers.append_raw("X-Multi", b"two".to_vec());
+
+        assert_eq!(headers.get_str("X-Multi"), None);
+        assert_eq!(headers.get_all_str("x-multi"), vec!["one", "two"]);
+    }
+}
@@ -1,8 +1,15 @@
 //! Defines some things used from different modules but not to be exported.
 
-use reqwest::header::Headers;
+use base64;
+use data::HeaderValue;
+use header_ext::HeadersExt;
+use reqwest::StatusCode;
+use reqwest::header::{Headers, HttpDate};
+use response::Response;
 use std::collections::BTreeMap;
 use std::iter::FromIterator;
+use std::str;
+use std::time::{Duration, SystemTime};
 
 pub fn serialize_headers(headers: &Headers) -> BTreeMap<String, String> {
     let tuples_iter = headers.iter().map(|hv| {
@@ -12,13 +19,118 @@ pub fn serialize_headers(headers: &Headers) -> BTreeMap<String, String> {
     BTreeMap::<String, String>::from_iter(tuples_iter)
 }
 
-pub fn deserialize_headers(map: &BTreeMap<String, String>) -> Headers {
+pub fn store_headers(headers: &Headers) -> BTreeMap<String, HeaderValue> {
+    let tuples_iter = headers.iter().map(|hv| {
+        let raw = hv.raw();
+        let value = match raw.iter().map(str::from_utf8).collect::<Result<Vec<_>, _>>() {
+            Ok(ref lines) if lines.len() > 1 => {
+                HeaderValue::Lines(lines.iter().map(|line| line.to_string()).collect())
+            }
+            Ok(_) => HeaderValue::Text(hv.value_string()),
+            Err(_) => {
+                let bytes: Vec<u8> = raw.iter().collect::<Vec<_>>().join(&b", "[..]);
+                HeaderValue::Binary { base64: base64::encode(&bytes) }
+            }
+        };
+        (hv.name().to_string(), value)
+    });
+
+    BTreeMap::<String, HeaderValue>::from_iter(tuples_iter)
+}
+
+pub fn deserialize_headers(
+    map: &BTreeMap<String, HeaderValue>,
+) -> Result<Headers, base64::DecodeError> {
     let mut headers = ::reqwest::header::Headers::new();
     for (name, value) in map.iter() {
-        headers.append_raw(name.clone(), value.as_bytes().to_vec())
+        for line in value.to_lines()? {
+            headers.append_raw(name.clone(), line);
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Returns how long to wait before retrying the request if the response asks for it using the
+/// `Retry-After` header, which can either be a number of seconds or a date.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    match response.status {
+        StatusCode::TooManyRequests |
+        StatusCode::ServiceUnavailable => {}
+        _ => return None,
+    }
+
+    let value = response.headers.get_str("Retry-After")?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date: HttpDate = value.parse().ok()?;
+    Some(SystemTime::from(date).duration_since(SystemTime::now()).unwrap_or(
+        Duration::from_secs(0),
+    ))
+}
+
+/// Describes how two bodies differ: the offset of the first difference, the lengths and a
+/// hexdump of a window around the first difference for both bodies. Returns `None` if they are
+/// equal.
+pub fn body_diff(recorded: &[u8], incoming: &[u8]) -> Option<String> {
+    const WINDOW: usize = 16;
+
+    let offset = match recorded.iter().zip(incoming).position(|(a, b)| a != b) {
+        Some(offset) => offset,
+        None if recorded.len() == incoming.len() => return None,
+        None => ::std::cmp::min(recorded.len(), incoming.len()),
+    };
+    let start = offset.saturating_sub(WINDOW / 2);
+
+    fn hexdump(body: &[u8], start: usize) -> String {
+        let window = &body[::std::cmp::min(start, body.len())..];
+        let window = &window[..::std::cmp::min(WINDOW, window.len())];
+        let hex: Vec<String> = window.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = window
+            .iter()
+            .map(|&b| if b >= 0x20 && b < 0x7f { b as char } else { '.' })
+            .collect();
+        format!("{:08x}: {:<47} |{}|", start, hex.join(" "), ascii)
     }
 
-    headers
+    Some(format!(
+        "bodies differ at offset {} (recorded length {}, incoming length {})\n  \
+         recorded: {}\n  incoming: {}",
+        offset,
+        recorded.len(),
+        incoming.len(),
+        hexdump(recorded, start),
+        hexdump(incoming, start)
+    ))
+}
+
+/// Answers a request on localhost with each of the raw responses in turn, returning the base url.
+#[cfg(test)]
+pub fn serve(responses: &'static [&'static str]) -> (String, ::std::thread::JoinHandle<()>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = ::std::thread::spawn(move || for response in responses {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        let mut content_length = 0;
+        while reader.read_line(&mut line).unwrap() > 2 {
+            let lower = line.to_lowercase();
+            if lower.starts_with("content-length:") {
+                content_length = lower[15..].trim().parse().unwrap();
+            }
+            line.clear();
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    (base_url, handle)
 }
 
 #[cfg(test)]
@@ -54,4 +166,104 @@ mod tests {
 
         assert_eq!(ser1, ser2);
     }
+
+    /// Header names have to keep the casing they were received with, as some servers are picky
+    /// consumers of their own headers.
+    #[test]
+    fn serialize_headers_preserves_case() {
+        let mut headers = Headers::new();
+        headers.set_raw("x-lower-Then-Upper", "value");
+
+        let stored = store_headers(&headers);
+        assert!(stored.contains_key("x-lower-Then-Upper"));
+
+        let deserialized = deserialize_headers(&stored).unwrap();
+        assert_eq!(store_headers(&deserialized), stored);
+    }
+
+    #[test]
+    fn store_headers_lines() {
+        let mut headers = Headers::new();
+        headers.append_raw("Set-Cookie", "a=1; Path=/");
+        headers.append_raw("Set-Cookie", "b=2");
+
+        let stored = store_headers(&headers);
+        assert_eq!(
+            stored["Set-Cookie"],
+            HeaderValue::Lines(vec!["a=1; Path=/".to_string(), "b=2".to_string()])
+        );
+
+        let json = ::serde_json::to_string(&stored).unwrap();
+        let deserialized = deserialize_headers(&::serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(deserialized.get_raw("Set-Cookie").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn store_headers_binary() {
+        let mut headers = Headers::new();
+        headers.set_raw("X-Text", "plain");
+        headers.set_raw("X-Binary", vec![0xff, 0x00, 0xfe]);
+
+        let stored = store_headers(&headers);
+        assert_eq!(
+            stored["X-Text"],
+            HeaderValue::Text("plain".to_string())
+        );
+        assert_eq!(
+            stored["X-Binary"],
+            HeaderValue::Binary { base64: "/wD+".to_string() }
+        );
+
+        let json = ::serde_json::to_string(&stored).unwrap();
+        let deserialized = deserialize_headers(&::serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(
+            deserialized.get_raw("X-Binary").and_then(|raw| raw.one()),
+            Some(&[0xff, 0x00, 0xfe][..])
+        );
+    }
+
+    #[test]
+    fn retry_after() {
+        use bytes::Bytes;
+        use reqwest::Url;
+
+        let mut response = Response {
+            url: Url::parse("http://example.com").unwrap(),
+            status: StatusCode::TooManyRequests,
+            headers: Headers::new(),
+            body: Bytes::new(),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        assert_eq!(super::retry_after(&response), None);
+
+        response.headers.set_raw("Retry-After", "120");
+        assert_eq!(super::retry_after(&response), Some(Duration::from_secs(120)));
+
+        response.headers.set_raw("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(super::retry_after(&response), Some(Duration::from_secs(0)));
+
+        response.status = StatusCode::Ok;
+        assert_eq!(super::retry_after(&response), None);
+    }
+
+    #[test]
+    fn body_diff() {
+        assert_eq!(super::body_diff(b"abc", b"abc"), None);
+
+        let diff = super::body_diff(&[0, 1, 2, 3], &[0, 1, 9]).unwrap();
+        assert_eq!(
+            diff,
+            "bodies differ at offset 2 (recorded length 4, incoming length 3)\n  \
+             recorded: 00000000: 00 01 02 03                                     |....|\n  \
+             incoming: 00000000: 00 01 09                                        |...|"
+        );
+
+        assert!(super::body_diff(b"abc", b"abcd").unwrap().starts_with(
+            "bodies differ at offset 3",
+        ));
+    }
 }
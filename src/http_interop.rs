@@ -0,0 +1,121 @@
+//! Conversions between the types of this crate and the ones of the `http` crate, available
+//! with the `http-interop` feature.
+//!
+//! This way handlers written against the `http` crate can be reused as dynamic responders and
+//! requests can be passed through middleware stacks built on it.
+
+use bytes::Bytes;
+use error::{Error, ResultExt};
+use http;
+use request::Request;
+use reqwest::header::Headers;
+use reqwest::{Method, StatusCode, Url};
+use response::Response;
+use std::str::FromStr;
+
+impl Request {
+    /// Converts a request of the `http` crate, its uri has to be absolute.
+    pub fn from_http(request: http::Request<Vec<u8>>) -> Result<Request, Error> {
+        let (parts, body) = request.into_parts();
+        let url = Url::parse(&parts.uri.to_string()).chain_err(
+            || "request uri has to be absolute",
+        )?;
+        let method = Method::from_str(parts.method.as_str()).chain_err(
+            || "invalid request method",
+        )?;
+
+        Ok(Request {
+            url: url,
+            method: method,
+            headers: from_http_headers(&parts.headers),
+            body: if body.is_empty() {
+                None
+            } else {
+                Some(Bytes::from(body))
+            },
+        })
+    }
+
+    /// Converts the request into a request of the `http` crate.
+    pub fn into_http(self) -> Result<http::Request<Vec<u8>>, Error> {
+        let mut builder = http::Request::builder();
+        builder.method(self.method.as_ref()).uri(self.url.as_str());
+        for header in self.headers.iter() {
+            builder.header(header.name(), header.value_string().as_str());
+        }
+        let body = self.body.map(|b| b.to_vec()).unwrap_or_default();
+        builder.body(body).chain_err(|| "invalid request")
+    }
+}
+
+impl Response {
+    /// Converts a response of the `http` crate, which doesn't know the url it was requested
+    /// from.
+    pub fn from_http(url: Url, response: http::Response<Vec<u8>>) -> Result<Response, Error> {
+        let (parts, body) = response.into_parts();
+        let status = StatusCode::try_from(parts.status.as_u16()).map_err(|_| {
+            Error::from(format!("invalid status code: {}", parts.status))
+        })?;
+
+        Ok(Response {
+            url: url,
+            status: status,
+            headers: from_http_headers(&parts.headers),
+            body: Bytes::from(body),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        })
+    }
+
+    /// Converts the response into a response of the `http` crate.
+    pub fn into_http(self) -> Result<http::Response<Vec<u8>>, Error> {
+        let mut builder = http::Response::builder();
+        builder.status(u16::from(self.status));
+        for header in self.headers.iter() {
+            builder.header(header.name(), header.value_string().as_str());
+        }
+        builder.body(self.body.to_vec()).chain_err(|| "invalid response")
+    }
+}
+
+fn from_http_headers(map: &http::HeaderMap) -> Headers {
+    let mut headers = Headers::new();
+    for (name, value) in map.iter() {
+        headers.append_raw(name.as_str().to_string(), value.as_bytes().to_vec());
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let request = http::Request::builder()
+            .method("POST")
+            .uri("https://example.com/users?page=2")
+            .header("Content-Type", "application/json")
+            .body(b"{}".to_vec())
+            .unwrap();
+        let converted = Request::from_http(request).unwrap();
+        assert_eq!(converted.method, Method::Post);
+        assert_eq!(converted.url.query(), Some("page=2"));
+        assert_eq!(converted.body, Some(Bytes::from(&b"{}"[..])));
+
+        let back = converted.into_http().unwrap();
+        assert_eq!(back.headers()["content-type"], "application/json");
+
+        let response = http::Response::builder()
+            .status(404)
+            .body(b"missing".to_vec())
+            .unwrap();
+        let url = Url::parse("https://example.com/").unwrap();
+        let converted = Response::from_http(url, response).unwrap();
+        assert_eq!(converted.status, StatusCode::NotFound);
+        assert_eq!(converted.into_http().unwrap().status(), 404);
+    }
+}
@@ -2,9 +2,11 @@
 //!
 //! Write your code generic over the [Client](trait.Client.html) trait,
 //! and in production use [DirectClient](struct.DirectClient.html) while in testing
-//! you can use [ReplayClient](struct.ReplayClient.html), which will record a request
-//! the first time and replay it every time the exact same request is made in the
-//! future.
+//! you can use [ReplayClient](struct.ReplayClient.html), which replays recorded requests.
+//!
+//! A client created with `ReplayClient::new` is offline: requests which weren't recorded yet
+//! fail instead of reaching the network. Use `ReplayClient::recording` to record a request the
+//! first time and replay it every time the exact same request is made in the future.
 //!
 //! # Examples
 //!
@@ -25,7 +27,7 @@
 //! #[cfg(test)]
 //! fn test_client(path: &str) -> MyClient<ReplayClient> {
 //!     MyClient {
-//!         client: ReplayClient::new(path)
+//!         client: ReplayClient::recording(path)
 //!     }
 //! }
 //!
@@ -45,32 +47,102 @@
 //! ```
 
 extern crate base64;
+#[cfg(feature = "brotli")]
+extern crate brotli;
+extern crate bytes;
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "client")]
+extern crate flate2;
+#[cfg(feature = "tower")]
+extern crate futures;
+#[cfg(feature = "http-interop")]
+extern crate http;
+#[cfg(feature = "json-schema")]
+extern crate jsonschema;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "client")]
+extern crate md5;
+#[cfg(feature = "client")]
 extern crate reqwest;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+#[cfg(feature = "tower")]
+extern crate tower_service;
 extern crate twox_hash;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
+#[cfg(feature = "client")]
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "client")]
+mod digest;
+#[cfg(feature = "client")]
 mod helper;
+#[cfg(feature = "http-interop")]
+mod http_interop;
 
 pub mod error;
+pub mod clock;
 pub mod config;
+pub mod data;
+pub mod duration;
+pub mod metrics;
+#[cfg(feature = "client")]
+pub mod pact;
+#[cfg(feature = "client")]
+pub mod proxy_recorder;
+#[cfg(feature = "client")]
+pub mod recorder;
 
 mod body;
 pub use body::IntoBody;
 
+#[cfg(feature = "client")]
+mod compression;
+#[cfg(feature = "client")]
+pub use compression::Compression;
+
+#[cfg(feature = "client")]
+mod cookie;
+#[cfg(feature = "client")]
+pub use cookie::Cookie;
+
+#[cfg(feature = "client")]
+mod header_ext;
+#[cfg(feature = "client")]
+pub use header_ext::HeadersExt;
+
+#[cfg(feature = "client")]
 mod request;
+#[cfg(feature = "client")]
+pub use request::Request;
+
+#[cfg(feature = "client")]
 mod response;
+#[cfg(feature = "client")]
+pub use response::{BodyReader, ReplayInfo, Response};
 
+#[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "client")]
 mod request_builder;
+#[cfg(feature = "client")]
+pub use request_builder::RequestBuilder;
+
+#[cfg(feature = "client")]
+pub mod prelude;
 
+#[cfg(feature = "client")]
 pub use self::client::*;
-pub use self::error::Error;
+pub use self::error::{Error, ErrorCategory};
 
+pub use bytes::Bytes;
+#[cfg(feature = "client")]
 pub use reqwest::{header, Url, UrlError, IntoUrl, Method, StatusCode};
@@ -0,0 +1,94 @@
+/// Defines a client type and a function constructing it, which use a mocking client in tests
+/// and the `DirectClient` otherwise.
+///
+/// This saves writing the type switching around the `Client` trait by hand. By default the
+/// mocking client is used under `cfg(test)`, an own condition can be provided with a `cfg`
+/// attribute, e.g. to also use it with a feature flag of your crate.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate reqwest_mock;
+///
+/// use reqwest_mock::{Client, RecordingTarget, ReplayClient};
+///
+/// mock_client! {
+///     #[cfg(any(test, feature = "mock-http"))]
+///     pub type HttpClient = ReplayClient;
+///
+///     pub fn http_client() -> HttpClient {
+///         ReplayClient::new(RecordingTarget::dir("tests/fixtures"))
+///     }
+/// }
+///
+/// fn main() {
+///     let client: HttpClient = http_client();
+///     let _ = client.get("https://example.com/");
+/// }
+/// ```
+#[macro_export]
+macro_rules! mock_client {
+    (
+        #[cfg($cond:meta)]
+        $vis:vis type $ty:ident = $mock:ty;
+
+        $fvis:vis fn $f:ident() -> $fty:ty $body:block
+    ) => {
+        #[cfg($cond)]
+        $vis type $ty = $mock;
+        #[cfg(not($cond))]
+        $vis type $ty = $crate::DirectClient;
+
+        #[cfg($cond)]
+        $fvis fn $f() -> $fty $body
+        #[cfg(not($cond))]
+        $fvis fn $f() -> $fty {
+            $crate::DirectClient::new()
+        }
+    };
+
+    (
+        $vis:vis type $ty:ident = $mock:ty;
+
+        $fvis:vis fn $f:ident() -> $fty:ty $body:block
+    ) => {
+        mock_client! {
+            #[cfg(test)]
+            $vis type $ty = $mock;
+
+            $fvis fn $f() -> $fty $body
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use client::{Client, DirectClient, StubClient, StubSettings};
+
+    mock_client! {
+        type MockedClient = StubClient;
+
+        fn mocked_client() -> MockedClient {
+            StubClient::new(StubSettings::default())
+        }
+    }
+
+    mock_client! {
+        #[cfg(not(test))]
+        type LiveClient = StubClient;
+
+        fn live_client() -> LiveClient {
+            StubClient::new(StubSettings::default())
+        }
+    }
+
+    #[test]
+    fn mock_client() {
+        let client: StubClient = mocked_client();
+        // Nothing was stubbed.
+        assert!(client.get("http://example.com/").send().is_err());
+
+        let _: DirectClient = live_client();
+    }
+}
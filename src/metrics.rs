@@ -0,0 +1,250 @@
+//! Hooks to observe the requests performed by a `Client`, e.g. to bridge them to a metrics
+//! system or to assert on them in tests.
+//!
+//! Register a sink in `ClientConfig::metrics`:
+//!
+//! ```
+//! use reqwest_mock::config::ClientConfig;
+//! use reqwest_mock::metrics::{InMemoryMetrics, Metrics};
+//!
+//! let metrics = InMemoryMetrics::new();
+//! let mut config = ClientConfig::default();
+//! config.metrics = Metrics::new(metrics.clone());
+//!
+//! // ... perform requests ...
+//!
+//! assert!(metrics.events().is_empty());
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Where the response to a request came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResponseSource {
+    /// The request was performed over the network.
+    Live,
+
+    /// The response was replayed from a recording.
+    Replayed,
+
+    /// The response was provided by a stub.
+    Stubbed,
+}
+
+/// Information about a completed request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestMetrics {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+
+    /// Length of the response body.
+    pub bytes: usize,
+
+    /// Time it took to get the response, measured with the clock of the `ClientConfig`.
+    pub duration: Duration,
+
+    pub source: ResponseSource,
+}
+
+/// Receives events about the requests performed by a client, all methods do nothing by default.
+///
+/// Only the client which finally provides the response reports a request, so requests passing
+/// through a `ReplayClient` to the network are reported once.
+pub trait MetricsSink: Send + Sync {
+    /// A request is about to be performed.
+    fn request_started(&self, _method: &str, _url: &str) {}
+
+    /// A request completed with a response.
+    fn request_completed(&self, _metrics: &RequestMetrics) {}
+
+    /// A request failed with an error.
+    fn request_failed(&self, _method: &str, _url: &str, _duration: Duration) {}
+}
+
+/// A sink ignoring all events, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {}
+
+/// An event recorded by `InMemoryMetrics`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetricsEvent {
+    Started { method: String, url: String },
+    Completed(RequestMetrics),
+    Failed {
+        method: String,
+        url: String,
+        duration: Duration,
+    },
+}
+
+/// A sink keeping all events in memory, to assert on them in tests.
+///
+/// Clones share their events, so keep a clone around to inspect the events.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryMetrics {
+    events: Arc<Mutex<Vec<MetricsEvent>>>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all events received so far.
+    pub fn events(&self) -> Vec<MetricsEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn request_started(&self, method: &str, url: &str) {
+        self.events.lock().unwrap().push(MetricsEvent::Started {
+            method: method.to_string(),
+            url: url.to_string(),
+        });
+    }
+
+    fn request_completed(&self, metrics: &RequestMetrics) {
+        self.events.lock().unwrap().push(
+            MetricsEvent::Completed(metrics.clone()),
+        );
+    }
+
+    fn request_failed(&self, method: &str, url: &str, duration: Duration) {
+        self.events.lock().unwrap().push(MetricsEvent::Failed {
+            method: method.to_string(),
+            url: url.to_string(),
+            duration: duration,
+        });
+    }
+}
+
+/// The `MetricsSink` of a `ClientConfig`.
+#[derive(Clone)]
+pub struct Metrics(Arc<dyn MetricsSink>);
+
+impl Metrics {
+    pub fn new<S: MetricsSink + 'static>(sink: S) -> Self {
+        Metrics(Arc::new(sink))
+    }
+
+    /// Returns the sink.
+    pub fn sink(&self) -> &dyn MetricsSink {
+        &*self.0
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new(NoopMetrics)
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+/// Configs are equal if they report to the same sink.
+impl PartialEq for Metrics {
+    fn eq(&self, other: &Metrics) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Reports the request to the sink of the config, `f` performs it.
+#[cfg(feature = "client")]
+pub(crate) fn instrument<F>(
+    config: &::config::ClientConfig,
+    request: ::request::Request,
+    f: F,
+) -> Result<::response::Response, ::error::Error>
+where
+    F: FnOnce(::request::Request)
+        -> Result<(::response::Response, ResponseSource), ::error::Error>,
+{
+    let sink = config.metrics.sink();
+    let (method, url) = (request.method.to_string(), request.url.to_string());
+    sink.request_started(&method, &url);
+
+    let start = config.clock.now();
+    let result = f(request);
+    let duration = config.clock.now() - start;
+
+    match result {
+        Ok((response, source)) => {
+            sink.request_completed(&RequestMetrics {
+                method: method,
+                url: url,
+                status: u16::from(response.status),
+                bytes: response.body.len(),
+                duration: duration,
+                source: source,
+            });
+            Ok(response)
+        }
+        Err(e) => {
+            sink.request_failed(&method, &url, duration);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+    use client::{Client, StubClient, StubDefault, StubSettings, StubStrictness};
+    use clock::Clock;
+    use reqwest::Url;
+
+    #[test]
+    fn stubbed_requests() {
+        let metrics = InMemoryMetrics::new();
+        let mut client = StubClient::new(StubSettings {
+            default: StubDefault::Error,
+            strictness: StubStrictness::Url,
+        });
+        client.config_mut().metrics = Metrics::new(metrics.clone());
+        client.config_mut().clock = Clock::mock();
+        let url = Url::parse("http://example.com/ok").unwrap();
+        client.stub(url.clone()).response().body("ok").mock();
+
+        client.get(url).send().unwrap();
+        assert!(client.get("http://example.com/missing").send().is_err());
+
+        let ok = "http://example.com/ok".to_string();
+        let missing = "http://example.com/missing".to_string();
+        assert_eq!(
+            metrics.events(),
+            vec![
+                MetricsEvent::Started {
+                    method: "GET".to_string(),
+                    url: ok.clone(),
+                },
+                MetricsEvent::Completed(RequestMetrics {
+                    method: "GET".to_string(),
+                    url: ok,
+                    status: 200,
+                    bytes: 2,
+                    duration: Duration::from_secs(0),
+                    source: ResponseSource::Stubbed,
+                }),
+                MetricsEvent::Started {
+                    method: "GET".to_string(),
+                    url: missing.clone(),
+                },
+                MetricsEvent::Failed {
+                    method: "GET".to_string(),
+                    url: missing,
+                    duration: Duration::from_secs(0),
+                },
+            ]
+        );
+    }
+}
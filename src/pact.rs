@@ -0,0 +1,192 @@
+//! Export of recorded requests and responses as [Pact](https://pact.io) v3 contract files.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use reqwest_mock::{RecordingTarget, ReplayClient};
+//! use reqwest_mock::pact::PactExport;
+//!
+//! let client = ReplayClient::new(RecordingTarget::dir("replays"));
+//!
+//! let mut pact = PactExport::new("my-client", "httpbin");
+//! pact.interactions(client.recorded().unwrap());
+//! pact.write_to("pacts/my-client-httpbin.json").unwrap();
+//! ```
+
+use base64;
+use error::Error;
+use request::Request;
+use response::Response;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{File, create_dir_all};
+use std::path::Path;
+
+/// Builds a Pact v3 contract from recorded requests and responses.
+pub struct PactExport {
+    consumer: String,
+    provider: String,
+    interactions: Vec<Value>,
+}
+
+impl PactExport {
+    /// Create a new export for the contract between the named consumer and provider.
+    pub fn new<C: Into<String>, P: Into<String>>(consumer: C, provider: P) -> Self {
+        PactExport {
+            consumer: consumer.into(),
+            provider: provider.into(),
+            interactions: Vec::new(),
+        }
+    }
+
+    /// Add a single interaction to the contract.
+    pub fn interaction(&mut self, request: &Request, response: &Response) {
+        let mut query = BTreeMap::<String, Vec<String>>::new();
+        for (key, value) in request.url.query_pairs() {
+            query.entry(key.into_owned()).or_insert_with(Vec::new).push(
+                value.into_owned(),
+            );
+        }
+
+        let mut req = Map::new();
+        req.insert("method".into(), Value::from(request.method.as_ref()));
+        req.insert("path".into(), Value::from(request.url.path()));
+        if !query.is_empty() {
+            req.insert("query".into(), json!(query));
+        }
+        req.insert(
+            "headers".into(),
+            json!(::helper::serialize_headers(&request.headers)),
+        );
+        if let Some(ref body) = request.body {
+            req.insert("body".into(), pact_body(body));
+        }
+
+        let mut resp = Map::new();
+        resp.insert(
+            "status".into(),
+            Value::from(u16::from(response.status.clone())),
+        );
+        resp.insert(
+            "headers".into(),
+            json!(::helper::serialize_headers(&response.headers)),
+        );
+        if !response.body.is_empty() {
+            resp.insert("body".into(), pact_body(&response.body));
+        }
+
+        self.interactions.push(json!({
+            "description": format!("{} {}", request.method, request.url),
+            "request": req,
+            "response": resp,
+        }));
+    }
+
+    /// Add multiple interactions to the contract, e.g. the ones returned by
+    /// [ReplayClient::recorded](../struct.ReplayClient.html#method.recorded).
+    pub fn interactions<I: IntoIterator<Item = (Request, Response)>>(&mut self, interactions: I) {
+        for (request, response) in interactions {
+            self.interaction(&request, &response);
+        }
+    }
+
+    /// Returns the contract in the Pact v3 JSON format.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "consumer": { "name": self.consumer },
+            "provider": { "name": self.provider },
+            "interactions": self.interactions,
+            "metadata": { "pactSpecification": { "version": "3.0.0" } },
+        })
+    }
+
+    /// Write the contract to the provided path, creating its parent directories if needed.
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                create_dir_all(parent)?;
+            }
+        }
+
+        let f = File::create(path)?;
+        ::serde_json::to_writer_pretty(f, &self.to_json())?;
+        Ok(())
+    }
+}
+
+/// Pact bodies are JSON values if possible and strings otherwise, binary bodies are stored base64
+/// encoded as Pact has no representation for them.
+fn pact_body(body: &[u8]) -> Value {
+    if let Ok(value) = ::serde_json::from_slice(body) {
+        return value;
+    }
+
+    match ::std::str::from_utf8(body) {
+        Ok(s) => Value::from(s),
+        Err(_) => Value::from(base64::encode(body)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use reqwest::header::Headers;
+    use reqwest::{Method, StatusCode, Url};
+    use std::fs::remove_dir_all;
+
+    #[test]
+    fn export() {
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", "application/json");
+        let request = Request {
+            url: Url::parse("https://example.com/users?tag=a&tag=b").unwrap(),
+            method: Method::Post,
+            headers: headers,
+            body: Some(Bytes::from(r#"{"name": "Jane"}"#)),
+        };
+        let response = Response {
+            url: request.url.clone(),
+            status: StatusCode::Created,
+            headers: Headers::new(),
+            body: Bytes::from("created"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+
+        let mut pact = PactExport::new("consumer", "provider");
+        pact.interactions(vec![(request, response)]);
+        let expected = json!({
+            "consumer": { "name": "consumer" },
+            "provider": { "name": "provider" },
+            "interactions": [{
+                "description": "POST https://example.com/users?tag=a&tag=b",
+                "request": {
+                    "method": "POST",
+                    "path": "/users",
+                    "query": { "tag": ["a", "b"] },
+                    "headers": { "Content-Type": "application/json" },
+                    "body": { "name": "Jane" },
+                },
+                "response": {
+                    "status": 201,
+                    "headers": {},
+                    "body": "created",
+                },
+            }],
+            "metadata": { "pactSpecification": { "version": "3.0.0" } },
+        });
+        assert_eq!(pact.to_json(), expected);
+
+        let dir = ::std::env::temp_dir().join("reqwest_mock_pact");
+        let path = dir.join("pacts").join("consumer-provider.json");
+        pact.write_to(&path).unwrap();
+        let written: Value = ::serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(written, expected);
+        remove_dir_all(dir).unwrap();
+    }
+}
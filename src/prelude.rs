@@ -0,0 +1,50 @@
+//! Re-exports the items needed by most code using this crate, so a single import is enough to
+//! send requests generically over the `Client` trait.
+//!
+//! ```
+//! use reqwest_mock::prelude::*;
+//!
+//! fn fetch<C: Client>(client: &C) -> Result<Response, Error> {
+//!     client.get("https://example.com/").send()
+//! }
+//! ```
+//!
+//! The `mock_client!` macro isn't included, as macros are imported with
+//! `#[macro_use] extern crate reqwest_mock;`.
+
+pub use client::{Client, DirectClient, GenericClient, ReplayClient, StubClient};
+pub use error::Error;
+pub use header_ext::HeadersExt;
+pub use request::Request;
+pub use request_builder::RequestBuilder;
+pub use response::Response;
+pub use reqwest::{Method, StatusCode, Url};
+
+#[cfg(test)]
+mod tests {
+    use client::{StubSettings, StubStrictness};
+    use prelude::*;
+
+    fn fetch<C: Client>(client: &C, url: Url) -> Result<Response, Error> {
+        client.request(Method::Get, url).send()
+    }
+
+    #[test]
+    fn generic_request() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::MethodUrl,
+            ..StubSettings::default()
+        });
+        let url = Url::parse("https://example.com/").unwrap();
+        client
+            .stub(url.clone())
+            .method(Method::Get)
+            .response()
+            .body("ok")
+            .mock();
+
+        let response = fetch(&client, url).unwrap();
+        assert_eq!(response.status, StatusCode::Ok);
+        assert_eq!(response.body, "ok");
+    }
+}
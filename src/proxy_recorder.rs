@@ -0,0 +1,288 @@
+//! An HTTP forward proxy recording all traffic passing through it.
+//!
+//! This allows recording requests made by code which can't be switched to the `Client` trait,
+//! like third-party SDKs or subprocesses, as long as it can be configured to use a proxy.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use reqwest_mock::{RecordingTarget, ReplayClient};
+//! use reqwest_mock::proxy_recorder::ProxyRecorder;
+//!
+//! let client = ReplayClient::recording(RecordingTarget::dir("replays"));
+//! let proxy = ProxyRecorder::start(client).unwrap();
+//!
+//! // Run the code under test with `HTTP_PROXY` set to this url.
+//! println!("{}", proxy.proxy_url());
+//!
+//! proxy.stop();
+//! ```
+
+use bytes::Bytes;
+use client::{Client, ReplayClient};
+use error::{Error, ResultExt};
+use request::Request;
+use reqwest::header::Headers;
+use reqwest::{Method, Url};
+use response::Response;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+/// Headers only meaningful for a single connection, which are neither forwarded nor recorded.
+const HOP_BY_HOP: &'static [&'static str] = &["Connection", "Keep-Alive", "Proxy-Connection",
+                                              "Proxy-Authenticate", "Proxy-Authorization",
+                                              "TE", "Trailer", "Transfer-Encoding", "Upgrade",
+                                              "Content-Length"];
+
+/// A running proxy, which is shut down when stopped or dropped.
+///
+/// Requests are passed to the provided `ReplayClient`, so they are recorded the first time and
+/// replayed afterwards. Only plain HTTP requests can be recorded, HTTPS connections tunneled
+/// through `CONNECT` are refused.
+pub struct ProxyRecorder {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ProxyRecorder {
+    /// Start the proxy on a random port of localhost.
+    pub fn start(client: ReplayClient) -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_shutdown = shutdown.clone();
+        let handle = thread::spawn(move || for stream in listener.incoming() {
+            if thread_shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let result = stream.map_err(Error::from).and_then(
+                |s| handle_connection(&client, s),
+            );
+            if let Err(e) = result {
+                warn!("Proxy recorder failed handling a connection: {}", e);
+            }
+        });
+        debug!("Proxy recorder listening on {}", addr);
+
+        Ok(ProxyRecorder {
+            addr: addr,
+            shutdown: shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the address the proxy is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the url of the proxy, to be used e.g. for the `HTTP_PROXY` environment variable.
+    pub fn proxy_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Stop the proxy, waiting for the request currently being handled to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.shutdown.store(true, Ordering::SeqCst);
+            // Wake up the thread, which is blocked waiting for a connection.
+            let _ = TcpStream::connect(self.addr);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for ProxyRecorder {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+/// Handles a single proxied request, the connection is closed afterwards.
+fn handle_connection(client: &ReplayClient, mut stream: TcpStream) -> Result<(), Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.trim_end().splitn(3, ' ');
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(m), Some(t)) => (m.to_string(), t.to_string()),
+        _ => return Err("malformed request line".into()),
+    };
+
+    let mut headers = Headers::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some(pos) = line.find(':') {
+            let (name, value) = (line[..pos].trim(), line[pos + 1..].trim());
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.parse().chain_err(|| "invalid Content-Length")?;
+            }
+            if !is_hop_by_hop(name) {
+                headers.append_raw(name.to_string(), value.as_bytes().to_vec());
+            }
+        }
+    }
+
+    if method == "CONNECT" {
+        return write_error(
+            &mut stream,
+            501,
+            "Not Implemented",
+            "HTTPS traffic can't be recorded by the proxy recorder.",
+        );
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let request = Request {
+        url: Url::parse(&target).chain_err(|| "invalid url")?,
+        method: Method::from_str(&method).chain_err(|| "invalid method")?,
+        headers: headers,
+        body: if body.is_empty() {
+            None
+        } else {
+            Some(Bytes::from(body))
+        },
+    };
+
+    match client.execute(None, request) {
+        Ok(response) => write_response(&mut stream, &response),
+        Err(e) => write_error(&mut stream, 502, "Bad Gateway", &e.to_string()),
+    }
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> Result<(), Error> {
+    let status = u16::from(response.status.clone());
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status,
+        response.status.canonical_reason().unwrap_or("")
+    ).into_bytes();
+
+    for header in response.headers.iter() {
+        if is_hop_by_hop(header.name()) {
+            continue;
+        }
+        for value in header.raw().iter() {
+            out.extend_from_slice(header.name().as_bytes());
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value);
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    out.extend_from_slice(
+        format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            response.body.len()
+        ).as_bytes(),
+    );
+    out.extend_from_slice(&response.body);
+
+    stream.write_all(&out)?;
+    Ok(())
+}
+
+fn write_error(stream: &mut TcpStream, status: u16, reason: &str, msg: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        msg.len(),
+        msg
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::RecordingTarget;
+    use data::FORMAT_VERSION;
+    use reqwest::StatusCode;
+    use std::fs::{remove_file, File};
+
+    fn send(proxy: &ProxyRecorder, request: &str) -> String {
+        let mut stream = TcpStream::connect(proxy.addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn replays_through_proxy() {
+        let file = ::std::env::temp_dir().join("reqwest_mock_proxy_recorder.json");
+        let request = Request {
+            url: Url::parse("http://example.com/items").unwrap(),
+            method: Method::Get,
+            headers: Headers::new(),
+            body: None,
+        };
+        let mut headers = Headers::new();
+        headers.set_raw("X-Recorded", "yes");
+        let response = Response {
+            url: request.url.clone(),
+            status: StatusCode::Ok,
+            headers: headers,
+            body: Bytes::from("items"),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+        let data = json!({
+            "request": request,
+            "response": response,
+            "format_version": FORMAT_VERSION,
+        });
+        ::serde_json::to_writer(File::create(&file).unwrap(), &data).unwrap();
+
+        let proxy = ProxyRecorder::start(ReplayClient::new(RecordingTarget::file(&file))).unwrap();
+
+        // Hop-by-hop headers aren't part of the recorded request.
+        let replayed = send(
+            &proxy,
+            "GET http://example.com/items HTTP/1.1\r\nProxy-Connection: keep-alive\r\n\r\n",
+        );
+        assert!(replayed.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(replayed.contains("X-Recorded: yes\r\n"));
+        assert!(replayed.ends_with("\r\n\r\nitems"));
+
+        // The offline client refuses requests which weren't recorded.
+        let unrecorded = send(&proxy, "GET http://example.com/other HTTP/1.1\r\n\r\n");
+        assert!(unrecorded.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+
+        let tunneled = send(&proxy, "CONNECT example.com:443 HTTP/1.1\r\n\r\n");
+        assert!(tunneled.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+
+        proxy.stop();
+        remove_file(file).unwrap();
+    }
+}
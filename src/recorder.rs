@@ -0,0 +1,100 @@
+//! Quickly bootstrap replay files from one-off live requests.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[macro_use] extern crate serde_json;
+//! # extern crate reqwest_mock;
+//! use reqwest_mock::RecordingTarget;
+//! use reqwest_mock::recorder::Recorder;
+//!
+//! # fn main() {
+//! let recorder = Recorder::new(RecordingTarget::dir("fixtures/users"));
+//! recorder.record_get("https://example.com/users").unwrap();
+//! recorder
+//!     .record_json_post("https://example.com/users", &json!({"name": "Jane"}))
+//!     .unwrap();
+//! # }
+//! ```
+
+use client::{Client, ReplayClient};
+use error::Error;
+use reqwest::IntoUrl;
+use reqwest::header::ContentType;
+use response::Response;
+use serde::Serialize;
+
+/// Performs live requests and records them into the target, replacing previous recordings of the
+/// same requests.
+pub struct Recorder {
+    client: ReplayClient,
+}
+
+impl Recorder {
+    /// Create a new `Recorder` recording to the specified target.
+    pub fn new(target: ::client::RecordingTarget) -> Self {
+        Recorder { client: ReplayClient::recording(target) }
+    }
+
+    /// Record a `GET` request to the url.
+    pub fn record_get<U: IntoUrl>(&self, url: U) -> Result<Response, Error> {
+        self.client.force_record_next();
+        self.client.get(url).send()
+    }
+
+    /// Record a `POST` request to the url with the JSON serialization of `body` as its body.
+    pub fn record_json_post<U: IntoUrl, T: Serialize>(
+        &self,
+        url: U,
+        body: &T,
+    ) -> Result<Response, Error> {
+        let body = ::serde_json::to_vec(body)?;
+
+        self.client.force_record_next();
+        self.client
+            .post(url)
+            .header(ContentType::json())
+            .body(body)
+            .send()
+    }
+
+    /// Returns the underlying `ReplayClient`, e.g. to record other kinds of requests.
+    pub fn client(&self) -> &ReplayClient {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::RecordingTarget;
+    use helper::serve;
+
+    #[test]
+    fn record() {
+        let dir = ::std::env::temp_dir().join("reqwest_mock_recorder");
+        let (base_url, server) = serve(
+            &[
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n[]",
+                "HTTP/1.1 201 Created\r\nContent-Length: 4\r\n\r\nJane",
+            ],
+        );
+        let url = format!("{}/users", base_url);
+
+        let recorder = Recorder::new(RecordingTarget::dir(&dir));
+        recorder.record_get(&url[..]).unwrap();
+        recorder.record_json_post(&url[..], &json!({"name": "Jane"})).unwrap();
+        server.join().unwrap();
+
+        let client = ReplayClient::new(RecordingTarget::dir(&dir));
+        assert_eq!(client.get(&url[..]).send().unwrap().body, "[]");
+        let created = client
+            .post(&url[..])
+            .header(ContentType::json())
+            .body(r#"{"name":"Jane"}"#)
+            .send()
+            .unwrap();
+        assert_eq!(created.body, "Jane");
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
+use bytes::Bytes;
+use data::RequestData;
+use error::{Error, ResultExt};
 use reqwest::{Url, Method};
 use reqwest::header::Headers;
 use serde::ser::{Serialize, Serializer, SerializeStruct};
-use serde::de::{Deserialize, Deserializer, Visitor, MapAccess, Unexpected};
+use serde::de::{Deserialize, Deserializer, IgnoredAny, Visitor, MapAccess, Unexpected};
 use serde::de::Error as DeError;
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -12,16 +15,65 @@ pub struct Request {
     pub url: Url,
     pub method: Method,
     pub headers: Headers,
-    pub body: Option<Vec<u8>>,
+    pub body: Option<Bytes>,
 }
 
-/// We need this so we can generate unique filenames for each request.
+impl Request {
+    /// Returns the query parameters of the url in the order they appear in.
+    ///
+    /// Sort the result for an order-insensitive comparison of two requests.
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        self.url
+            .query_pairs()
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect()
+    }
+
+    /// Returns the first value of the query parameter with the provided name.
+    pub fn query(&self, name: &str) -> Option<String> {
+        self.url.query_pairs().find(|&(ref k, _)| k == name).map(
+            |(_, v)| {
+                v.into_owned()
+            },
+        )
+    }
+
+    /// A stable hash of the request, used to identify it in replay files.
+    ///
+    /// See `RequestData::fingerprint` for computing it without reqwest.
+    pub fn fingerprint(&self) -> u64 {
+        self.to_data().fingerprint()
+    }
+
+    /// Returns the request as stored in replay files.
+    pub fn to_data(&self) -> RequestData {
+        RequestData {
+            url: self.url.as_str().to_string(),
+            method: self.method.as_ref().to_string(),
+            headers: ::helper::store_headers(&self.headers),
+            body: self.body.as_ref().map(|b| b.to_vec()),
+            query: self.query_pairs(),
+        }
+    }
+
+    /// Converts a request read from a replay file.
+    pub fn from_data(data: RequestData) -> Result<Self, Error> {
+        Ok(Request {
+            url: Url::parse(&data.url).chain_err(|| "invalid url")?,
+            method: Method::from_str(&data.method).chain_err(|| "invalid method")?,
+            headers: ::helper::deserialize_headers(&data.headers).map_err(
+                |_| "invalid base64 header value",
+            )?,
+            body: data.body.map(Bytes::from),
+        })
+    }
+}
+
+/// Hashes the `Request::fingerprint`, which replay files are named after, so requests with the
+/// same fingerprint always hash the same.
 impl Hash for Request {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.url.hash(state);
-        self.method.hash(state);
-        ::helper::serialize_headers(&self.headers).hash(state);
-        self.body.hash(state);
+        self.fingerprint().hash(state);
     }
 }
 
@@ -30,15 +82,17 @@ impl Serialize for Request {
     where
         S: Serializer,
     {
-        let mut req = serializer.serialize_struct("Request", 4)?;
+        let mut req = serializer.serialize_struct("Request", 5)?;
 
         req.serialize_field("url", self.url.as_ref())?;
         req.serialize_field("method", self.method.as_ref())?;
-        req.serialize_field("body", &self.body)?;
+        req.serialize_field("body", &self.body.as_ref().map(|b| &b[..]))?;
         req.serialize_field(
             "headers",
-            &::helper::serialize_headers(&self.headers),
+            &::helper::store_headers(&self.headers),
         )?;
+        // Stored for `RequestData`, it is parsed from the url again when deserializing.
+        req.serialize_field("query", &self.query_pairs())?;
 
         req.end()
     }
@@ -56,6 +110,7 @@ impl<'de> Deserialize<'de> for Request {
             Method,
             Body,
             Headers,
+            Query,
         }
 
         struct RequestVisitor {}
@@ -100,13 +155,18 @@ impl<'de> Deserialize<'de> for Request {
                             if body.is_some() {
                                 return Err(DeError::duplicate_field("body"));
                             }
-                            body = map.next_value()?;
+                            let b: Option<Vec<u8>> = map.next_value()?;
+                            body = b.map(Bytes::from);
                         }
                         Field::Headers => {
                             if headers.is_some() {
                                 return Err(DeError::duplicate_field("headers"));
                             }
-                            headers = Some(::helper::deserialize_headers(&map.next_value()?));
+                            headers = Some(::helper::deserialize_headers(&map.next_value()?)
+                                .map_err(|_| DeError::invalid_value(Unexpected::Map, &"headers"))?);
+                        }
+                        Field::Query => {
+                            map.next_value::<IgnoredAny>()?;
                         }
                     }
                 }
@@ -120,7 +180,7 @@ impl<'de> Deserialize<'de> for Request {
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["url", "method", "body"];
+        const FIELDS: &'static [&'static str] = &["url", "method", "body", "headers", "query"];
         deserializer.deserialize_struct("Request", FIELDS, RequestVisitor {})
     }
 }
@@ -140,7 +200,7 @@ mod tests {
         let req1 = Request {
             url: Url::parse("https://example.com").unwrap(),
             method: Method::Get,
-            body: Some(vec![2, 4, 11, 32, 99, 1, 4, 5]),
+            body: Some(Bytes::from(vec![2, 4, 11, 32, 99, 1, 4, 5])),
             headers: headers,
         };
 
@@ -148,4 +208,126 @@ mod tests {
         let req2 = ::serde_json::from_str(json.as_ref()).unwrap();
         assert_eq!(req1, req2);
     }
+
+    #[test]
+    fn query_pairs() {
+        let req = Request {
+            url: Url::parse("https://example.com/?b=2&a=1&b=3").unwrap(),
+            method: Method::Get,
+            body: None,
+            headers: Headers::new(),
+        };
+
+        assert_eq!(
+            req.query_pairs(),
+            vec![
+                ("b".to_string(), "2".to_string()),
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "3".to_string()),
+            ]
+        );
+        assert_eq!(req.query("b"), Some("2".to_string()));
+        assert_eq!(req.query("c"), None);
+
+        // The stored parameters are read back into `RequestData`.
+        let stored = ::serde_json::to_value(&req).unwrap();
+        let data: RequestData = ::serde_json::from_value(stored).unwrap();
+        assert_eq!(data.query, req.query_pairs());
+        assert_eq!(data, req.to_data());
+        assert_eq!(data.query("b"), Some("2"));
+
+        let mut reordered = req.clone();
+        reordered.url = Url::parse("https://example.com/?a=1&b=3&b=2").unwrap();
+        assert!(data.same_query(&reordered.to_data()));
+        assert_ne!(data.fingerprint(), reordered.fingerprint());
+    }
+
+    /// The fingerprint has to be stable regardless of the order headers were set in, as it is
+    /// used to name replay files.
+    #[test]
+    fn fingerprint_deterministic() {
+        use reqwest::header::{ContentType, UserAgent};
+
+        let mut headers1 = Headers::new();
+        headers1.set(UserAgent::new("testing"));
+        headers1.set(ContentType::png());
+        let mut headers2 = Headers::new();
+        headers2.set(ContentType::png());
+        headers2.set(UserAgent::new("testing"));
+
+        let req1 = Request {
+            url: Url::parse("https://example.com").unwrap(),
+            method: Method::Get,
+            body: None,
+            headers: headers1,
+        };
+        let mut req2 = req1.clone();
+        req2.headers = headers2;
+
+        assert_eq!(req1.fingerprint(), req2.fingerprint());
+    }
+
+    /// The fingerprint of the stored request has to stay the same as the former hash of the
+    /// request, which existing replay files are named after.
+    #[test]
+    fn fingerprint_data() {
+        use twox_hash::XxHash;
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept", "*/*");
+        headers.set_raw("X-Custom", "value");
+        let methods = vec![Method::Get, Method::Patch, Method::Extension("PURGE".to_string())];
+        for method in methods {
+            let request = Request {
+                url: Url::parse("https://example.com/a?b=c").unwrap(),
+                method: method,
+                headers: headers.clone(),
+                body: Some(Bytes::from("body")),
+            };
+
+            let mut hasher = XxHash::with_seed(42);
+            request.url.hash(&mut hasher);
+            request.method.hash(&mut hasher);
+            ::helper::serialize_headers(&request.headers).hash(&mut hasher);
+            request.body.hash(&mut hasher);
+            assert_eq!(request.fingerprint(), hasher.finish());
+
+            let stored = ::serde_json::to_value(&request).unwrap();
+            let data: RequestData = ::serde_json::from_value(stored).unwrap();
+            assert_eq!(data.fingerprint(), request.fingerprint());
+            assert_eq!(Request::from_data(data).unwrap(), request);
+        }
+    }
+
+    /// Binary headers are stored base64 encoded, hashing has to agree with the fingerprint for
+    /// them as well.
+    #[test]
+    fn hash_binary_header() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let request = |value: &'static [u8]| {
+            let mut headers = Headers::new();
+            headers.set_raw("X-Binary", value);
+            Request {
+                url: Url::parse("https://example.com/").unwrap(),
+                method: Method::Get,
+                headers: headers,
+                body: None,
+            }
+        };
+        let hash = |request: &Request| {
+            let mut hasher = DefaultHasher::new();
+            request.hash(&mut hasher);
+            hasher.finish()
+        };
+        let fingerprint_hash = |request: &Request| {
+            let mut hasher = DefaultHasher::new();
+            request.to_data().fingerprint().hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let binary = request(b"\xff\x01");
+        assert_eq!(hash(&binary), fingerprint_hash(&binary));
+        assert_ne!(hash(&binary), hash(&request(b"text")));
+    }
 }
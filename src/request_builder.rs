@@ -1,10 +1,15 @@
 use body::IntoBody;
+use bytes::Bytes;
 use client::Client;
-use reqwest::{IntoUrl, Url, Method};
+use compression::Compression;
+use reqwest::{IntoUrl, Url, Method, StatusCode};
 use request::Request;
 use response::Response;
 use reqwest::header::{Headers, Header};
-use error::{Error, ResultExt};
+use config::ClientConfig;
+use error::{Error, ErrorKind, ResultExt};
+use std::io::Read;
+use std::time::Instant;
 
 pub struct RequestBuilder<'cl, Cl: Client + 'cl> {
     client: &'cl Cl,
@@ -12,7 +17,12 @@ pub struct RequestBuilder<'cl, Cl: Client + 'cl> {
     url: Result<Url, Error>,
     method: Method,
     headers: Headers,
-    body: Option<Vec<u8>>,
+    body: Option<Bytes>,
+    stream: Option<Box<dyn Read + Send>>,
+    compression: Option<Compression>,
+    digest_auth: Option<(String, String)>,
+    deadline: Option<Instant>,
+    config: Option<ClientConfig>,
 }
 
 impl<'cl, Cl: Client + 'cl> RequestBuilder<'cl, Cl> {
@@ -24,6 +34,11 @@ impl<'cl, Cl: Client + 'cl> RequestBuilder<'cl, Cl> {
             method: method,
             headers: Headers::new(),
             body: None,
+            stream: None,
+            compression: None,
+            digest_auth: None,
+            deadline: None,
+            config: None,
         }
     }
 
@@ -42,18 +57,308 @@ impl<'cl, Cl: Client + 'cl> RequestBuilder<'cl, Cl> {
     /// Set the body of the request.
     pub fn body<B: IntoBody>(mut self, body: B) -> Self {
         self.body = Some(body.into_body());
+        self.stream = None;
         self
     }
 
-    /// Send the request.
-    pub fn send(self) -> Result<Response, Error> {
-        let request = Request {
+    /// Stream the body of the request from a reader.
+    ///
+    /// The `DirectClient` sends the body without buffering it, see `Client::execute_streaming`.
+    /// As the body can only be read once, a request with a streamed body can't be cloned and
+    /// isn't retried or sent again to answer a digest challenge.
+    pub fn body_reader<R: Read + Send + 'static>(mut self, reader: R) -> Self {
+        self.body = None;
+        self.stream = Some(Box::new(reader));
+        self
+    }
+
+    /// Compress the body, setting the `Content-Encoding` header accordingly.
+    ///
+    /// The body is compressed when the request is built, so it is sent, recorded and matched
+    /// against stubs compressed with any client. See `ReplaySettings::decompress_request_body`
+    /// for matching recorded requests by their decompressed body instead.
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Authenticate using HTTP Digest authentication.
+    ///
+    /// The request is sent without credentials first, if it is answered with `401 Unauthorized`
+    /// and a digest challenge it is sent again answering the challenge. As this happens on top of
+    /// the `Client`, it works with all clients and the whole exchange can be stubbed or recorded.
+    ///
+    /// Only the `MD5` algorithm is supported.
+    ///
+    /// TODO: NTLM would additionally require DES and MD4 and the handshake to happen on a single
+    ///       connection, which can't be controlled through reqwest 0.7.
+    pub fn digest_auth<U, P>(mut self, username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        self.digest_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Set a deadline for the request, e.g. one propagated from an incoming request.
+    ///
+    /// When the request is sent, the time remaining until the deadline is determined using the
+    /// clock of the request config and applied as `timeout` and `total_timeout`, if they are
+    /// longer. Requests sent after the deadline fail with an `ErrorKind::DeadlineExceeded` error
+    /// without being executed, this also applies to retries.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Send the request with the provided config instead of the one of the client, e.g. to retry
+    /// a single request according to a `RetryAfterPolicy`.
+    pub fn config(mut self, config: ClientConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Returns a copy of the builder, so the same request can be sent again, e.g. by retry loops.
+    ///
+    /// Unlike with reqwest the body is buffered and can be cloned, so this only returns `None` if
+    /// the url is invalid, in which case sending would fail anyway, or if the body is streamed
+    /// with `body_reader`.
+    pub fn try_clone(&self) -> Option<Self> {
+        if self.stream.is_some() {
+            return None;
+        }
+        let url = match self.url {
+            Ok(ref url) => url.clone(),
+            Err(_) => return None,
+        };
+
+        Some(RequestBuilder {
+            client: self.client,
+            url: Ok(url),
+            method: self.method.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            stream: None,
+            compression: self.compression,
+            digest_auth: self.digest_auth.clone(),
+            deadline: self.deadline,
+            config: self.config.clone(),
+        })
+    }
+
+    /// Build the request without sending it, so it can be inspected first.
+    ///
+    /// It can be sent afterwards with `Client::execute`, which however doesn't retry requests
+    /// according to `ClientConfig::retry_after` like `send` does. A body streamed with
+    /// `body_reader` is read into memory.
+    pub fn build(mut self) -> Result<Request, Error> {
+        if let Some(mut stream) = self.take_stream()? {
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf)?;
+            self.body = Some(Bytes::from(buf));
+        } else if let Some(compression) = self.compression {
+            if let Some(body) = self.body.take() {
+                self.body = Some(compression.compress(&body)?);
+            }
+        }
+
+        let mut headers = self.headers;
+        if let (Some(compression), true) = (self.compression, self.body.is_some()) {
+            headers.set_raw("Content-Encoding", compression.encoding());
+        }
+        let config = self.config.as_ref().unwrap_or_else(|| self.client.config());
+        if let Some(ref user_agent) = config.user_agent {
+            if headers.get_raw("User-Agent").is_none() {
+                headers.set_raw("User-Agent", user_agent.clone());
+            }
+        }
+        for (name, value) in &config.default_headers {
+            if headers.get_raw(name).is_none() {
+                headers.set_raw(name.clone(), value.clone());
+            }
+        }
+
+        Ok(Request {
             url: self.url?,
             method: self.method,
-            headers: self.headers,
+            headers: headers,
             body: self.body,
-        };
+        })
+    }
+
+    /// Send the request.
+    pub fn send(mut self) -> Result<Response, Error> {
+        let client = self.client;
+        let deadline = self.deadline;
+        let config_override = self.config.clone();
+        let base_config = config_override.as_ref().unwrap_or_else(|| client.config());
+        if let Some(stream) = self.take_stream()? {
+            let compression = self.compression;
+            let mut request = self.build()?;
+            if let Some(compression) = compression {
+                request.headers.set_raw("Content-Encoding", compression.encoding());
+            }
+            let deadline_config = deadline_config(base_config, deadline, &request.url)?;
+            let config = deadline_config.as_ref().or(config_override.as_ref());
+            return client.execute_streaming(config, request, stream);
+        }
+
+        let digest_auth = self.digest_auth.clone();
+        let mut request = self.build()?;
+
+        let mut retries = 0;
+        let mut authenticated = false;
+        loop {
+            let deadline_config = deadline_config(base_config, deadline, &request.url)?;
+            let request_config = deadline_config.as_ref().or(config_override.as_ref());
+            let config = request_config.unwrap_or(base_config);
+            let response = client.execute(request_config, request.clone())?;
+
+            if let (Some(&(ref username, ref password)), false) =
+                (digest_auth.as_ref(), authenticated)
+            {
+                if response.status == StatusCode::Unauthorized {
+                    let authorization =
+                        ::digest::authorization(&request, &response, username, password);
+                    if let Some(authorization) = authorization {
+                        debug!("Answering digest challenge.");
+                        request.headers.set_raw("Authorization", authorization);
+                        authenticated = true;
+                        continue;
+                    }
+                }
+            }
+
+            if let Some(ref policy) = config.retry_after {
+                if let Some(wait) = ::helper::retry_after(&response) {
+                    if retries < policy.max_retries && wait <= policy.max_wait {
+                        debug!("Retrying request after waiting {:?} as requested.", wait);
+                        config.clock.sleep(wait);
+                        retries += 1;
+                        continue;
+                    }
+                }
+            }
+
+            return Ok(response);
+        }
+    }
+
+    /// Takes the streamed body, compressing it while it is read if requested.
+    fn take_stream(&mut self) -> Result<Option<Box<dyn Read + Send>>, Error> {
+        match (self.stream.take(), self.compression) {
+            (Some(stream), Some(compression)) => Ok(Some(compression.encoder(stream)?)),
+            (stream, _) => Ok(stream),
+        }
+    }
+}
+
+/// Returns a copy of the config with its timeouts shortened to the time remaining until the
+/// deadline, see `RequestBuilder::deadline`.
+fn deadline_config(
+    config: &ClientConfig,
+    deadline: Option<Instant>,
+    url: &Url,
+) -> Result<Option<ClientConfig>, Error> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Ok(None),
+    };
+    let now = config.clock.now();
+    if now >= deadline {
+        return Err(ErrorKind::DeadlineExceeded(url.to_string()).into());
+    }
+
+    let remaining = deadline - now;
+    let mut config = config.clone();
+    if config.timeout.map_or(true, |t| t > remaining) {
+        config.timeout = Some(remaining);
+    }
+    if config.total_timeout.map_or(true, |t| t > remaining) {
+        config.total_timeout = Some(remaining);
+    }
+    Ok(Some(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use client::{StubClient, StubSettings, StubStrictness};
+    use std::io::Cursor;
+
+    #[test]
+    fn try_clone() {
+        let client = StubClient::new(StubSettings::default());
+        let mut headers = Headers::new();
+        headers.set_raw("X-Attempt", "1");
+        let builder = client
+            .post("http://example.com/")
+            .headers(headers)
+            .body("body");
+
+        let cloned = builder.try_clone().unwrap().build().unwrap();
+        assert_eq!(cloned, builder.build().unwrap());
+
+        // Streamed bodies can't be sent twice.
+        let streamed = client.post("http://example.com/").body_reader(Cursor::new(b"body"));
+        assert!(streamed.try_clone().is_none());
+        assert!(client.get("not a url").try_clone().is_none());
+    }
+
+    /// The built request can be inspected and executed afterwards.
+    #[test]
+    fn build() {
+        let mut client = StubClient::new(StubSettings {
+            strictness: StubStrictness::BodyMethodUrl,
+            ..StubSettings::default()
+        });
+        let mut headers = Headers::new();
+        headers.set_raw("X-Custom", "value");
+        let request = client
+            .put("http://example.com/items/1")
+            .headers(headers.clone())
+            .body("item")
+            .build()
+            .unwrap();
+        assert_eq!(
+            request,
+            Request {
+                url: Url::parse("http://example.com/items/1").unwrap(),
+                method: Method::Put,
+                headers: headers,
+                body: Some(Bytes::from("item")),
+            }
+        );
+
+        client
+            .stub(request.url.clone())
+            .method(Method::Put)
+            .body("item")
+            .response()
+            .body("stored")
+            .mock();
+        let response = client.execute(None, request).unwrap();
+        assert_eq!(response.body, "stored");
+
+        assert!(client.get("not a url").build().is_err());
+    }
+
+    #[test]
+    fn user_agent() {
+        use reqwest::header::UserAgent;
+
+        let mut client = StubClient::new(StubSettings::default());
+        client.user_agent("my-app/1.0");
+        let request = client.get("http://example.com/").build().unwrap();
+        assert_eq!(request.headers.get(), Some(&UserAgent::new("my-app/1.0")));
 
-        self.client.execute(None, request)
+        // Requests setting one themselves keep it.
+        let request = client
+            .get("http://example.com/")
+            .header(UserAgent::new("other"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers.get(), Some(&UserAgent::new("other")));
     }
 }
@@ -1,13 +1,22 @@
 use base64;
-use error::Error;
+use bytes::Bytes;
+use error::{Error, ResultExt};
+use header_ext::HeadersExt;
 use reqwest::header::Headers;
 use reqwest::{Url, StatusCode};
 use serde::de::Error as DeError;
 use serde::de::{Deserialize, Deserializer, Visitor, MapAccess, Unexpected};
 use serde::ser::{Serialize, Serializer, SerializeStruct};
+use clock::Clock;
+use cookie::Cookie;
+use data::ResponseData;
+use std::cmp;
 use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Response {
     /// The final url of this response.
     pub url: Url,
@@ -15,31 +24,250 @@ pub struct Response {
     /// Status code.
     pub status: StatusCode,
 
-    /// Headers
+    /// Headers, the names are stored with the same casing as received from the server.
     pub headers: Headers,
 
     /// The response body in binary format.
-    pub body: Vec<u8>,
+    pub body: Bytes,
+
+    /// How long it took to perform the request, if it was performed live (or replayed from a
+    /// recording of such a request).
+    ///
+    /// It is not taken into account when comparing responses.
+    pub elapsed: Option<Duration>,
+
+    /// Which recorded interaction the response was replayed from, see `replay_info`.
+    ///
+    /// It is neither taken into account when comparing responses nor recorded.
+    pub replay: Option<ReplayInfo>,
+
+    /// The status codes of the interim `1xx` responses received before this one, e.g.
+    /// `100 Continue` answering `Expect: 100-continue`, see `continued`.
+    ///
+    /// They are only ever emitted by stubs and replay files, as hyper 0.11 consumes interim
+    /// responses internally, so they can't be recorded from live responses but have to be added
+    /// to replay files by hand.
+    pub interim: Vec<StatusCode>,
+
+    /// The trailers sent after the body, e.g. `grpc-status`, see `trailers`.
+    ///
+    /// Like interim responses they are only ever emitted by stubs and replay files, as neither
+    /// reqwest 0.7 nor hyper 0.11 read the trailers of chunked bodies.
+    pub trailers: Headers,
+
+    /// The number of bytes of the body after which reading it fails as if the connection was
+    /// reset, see `reset_after`.
+    ///
+    /// It is neither taken into account when comparing responses nor recorded.
+    pub reset_at: Option<usize>,
 }
 
 impl Response {
     pub fn body_to_utf8(&self) -> Result<String, Error> {
-        Ok(String::from_utf8(self.body.clone())?)
+        Ok(String::from_utf8(self.body.to_vec())?)
+    }
+
+    /// Returns the response with the status replaced.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Returns the response with the header set to the value, replacing previous values.
+    pub fn with_header<K: Into<String>, V: Into<Vec<u8>>>(mut self, name: K, value: V) -> Self {
+        self.headers.set_raw(name.into(), value.into());
+        self
+    }
+
+    /// Returns the response with the body replaced.
+    pub fn with_body<B: ::body::IntoBody>(mut self, body: B) -> Self {
+        self.body = body.into_body();
+        self
+    }
+
+    /// Returns the cookies set by the `Set-Cookie` headers of the response, skipping invalid ones.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.headers
+            .get_raw("Set-Cookie")
+            .into_iter()
+            .flat_map(|raw| raw.iter())
+            .filter_map(|line| ::std::str::from_utf8(line).ok())
+            .filter_map(Cookie::parse)
+            .collect()
+    }
+
+    /// Returns the response with the body cut off after `n` bytes, but the `Content-Length`
+    /// header still announcing the whole body. Its `reader()` fails with a `ConnectionReset`
+    /// error once the `n` bytes were read, simulating a connection dropped during the download.
+    pub fn reset_after(mut self, n: usize) -> Self {
+        if self.body.len() > n {
+            self.headers.set_raw(
+                "Content-Length",
+                self.body.len().to_string(),
+            );
+            self.body.truncate(n);
+            self.reset_at = Some(n);
+        }
+        self
+    }
+
+    /// Returns a reader over the body, for code consuming the body through the `Read` trait.
+    ///
+    /// If the response was set up with `reset_after`, reading fails with a `ConnectionReset`
+    /// error after the delivered part of the body.
+    pub fn reader(&self) -> BodyReader {
+        BodyReader {
+            body: self.body.clone(),
+            position: 0,
+            throttle: None,
+            reset_at: self.reset_at,
+        }
+    }
+
+    /// Returns information about the recorded interaction this response was replayed from, or
+    /// `None` if it wasn't replayed by a `ReplayClient`.
+    ///
+    /// Useful to report which fixture produced unexpected data when a test fails.
+    pub fn replay_info(&self) -> Option<&ReplayInfo> {
+        self.replay.as_ref()
+    }
+
+    /// Returns whether the server answered `Expect: 100-continue` with `100 Continue` before
+    /// responding, i.e. whether the body was sent after the handshake, see `interim`.
+    pub fn continued(&self) -> bool {
+        self.interim.contains(&StatusCode::Continue)
+    }
+
+    /// Returns the trailers sent after the body.
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    /// Returns the length of the body as indicated by the `Content-Length` header, or the actual
+    /// length of the body if the header is missing.
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers.content_length().or_else(
+            || Some(self.body.len() as u64),
+        )
+    }
+
+    /// Converts a response read from a replay file.
+    pub fn from_data(data: ResponseData) -> Result<Self, Error> {
+        let status = |s: u16| StatusCode::try_from(s).map_err(|_| format!("invalid status {}", s));
+        Ok(Response {
+            url: Url::parse(&data.url).chain_err(|| "invalid url")?,
+            status: status(data.status)?,
+            headers: ::helper::deserialize_headers(&data.headers).map_err(
+                |_| "invalid base64 header value",
+            )?,
+            body: Bytes::from(data.body),
+            elapsed: data.elapsed,
+            replay: None,
+            interim: data.interim.into_iter().map(status).collect::<Result<_, _>>()?,
+            trailers: ::helper::deserialize_headers(&data.trailers).map_err(
+                |_| "invalid base64 header value",
+            )?,
+            reset_at: None,
+        })
     }
 }
 
+/// The recorded interaction a response was replayed from, see `Response::replay_info`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayInfo {
+    /// The replay file the interaction was read from, `None` for data embedded with
+    /// `ReplayClient::from_static`.
+    pub path: Option<PathBuf>,
+
+    /// Fingerprint of the recorded request, in the hex format used for replay file names.
+    pub fingerprint: String,
+
+    /// Index of the response among the responses recorded for the request, `0` unless a
+    /// sequence of responses was recorded.
+    pub index: usize,
+
+    /// When the interaction was recorded, `None` for replay files recorded before this was
+    /// stored.
+    pub recorded_at: Option<SystemTime>,
+}
+
+/// Reads the body of a `Response`, see `Response::reader`.
+#[derive(Clone, Debug)]
+pub struct BodyReader {
+    body: Bytes,
+    position: usize,
+    throttle: Option<(u64, Clock)>,
+    reset_at: Option<usize>,
+}
+
+impl BodyReader {
+    /// Serve the body at the rate of `bytes_per_second`, by sleeping on the clock after every
+    /// chunk read. With `Clock::mock()` this allows testing read timeouts or progress reporting
+    /// deterministically and without actually waiting.
+    pub fn throttled(mut self, bytes_per_second: u64, clock: Clock) -> Self {
+        self.throttle = Some((bytes_per_second, clock));
+        self
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let end = self.reset_at.map_or(self.body.len(), |at| cmp::min(at, self.body.len()));
+        let remaining = &self.body[self.position..end];
+        let mut n = cmp::min(buf.len(), remaining.len());
+
+        if self.reset_at.is_some() && n == 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                format!("connection reset after {} bytes", self.position),
+            ));
+        }
+
+        if let Some((rate, ref clock)) = self.throttle {
+            // Serve chunks of a tenth of a second, at least a single byte.
+            n = cmp::min(n, cmp::max(rate / 10, 1) as usize);
+            if n > 0 {
+                let nanos = n as u64 * 1_000_000_000 / cmp::max(rate, 1);
+                clock.sleep(Duration::new(
+                    nanos / 1_000_000_000,
+                    (nanos % 1_000_000_000) as u32,
+                ));
+            }
+        }
+
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl PartialEq for Response {
+    fn eq(&self, other: &Response) -> bool {
+        self.url == other.url && self.status == other.status &&
+            self.headers == other.headers && self.body == other.body &&
+            self.interim == other.interim && self.trailers == other.trailers
+    }
+}
+
+/// Wrapper to (de)serialize the elapsed time in the format of the `duration` module.
+#[derive(Serialize, Deserialize)]
+struct Elapsed(#[serde(with = "::duration")] Duration);
+
 const N_RESPONSE: &'static str = "Response";
 const F_URL: &'static str = "url";
 const F_STATUS: &'static str = "status";
 const F_HEADERS: &'static str = "headers";
 const F_BODY: &'static str = "body";
+const F_ELAPSED: &'static str = "elapsed";
+const F_INTERIM: &'static str = "interim";
+const F_TRAILERS: &'static str = "trailers";
 
 impl Serialize for Response {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut res = serializer.serialize_struct(N_RESPONSE, 5)?;
+        let mut res = serializer.serialize_struct(N_RESPONSE, 7)?;
 
         res.serialize_field(F_URL, self.url.as_ref())?;
         // TODO: actually the docs for this are hidden
@@ -49,9 +277,28 @@ impl Serialize for Response {
         )?;
         res.serialize_field(
             F_HEADERS,
-            &::helper::serialize_headers(&self.headers),
+            &::helper::store_headers(&self.headers),
         )?;
         res.serialize_field(F_BODY, &base64::encode(&self.body))?;
+        if let Some(elapsed) = self.elapsed {
+            res.serialize_field(F_ELAPSED, &Elapsed(elapsed))?;
+        } else {
+            res.skip_field(F_ELAPSED)?;
+        }
+        if self.interim.is_empty() {
+            res.skip_field(F_INTERIM)?;
+        } else {
+            let interim: Vec<u16> = self.interim.iter().map(|s| u16::from(s.clone())).collect();
+            res.serialize_field(F_INTERIM, &interim)?;
+        }
+        if self.trailers.len() == 0 {
+            res.skip_field(F_TRAILERS)?;
+        } else {
+            res.serialize_field(
+                F_TRAILERS,
+                &::helper::store_headers(&self.trailers),
+            )?;
+        }
 
         res.end()
     }
@@ -64,6 +311,9 @@ enum Field {
     Status,
     Headers,
     Body,
+    Elapsed,
+    Interim,
+    Trailers,
 }
 
 
@@ -84,6 +334,9 @@ impl<'de> Visitor<'de> for ResponseVisitor {
         let mut status = None;
         let mut headers = None;
         let mut body = None;
+        let mut elapsed = None;
+        let mut interim = None;
+        let mut trailers = None;
 
         while let Some(key) = map.next_key()? {
             match key {
@@ -109,16 +362,43 @@ impl<'de> Visitor<'de> for ResponseVisitor {
                     if headers.is_some() {
                         return Err(DeError::duplicate_field(F_HEADERS));
                     }
-                    headers = Some(::helper::deserialize_headers(&map.next_value()?));
+                    headers = Some(::helper::deserialize_headers(&map.next_value()?)
+                        .map_err(|_| DeError::invalid_value(Unexpected::Map, &F_HEADERS))?);
                 }
                 Field::Body => {
                     if body.is_some() {
                         return Err(DeError::duplicate_field(F_BODY));
                     }
                     let s: String = map.next_value()?;
-                    body = Some(base64::decode(&s).map_err(|_| {
+                    body = Some(Bytes::from(base64::decode(&s).map_err(|_| {
                         DeError::invalid_value(Unexpected::Str(s.as_ref()), &F_BODY)
-                    })?);
+                    })?));
+                }
+                Field::Elapsed => {
+                    if elapsed.is_some() {
+                        return Err(DeError::duplicate_field(F_ELAPSED));
+                    }
+                    let Elapsed(d) = map.next_value()?;
+                    elapsed = Some(d);
+                }
+                Field::Interim => {
+                    if interim.is_some() {
+                        return Err(DeError::duplicate_field(F_INTERIM));
+                    }
+                    let codes: Vec<u16> = map.next_value()?;
+                    let codes = codes.into_iter().map(|s| {
+                        StatusCode::try_from(s).map_err(|_| {
+                            DeError::invalid_value(Unexpected::Unsigned(s as u64), &"StatusCode")
+                        })
+                    });
+                    interim = Some(codes.collect::<Result<_, _>>()?);
+                }
+                Field::Trailers => {
+                    if trailers.is_some() {
+                        return Err(DeError::duplicate_field(F_TRAILERS));
+                    }
+                    trailers = Some(::helper::deserialize_headers(&map.next_value()?)
+                        .map_err(|_| DeError::invalid_value(Unexpected::Map, &F_TRAILERS))?);
                 }
             }
         }
@@ -128,6 +408,11 @@ impl<'de> Visitor<'de> for ResponseVisitor {
             status: status.ok_or_else(|| DeError::missing_field(F_STATUS))?,
             headers: headers.ok_or_else(|| DeError::missing_field(F_HEADERS))?,
             body: body.ok_or_else(|| DeError::missing_field(F_BODY))?,
+            elapsed: elapsed,
+            replay: None,
+            interim: interim.unwrap_or_default(),
+            trailers: trailers.unwrap_or_else(Headers::new),
+            reset_at: None,
         })
     }
 }
@@ -137,7 +422,8 @@ impl<'de> Deserialize<'de> for Response {
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &'static [&'static str] = &[F_URL, F_STATUS, F_HEADERS, F_BODY];
+        const FIELDS: &'static [&'static str] =
+            &[F_URL, F_STATUS, F_HEADERS, F_BODY, F_ELAPSED, F_INTERIM, F_TRAILERS];
         deserializer.deserialize_struct(N_RESPONSE, FIELDS, ResponseVisitor {})
     }
 }
@@ -153,12 +439,19 @@ mod tests {
         let mut headers = Headers::new();
         headers.set(ContentLength(2000));
         headers.set(UserAgent::new("Testing Code"));
+        let mut trailers = Headers::new();
+        trailers.set_raw("grpc-status", "0");
 
         let resp1 = Response {
             url: Url::parse("http://example.com/index.html").unwrap(),
             status: StatusCode::Ok,
             headers: headers,
-            body: vec![2, 4, 8, 16, 32, 64, 42],
+            body: Bytes::from(vec![2, 4, 8, 16, 32, 64, 42]),
+            elapsed: Some(Duration::from_millis(150)),
+            replay: None,
+            interim: vec![StatusCode::Continue],
+            trailers: trailers,
+            reset_at: None,
         };
 
         let json = ::serde_json::to_string(&resp1).unwrap();
@@ -166,4 +459,71 @@ mod tests {
         let resp2 = ::serde_json::from_str(json.as_ref()).unwrap();
         assert_eq!(resp1, resp2);
     }
+
+    #[test]
+    fn throttled_reader() {
+        let response = Response {
+            url: Url::parse("http://example.com/").unwrap(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from(vec![42u8; 1000]),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        };
+
+        let clock = ::clock::MockClock::new();
+        let mut reader = response.reader().throttled(100, Clock::Mock(clock.clone()));
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+
+        assert_eq!(body, &response.body[..]);
+        assert_eq!(clock.elapsed(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn reset_after() {
+        let response = Response {
+            url: Url::parse("http://example.com/").unwrap(),
+            status: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Bytes::from(vec![42u8; 1000]),
+            elapsed: None,
+            replay: None,
+            interim: Vec::new(),
+            trailers: Headers::new(),
+            reset_at: None,
+        }.reset_after(100);
+
+        let mut reader = response.reader();
+        let mut body = Vec::new();
+        let err = reader.read_to_end(&mut body).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(body.len(), 100);
+    }
+
+    /// Responses to `HEAD` requests and `304 Not Modified` responses announce the length of a
+    /// body they don't have, which mustn't be mistaken for a reset connection.
+    #[test]
+    fn announced_without_body() {
+        for status in &[StatusCode::Ok, StatusCode::NotModified] {
+            let response = Response {
+                url: Url::parse("http://example.com/").unwrap(),
+                status: status.clone(),
+                headers: Headers::new(),
+                body: Bytes::new(),
+                elapsed: None,
+                replay: None,
+                interim: Vec::new(),
+                trailers: Headers::new(),
+                reset_at: None,
+            }.with_header("Content-Length", "1000");
+
+            let mut body = Vec::new();
+            response.reader().read_to_end(&mut body).unwrap();
+            assert!(body.is_empty());
+        }
+    }
 }